@@ -0,0 +1,91 @@
+//! Evaluates arithmetic by precedence climbing over the lexer's tokens
+//!
+//! `cargo run --example calculator -- "123 + 456 * (7 - 89)"`
+
+use lexer_generator::{Lexer, ParsingError, RuleSet, Token};
+
+const RULES: &str = r#"{
+    "version": 2,
+    "literals": {
+        "number": "[0-9]+(\\.[0-9]+)?",
+        "add": { "literal": "+", "category": "additive" },
+        "subtract": { "literal": "-", "category": "additive" },
+        "multiply": { "literal": "*", "category": "multiplicative" },
+        "divide": { "literal": "/", "category": "multiplicative" },
+        "open": { "literal": "(" },
+        "close": { "literal": ")" }
+    }
+}"#;
+
+/// Binding power of a binary operator, from its rule's category
+fn precedence(token: &Token) -> Option<u8> {
+    match token.category()? {
+        "additive" => Some(1),
+        "multiplicative" => Some(2),
+        _ => None
+    }
+}
+
+/// Evaluates operands joined by operators binding at least as tightly as `min`
+fn expression(lexer: &mut Lexer, min: u8) -> Result<f64, String> {
+    let mut left = operand(lexer)?;
+    loop {
+        let operator = match lexer.peek_nth_token(0) {
+            Ok(token) => token,
+            Err(ParsingError::EndOfFileError) => return Ok(left),
+            Err(e) => return Err(e.to_string())
+        };
+        let binding = match precedence(&operator) {
+            Some(binding) if binding >= min => binding,
+            _ => return Ok(left)
+        };
+        lexer.next_token().map_err(|e| e.to_string())?;
+        let right = expression(lexer, binding + 1)?;
+        left = match operator.token_type.as_str() {
+            "add" => left + right,
+            "subtract" => left - right,
+            "multiply" => left * right,
+            _ => left / right
+        };
+    }
+}
+
+fn operand(lexer: &mut Lexer) -> Result<f64, String> {
+    let token = lexer.next_token().map_err(|e| e.to_string())?;
+    match token.token_type.as_str() {
+        "number" => token.value.parse().map_err(|_| format!("{}: invalid number {}", token.location(), token.value)),
+        "subtract" => Ok(-operand(lexer)?),
+        "open" => {
+            let value = expression(lexer, 1)?;
+            match lexer.next_token() {
+                Ok(close) if close.is(vec!["close"]) => Ok(value),
+                Ok(other) => Err(format!("{}: expected ), found {}", other.location(), other.value)),
+                Err(e) => Err(e.to_string())
+            }
+        }
+        _ => Err(format!("{}: expected a number, found {}", token.location(), token.value))
+    }
+}
+
+fn evaluate(source: &str) -> Result<f64, String> {
+    let rules = RuleSet::load(RULES).map_err(|e| e.to_string())?;
+    let mut lexer = Lexer::from_rules(rules, source.to_string()).with_source_name("input");
+    let value = expression(&mut lexer, 1)?;
+    match lexer.next_token() {
+        Err(ParsingError::EndOfFileError) => Ok(value),
+        Ok(token) => Err(format!("{}: unexpected {}", token.location(), token.value)),
+        Err(e) => Err(e.to_string())
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let source = if args.is_empty() { String::from("123 + 456 * 789") } else { args.join(" ") };
+    match evaluate(&source) {
+        Ok(value) => println!("{}", value),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
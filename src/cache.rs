@@ -0,0 +1,146 @@
+//! Caching lexed tokens in a compact binary file, only built with the `postcard` feature
+
+use serde::{Deserialize, Serialize};
+
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{NumberFormat, RuleSet, Token, TokenOrigin, TokenTypeId};
+
+/// Version of the cache format, files of another version are rejected
+const FORMAT: u32 = 1;
+
+#[derive(Debug)]
+pub enum CacheError {
+    Io(std::io::Error),
+    /// The file isn't a token cache
+    Format(postcard::Error),
+    /// The file is a token cache of another version of the format
+    Version(u32),
+    /// The tokens were lexed with other rules, (fingerprint of the given rules, fingerprint in the file)
+    StaleRules(u64, u64)
+}
+
+impl std::fmt::Display for CacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::Io(e) => write!(f, "{}", e),
+            CacheError::Format(e) => write!(f, "not a token cache: {}", e),
+            CacheError::Version(version) => write!(f, "token cache of format {} instead of {}", version, FORMAT),
+            CacheError::StaleRules(expected, found) => write!(f, "tokens were lexed with other rules, fingerprint {:016x} instead of {:016x}", found, expected)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedTokens {
+    format: u32,
+    fingerprint: u64,
+    tokens: Vec<CachedToken>
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedToken {
+    token_type: String,
+    type_id: u16,
+    value: String,
+    raw: String,
+    line: usize,
+    column: usize,
+    start: usize,
+    end: usize,
+    source_name: Option<String>,
+    category: Option<String>,
+    sub_kind: Option<String>,
+    origin: u8
+}
+
+impl From<&Token> for CachedToken {
+    fn from(token: &Token) -> Self {
+        Self {
+            token_type: token.token_type.clone(),
+            type_id: token.type_id.0,
+            value: token.value.clone(),
+            raw: token.raw.clone(),
+            line: token.line,
+            column: token.column,
+            start: token.span.start,
+            end: token.span.end,
+            source_name: token.source_name.as_deref().map(String::from),
+            category: token.category.as_deref().map(String::from),
+            sub_kind: token.sub_kind.map(String::from),
+            origin: match token.origin {
+                TokenOrigin::Source => 0,
+                TokenOrigin::Inserted => 1,
+                TokenOrigin::Recovered => 2
+            }
+        }
+    }
+}
+
+impl From<CachedToken> for Token {
+    fn from(token: CachedToken) -> Self {
+        Token {
+            token_type: token.token_type,
+            type_id: TokenTypeId(token.type_id),
+            value: token.value,
+            raw: token.raw,
+            line: token.line,
+            column: token.column,
+            span: token.start..token.end,
+            source_name: token.source_name.map(Arc::from),
+            category: token.category.map(Arc::from),
+            sub_kind: token.sub_kind.and_then(|kind| NumberFormat::SUB_KINDS.into_iter().find(|known| *known == kind)),
+            origin: match token.origin {
+                1 => TokenOrigin::Inserted,
+                2 => TokenOrigin::Recovered,
+                _ => TokenOrigin::Source
+            }
+        }
+    }
+}
+
+/// Encodes tokens lexed with `rules` in the binary cache format, with the fingerprint of the rules
+pub fn encode_tokens(tokens: &[Token], rules: &RuleSet) -> Vec<u8> {
+    let cached = CachedTokens { format: FORMAT, fingerprint: rules.fingerprint(), tokens: tokens.iter().map(CachedToken::from).collect() };
+    postcard::to_allocvec(&cached).unwrap()
+}
+
+/// Decodes tokens encoded by `encode_tokens`, failing with `CacheError::StaleRules` unless they were lexed with the
+/// same rules
+pub fn decode_tokens(bytes: &[u8], rules: &RuleSet) -> Result<Vec<Token>, CacheError> {
+    let cached: CachedTokens = postcard::from_bytes(bytes).map_err(CacheError::Format)?;
+    if cached.format != FORMAT {
+        return Err(CacheError::Version(cached.format));
+    }
+    let fingerprint = rules.fingerprint();
+    if cached.fingerprint != fingerprint {
+        return Err(CacheError::StaleRules(fingerprint, cached.fingerprint));
+    }
+    Ok(cached.tokens.into_iter().map(Token::from).collect())
+}
+
+/// Writes tokens lexed with `rules` to a cache file at `path`
+///
+/// ```no_run
+/// use lexer_generator::{grammars, load_cached_tokens, save_cached_tokens, Lexer};
+///
+/// let rules = grammars::arithmetic();
+/// let tokens = match load_cached_tokens("corpus/1.tokens", &rules) {
+///     Ok(tokens) => tokens,
+///     Err(_) => {
+///         let source = std::fs::read_to_string("corpus/1.txt").unwrap();
+///         let tokens: Vec<_> = Lexer::from_rules(rules.clone(), source).map(Result::unwrap).collect();
+///         save_cached_tokens("corpus/1.tokens", &tokens, &rules).unwrap();
+///         tokens
+///     }
+/// };
+/// ```
+pub fn save_cached_tokens(path: impl AsRef<Path>, tokens: &[Token], rules: &RuleSet) -> Result<(), CacheError> {
+    std::fs::write(path, encode_tokens(tokens, rules)).map_err(CacheError::Io)
+}
+
+/// Reads the tokens of a cache file written by `save_cached_tokens`, only if they were lexed with `rules`
+pub fn load_cached_tokens(path: impl AsRef<Path>, rules: &RuleSet) -> Result<Vec<Token>, CacheError> {
+    decode_tokens(&std::fs::read(path).map_err(CacheError::Io)?, rules)
+}
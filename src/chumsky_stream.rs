@@ -0,0 +1,33 @@
+//! Lexed tokens as input for chumsky parsers, only built with the `chumsky` feature
+
+use chumsky::{BoxStream, Stream};
+
+use std::ops::Range;
+
+use crate::{Lexer, Token};
+
+/// The tokens as a chumsky stream of `(token, span)` pairs, whose end of input is the end of the last token
+///
+/// ```
+/// use lexer_generator::{chumsky_stream, Lexer};
+///
+/// let rules = String::from(r#"{ "literals": { "number": "[0-9]+" } }"#);
+/// let tokens: Vec<_> = Lexer::from(rules.clone(), String::from("1 23")).map(Result::unwrap).collect();
+/// let stream = chumsky_stream(tokens);
+/// // or straight from a lexer
+/// let stream = Lexer::from(rules, String::from("1 23")).chumsky_stream().unwrap();
+/// ```
+pub fn chumsky_stream<'a>(tokens: Vec<Token>) -> BoxStream<'a, Token, Range<usize>> {
+    let end = tokens.last().map_or(0, |token| token.span.end);
+    Stream::from_iter(end..end, Box::new(tokens.into_iter().map(|token| {
+        let span = token.span.clone();
+        (token, span)
+    })))
+}
+
+impl Lexer {
+    /// Lexes the rest of the source into a chumsky stream with `chumsky_stream`, stopping at the first error
+    pub fn chumsky_stream<'a>(&mut self) -> Result<BoxStream<'a, Token, Range<usize>>, crate::ParsingError> {
+        Ok(chumsky_stream(self.by_ref().collect::<Result<_, _>>()?))
+    }
+}
@@ -0,0 +1,127 @@
+//! Cursor over lexed tokens with the conveniences hand-written parsers need
+
+use crate::{Lexer, ParseError, ParsingError, Token};
+
+/// Position in the tokens of a cursor, returned by `TokenCursor::save` and given back to `TokenCursor::restore`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CursorPos(usize);
+
+/// Cursor over a vector of tokens, for recursive descent parsers
+///
+/// ```
+/// use lexer_generator::{Lexer, TokenCursor};
+///
+/// let rules = String::from(r#"{ "literals": { "number": "[0-9]+", "add": "\\+", "lparen": "\\(", "rparen": "\\)" } }"#);
+/// let mut cursor = TokenCursor::lex(&mut Lexer::from(rules, String::from("(1 + 2)"))).unwrap();
+///
+/// cursor.expect("lparen").unwrap();
+/// let saved = cursor.save();
+/// assert_eq!(cursor.bump().unwrap().value, "1");
+/// assert!(cursor.eat("add").is_some());
+/// cursor.restore(saved);
+/// assert!(cursor.at("number"));
+/// assert!(cursor.expect("rparen").is_err());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TokenCursor {
+    tokens: Vec<Token>,
+    pos: usize
+}
+
+impl TokenCursor {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self { tokens, pos: 0 }
+    }
+
+    /// A cursor over the rest of the lexer's tokens
+    pub fn lex(lexer: &mut Lexer) -> Result<Self, ParsingError> {
+        let mut tokens = vec![];
+        loop {
+            match lexer.next_token() {
+                Ok(token) => tokens.push(token),
+                Err(ParsingError::EndOfFileError) => return Ok(Self::new(tokens)),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    /// The next token, None at the end
+    pub fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// The token `n` tokens after the next one, `peek_nth(0)` is `peek()`
+    pub fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + n)
+    }
+
+    /// Moves past the next token and returns it, None at the end
+    pub fn bump(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    /// Whether the next token is of type `kind`
+    pub fn at(&self, kind: &str) -> bool {
+        self.peek().is_some_and(|token| token.token_type == kind)
+    }
+
+    /// Moves past the next token and returns it if it is of type `kind`
+    pub fn eat(&mut self, kind: &str) -> Option<&Token> {
+        if self.at(kind) { self.bump() } else { None }
+    }
+
+    /// Moves past the next token and returns it if it is of type `kind`, otherwise leaves it for error recovery and
+    /// returns `ParseError::Unexpected`
+    pub fn expect(&mut self, kind: &str) -> Result<&Token, ParseError> {
+        if self.at(kind) {
+            Ok(self.bump().unwrap())
+        } else {
+            Err(ParseError::Unexpected { expected: vec![kind.to_string()], found: self.peek().cloned().map(Box::new) })
+        }
+    }
+
+    /// Whether every token was moved past
+    pub fn is_at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    /// The current position, to backtrack to with `restore`
+    pub fn save(&self) -> CursorPos {
+        CursorPos(self.pos)
+    }
+
+    /// Goes back, or forward, to a position saved from this cursor
+    pub fn restore(&mut self, pos: CursorPos) {
+        self.pos = pos.0.min(self.tokens.len());
+    }
+
+    /// Byte offset of the next token, or the end of the last one
+    pub fn offset(&self) -> usize {
+        match self.peek() {
+            Some(token) => token.span.start,
+            None => self.tokens.last().map_or(0, |t| t.span.end)
+        }
+    }
+
+    /// The tokens moved past since `pos`
+    pub fn since(&self, pos: CursorPos) -> &[Token] {
+        &self.tokens[pos.0.min(self.pos)..self.pos]
+    }
+
+    /// All the tokens, including those moved past
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn into_tokens(self) -> Vec<Token> {
+        self.tokens
+    }
+}
+
+impl From<Vec<Token>> for TokenCursor {
+    fn from(tokens: Vec<Token>) -> Self {
+        Self::new(tokens)
+    }
+}
@@ -0,0 +1,149 @@
+//! Token-level diffs of two versions of a source, for telling changes of meaning from changes of layout
+
+use std::sync::Arc;
+
+use crate::{CompiledRules, Lexer, ParsingError, RuleSet, Token};
+
+#[derive(Clone, Debug)]
+/// A run of tokens of the old version replaced with a run of the new one, one of them is empty for a pure removal
+/// or addition
+pub struct TokenChange {
+    pub removed: Vec<Token>,
+    pub added: Vec<Token>
+}
+
+impl std::fmt::Display for TokenChange {
+    /// Writes a line per token, `- line:column type(value)` for removed and `+ ...` for added ones
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (sign, token) in self.removed.iter().map(|token| ('-', token)).chain(self.added.iter().map(|token| ('+', token))) {
+            writeln!(f, "{} {}:{} {}", sign, token.line, token.column, token)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares two versions of a source by their tokens, ignoring trivia, so reformatting or editing comments isn't a
+/// change
+///
+/// Tokens are equal if their types and values are. Trivia are whitespace tokens and comments, tokens whose type or
+/// category is `comment` or ends with `_comment`, and `with_trivia` adds more.
+///
+/// ```
+/// use lexer_generator::{RuleSet, TokenDiff};
+///
+/// let rules = RuleSet::load(r##"{ "version": 2,
+///     "literals": { "key": "[a-z]+", "equals": "=", "number": "[0-9]+", "line_comment": "#[^\\n]*" }
+/// }"##).unwrap();
+/// let changes = TokenDiff::new(&rules).diff("port = 80\nhost = 1", "# web\nport=8080\nhost = 1").unwrap();
+/// assert_eq!(changes.len(), 1);
+/// assert_eq!(changes[0].to_string(), "- 0:7 number(80)\n+ 1:5 number(8080)\n");
+/// ```
+#[derive(Clone)]
+pub struct TokenDiff {
+    compiled: Arc<CompiledRules>,
+    trivia: Vec<String>
+}
+
+impl TokenDiff {
+    pub fn new(rules: &RuleSet) -> Self {
+        Self { compiled: Arc::new(CompiledRules::from(rules.clone())), trivia: rules.whitespace_token.iter().cloned().collect() }
+    }
+
+    /// Also ignores tokens whose type or category is `kind`
+    pub fn with_trivia(mut self, kind: &str) -> Self {
+        self.trivia.push(kind.to_string());
+        self
+    }
+
+    /// Lexes both versions and compares them, failing at the first error of either
+    pub fn diff(&self, old: &str, new: &str) -> Result<Vec<TokenChange>, ParsingError> {
+        let lex = |source: &str| Lexer::from_compiled(self.compiled.clone(), source.to_string()).collect::<Result<Vec<_>, _>>();
+        Ok(self.diff_tokens(&lex(old)?, &lex(new)?))
+    }
+
+    /// Compares tokens lexed before, the changes are in the order of the source
+    pub fn diff_tokens(&self, old: &[Token], new: &[Token]) -> Vec<TokenChange> {
+        let old: Vec<&Token> = old.iter().filter(|token| !self.is_trivia(token)).collect();
+        let new: Vec<&Token> = new.iter().filter(|token| !self.is_trivia(token)).collect();
+        let key = |token: &&Token| (token.token_type.clone(), token.value.clone());
+        let (old_keys, new_keys): (Vec<_>, Vec<_>) = (old.iter().map(key).collect(), new.iter().map(key).collect());
+        let mut changes = vec![];
+        let mut change = TokenChange { removed: vec![], added: vec![] };
+        for edit in shortest_edit(&old_keys, &new_keys) {
+            match edit {
+                Edit::Keep => {
+                    if !change.removed.is_empty() || !change.added.is_empty() {
+                        changes.push(std::mem::replace(&mut change, TokenChange { removed: vec![], added: vec![] }));
+                    }
+                }
+                Edit::Remove(i) => change.removed.push(old[i].clone()),
+                Edit::Add(j) => change.added.push(new[j].clone())
+            }
+        }
+        if !change.removed.is_empty() || !change.added.is_empty() {
+            changes.push(change);
+        }
+        changes
+    }
+
+    fn is_trivia(&self, token: &Token) -> bool {
+        let comment = |kind: &str| kind == "comment" || kind.ends_with("_comment");
+        comment(&token.token_type) || token.category().is_some_and(comment)
+            || self.trivia.iter().any(|kind| *kind == token.token_type || token.category() == Some(kind.as_str()))
+    }
+}
+
+/// Step of an edit script, with the index of the removed or added item
+enum Edit {
+    Keep,
+    Remove(usize),
+    Add(usize)
+}
+
+/// Shortest edit script turning `a` into `b`, Myers' algorithm, removals before additions at each place
+fn shortest_edit<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    // furthest x on each diagonal k = x - y, at index k + max
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    // the diagonals -d..=d after each round d
+    let mut trace: Vec<Vec<isize>> = vec![];
+    'rounds: for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let down = k == -d || (k != d && v[(k - 1 + max) as usize] < v[(k + 1 + max) as usize]);
+            let mut x = if down { v[(k + 1 + max) as usize] } else { v[(k - 1 + max) as usize] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(k + max) as usize] = x;
+            if x >= n && y >= m {
+                trace.push(v[(max - d) as usize..=(max + d) as usize].to_vec());
+                break 'rounds;
+            }
+        }
+        trace.push(v[(max - d) as usize..=(max + d) as usize].to_vec());
+    }
+    let mut edits = vec![];
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let previous = &trace[d as usize - 1];
+        let at = |k: isize| previous[(k + d - 1) as usize];
+        let k = x - y;
+        let down = k == -d || (k != d && at(k - 1) < at(k + 1));
+        let previous_k = if down { k + 1 } else { k - 1 };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        edits.push(if down { Edit::Add(y as usize - 1) } else { Edit::Remove(x as usize - 1) });
+        (x, y) = (previous_x, previous_y);
+    }
+    edits.extend((0..x).map(|_| Edit::Keep));
+    edits.reverse();
+    edits
+}
@@ -0,0 +1,447 @@
+//! Exporters from a `RuleSet` to other tools' grammar formats
+
+use serde_json::{json, Map, Value};
+
+use std::collections::BTreeMap;
+
+use crate::{fixed_string, Rule, RuleSet, MAIN_MODE};
+
+#[derive(Clone, Copy)]
+/// Coarse kind of token guessed from its name or pattern, so editor themes color it without extra configuration
+enum Highlight {
+    Comment,
+    String,
+    Number,
+    Keyword,
+    Constant,
+    Variable,
+    Operator,
+    Other
+}
+
+impl Highlight {
+    fn guess(token_type: &str, pattern: &str) -> Self {
+        let name = token_type.to_lowercase();
+        let has = |words: &[&str]| words.iter().any(|w| name.contains(w));
+        if has(&["comment"]) {
+            Highlight::Comment
+        } else if has(&["string", "str", "char"]) {
+            Highlight::String
+        } else if has(&["number", "num", "int", "float", "digit", "hex"]) {
+            Highlight::Number
+        } else if has(&["keyword", "kw"]) {
+            Highlight::Keyword
+        } else if has(&["bool", "true", "false", "null", "nil"]) {
+            Highlight::Constant
+        } else if has(&["ident", "name", "id"]) {
+            Highlight::Variable
+        } else {
+            match fixed_string(pattern) {
+                Some(s) if s.chars().all(|c| c.is_alphanumeric() || c == '_') => Highlight::Keyword,
+                Some(_) => Highlight::Operator,
+                None => Highlight::Other
+            }
+        }
+    }
+
+    fn textmate_scope(self) -> &'static str {
+        match self {
+            Highlight::Comment => "comment.line",
+            Highlight::String => "string.quoted",
+            Highlight::Number => "constant.numeric",
+            Highlight::Keyword => "keyword.control",
+            Highlight::Constant => "constant.language",
+            Highlight::Variable => "variable.other",
+            Highlight::Operator => "keyword.operator",
+            Highlight::Other => "meta.token"
+        }
+    }
+
+    /// Standard tree-sitter highlight capture, none for tokens which shouldn't be highlighted
+    fn capture(self) -> Option<&'static str> {
+        match self {
+            Highlight::Comment => Some("comment"),
+            Highlight::String => Some("string"),
+            Highlight::Number => Some("number"),
+            Highlight::Keyword => Some("keyword"),
+            Highlight::Constant => Some("constant.builtin"),
+            Highlight::Variable => Some("variable"),
+            Highlight::Operator => Some("operator"),
+            Highlight::Other => None
+        }
+    }
+}
+
+/// Rewrites the syntax `regex` accepts but Oniguruma doesn't
+fn oniguruma(pattern: &str) -> String {
+    pattern.replace("(?P<", "(?<")
+}
+
+/// Character of a pest string or character literal, quoted with `quote`
+fn pest_char(c: char, quote: char) -> String {
+    match c {
+        '\\' => String::from("\\\\"),
+        c if c == quote => format!("\\{}", c),
+        c if c.is_control() || (c.is_whitespace() && c != ' ') => format!("\\u{{{:x}}}", c as u32),
+        c => c.to_string()
+    }
+}
+
+/// The pest expression matching what `pattern` matches, None for patterns using what pest can't express, like word
+/// boundaries, lazy repetitions or large character classes other than `\w`, `\d` and `\s`
+///
+/// PEG repetitions never give back what they matched, so a pattern like `a*a` converts to an expression which can't
+/// match and has to be rewritten by hand.
+fn pest_expression(pattern: &str) -> Option<String> {
+    use regex_syntax::hir::{Anchor, Class, ClassUnicode, Hir, HirKind, Literal, RepetitionKind, RepetitionRange};
+    let builtins: Vec<(ClassUnicode, &str)> = [
+        ("\\w", "(ALPHABETIC | MARK | DECIMAL_NUMBER | CONNECTOR_PUNCTUATION | JOIN_CONTROL)"),
+        ("\\d", "DECIMAL_NUMBER"),
+        ("\\s", "WHITE_SPACE")
+    ].into_iter().filter_map(|(class, builtin)| match regex_syntax::Parser::new().parse(class).ok()?.into_kind() {
+        HirKind::Class(Class::Unicode(class)) => Some((class, builtin)),
+        _ => None
+    }).collect();
+    let class_expression = |class: &ClassUnicode| -> Option<String> {
+        if let Some((_, builtin)) = builtins.iter().find(|(known, _)| known == class) {
+            return Some(builtin.to_string());
+        }
+        let ranges = |class: &ClassUnicode| class.iter().map(|r| if r.start() == r.end() {
+            format!("\"{}\"", pest_char(r.start(), '"'))
+        } else {
+            format!("'{}'..'{}'", pest_char(r.start(), '\''), pest_char(r.end(), '\''))
+        }).collect::<Vec<_>>();
+        let mut negated = class.clone();
+        negated.negate();
+        // `[^"]` is a handful of ranges up to U+10FFFF, but reads better as everything but `"`
+        let (ranges, negated) = if negated.iter().count() < class.iter().count() {
+            let known = builtins.iter().find(|(known, _)| *known == negated).map(|(_, builtin)| builtin.to_string());
+            (known.map_or_else(|| ranges(&negated), |builtin| vec![builtin]), true)
+        } else {
+            (ranges(class), false)
+        };
+        if ranges.is_empty() || ranges.len() > 16 {
+            None
+        } else if negated && ranges.len() == 1 {
+            Some(format!("(!{} ~ ANY)", ranges[0]))
+        } else if negated {
+            Some(format!("(!({}) ~ ANY)", ranges.join(" | ")))
+        } else if ranges.len() == 1 {
+            Some(ranges.into_iter().next().unwrap())
+        } else {
+            Some(format!("({})", ranges.join(" | ")))
+        }
+    };
+    fn walk(hir: &Hir, class_expression: &dyn Fn(&ClassUnicode) -> Option<String>) -> Option<String> {
+        match hir.kind() {
+            HirKind::Empty => Some(String::from("\"\"")),
+            HirKind::Literal(Literal::Unicode(c)) => Some(format!("\"{}\"", pest_char(*c, '"'))),
+            HirKind::Class(Class::Unicode(class)) => class_expression(class),
+            HirKind::Anchor(Anchor::StartText) => Some(String::from("SOI")),
+            HirKind::Anchor(Anchor::EndText) => Some(String::from("EOI")),
+            HirKind::Repetition(repetition) if repetition.greedy => {
+                let operator = match &repetition.kind {
+                    RepetitionKind::ZeroOrOne => String::from("?"),
+                    RepetitionKind::ZeroOrMore => String::from("*"),
+                    RepetitionKind::OneOrMore => String::from("+"),
+                    RepetitionKind::Range(RepetitionRange::Exactly(n)) => format!("{{{}}}", n),
+                    RepetitionKind::Range(RepetitionRange::AtLeast(n)) => format!("{{{},}}", n),
+                    RepetitionKind::Range(RepetitionRange::Bounded(n, m)) => format!("{{{}, {}}}", n, m)
+                };
+                let inner = walk(&repetition.hir, class_expression)?;
+                let mut repeated = &repetition.hir;
+                while let HirKind::Group(group) = repeated.kind() {
+                    repeated = &group.hir;
+                }
+                // a postfix operator binds to one term, so sequences need parentheses unless they became one string,
+                // choices already have them
+                let simple = match repeated.kind() {
+                    HirKind::Concat(hirs) => hirs.iter().all(|h| matches!(h.kind(), HirKind::Literal(Literal::Unicode(_)))),
+                    _ => true
+                };
+                Some(if simple { format!("{}{}", inner, operator) } else { format!("({}){}", inner, operator) })
+            }
+            HirKind::Group(group) => walk(&group.hir, class_expression),
+            HirKind::Concat(hirs) => {
+                // runs of characters become one string
+                let mut parts: Vec<String> = vec![];
+                let mut run = String::new();
+                for hir in hirs {
+                    if let HirKind::Literal(Literal::Unicode(c)) = hir.kind() {
+                        run.push_str(&pest_char(*c, '"'));
+                        continue;
+                    }
+                    if !run.is_empty() {
+                        parts.push(format!("\"{}\"", std::mem::take(&mut run)));
+                    }
+                    parts.push(walk(hir, class_expression)?);
+                }
+                if !run.is_empty() {
+                    parts.push(format!("\"{}\"", run));
+                }
+                Some(if parts.len() == 1 { parts.remove(0) } else { parts.join(" ~ ") })
+            }
+            HirKind::Alternation(hirs) => {
+                let choices = hirs.iter().map(|h| walk(h, class_expression)).collect::<Option<Vec<_>>>()?;
+                Some(format!("({})", choices.join(" | ")))
+            }
+            _ => None
+        }
+    }
+    let hir = regex_syntax::Parser::new().parse(pattern).ok()?;
+    walk(&hir, &class_expression)
+}
+
+/// `name` as a pest rule name, which only has letters, digits and underscores
+fn pest_name(name: &str) -> String {
+    let name: String = name.chars().map(|c| if c.is_alphanumeric() || c == '_' { c } else { '_' }).collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) { format!("_{}", name) } else { name }
+}
+
+/// Markdown code span of `text` which is safe inside a table cell
+fn code(text: &str) -> String {
+    let text = text.replace('|', "\\|");
+    if text.contains('`') { format!("`` {} ``", text) } else { format!("`{}`", text) }
+}
+
+/// A rule's patterns for people, fixed strings as they are and regexes between slashes
+fn readable(rule: &Rule) -> String {
+    let one = |pattern: &str| match fixed_string(pattern) {
+        Some(s) => code(&s),
+        None => code(&format!("/{}/", pattern))
+    };
+    match rule {
+        Rule::Pattern(pattern) => one(pattern),
+        Rule::Alternatives(patterns) => patterns.iter().map(|p| one(p)).collect::<Vec<_>>().join(" or "),
+        Rule::Options(options) => match (&options.pattern, &options.literal, &options.number, &options.native) {
+            (Some(pattern), _, _, _) => readable(pattern),
+            (None, Some(literal), _, _) => code(literal),
+            (None, None, Some(number), _) => {
+                let mut formats: Vec<String> = number.radixes.clone();
+                formats.extend([("float", number.float), ("exponent", number.exponent)].iter().filter(|(_, on)| *on).map(|(f, _)| f.to_string()));
+                formats.extend(number.separator.iter().map(|s| format!("{} separators", code(s))));
+                formats.extend(number.suffixes.iter().map(|s| format!("suffix {}", code(s))));
+                if formats.is_empty() { String::from("decimal number") } else { format!("number with {}", formats.join(", ")) }
+            }
+            (None, None, None, Some(native)) => format!("native {}", code(native)),
+            (None, None, None, None) => String::new()
+        }
+    }
+}
+
+/// A short string the whole pattern matches, repeating as little as possible and preferring letters and digits, None
+/// for patterns whose shortest match is empty or which can't be built from their parts, like ones with anchors
+pub(crate) fn example(pattern: &str) -> Option<String> {
+    use regex_syntax::hir::{Class, Hir, HirKind, Literal, RepetitionKind, RepetitionRange};
+    fn walk(hir: &Hir, out: &mut String) -> bool {
+        match hir.kind() {
+            HirKind::Empty => true,
+            HirKind::Literal(Literal::Unicode(c)) => { out.push(*c); true }
+            HirKind::Class(Class::Unicode(class)) => {
+                let contains = |c: char| class.iter().any(|r| r.start() <= c && c <= r.end());
+                match ['a', '0', 'x', ' '].into_iter().find(|c| contains(*c)).or_else(|| class.iter().next().map(|r| r.start())) {
+                    Some(c) => { out.push(c); true }
+                    None => false
+                }
+            }
+            HirKind::Repetition(repetition) => {
+                let times = match &repetition.kind {
+                    RepetitionKind::ZeroOrOne | RepetitionKind::ZeroOrMore => 0,
+                    RepetitionKind::OneOrMore => 1,
+                    RepetitionKind::Range(RepetitionRange::Exactly(n) | RepetitionRange::AtLeast(n) | RepetitionRange::Bounded(n, _)) => *n
+                };
+                (0..times).all(|_| walk(&repetition.hir, out))
+            }
+            HirKind::Group(group) => walk(&group.hir, out),
+            HirKind::Concat(hirs) => hirs.iter().all(|h| walk(h, out)),
+            HirKind::Alternation(hirs) => hirs.first().is_some_and(|h| walk(h, out)),
+            _ => false
+        }
+    }
+    let hir = regex_syntax::Parser::new().parse(pattern).ok()?;
+    let mut out = String::new();
+    // the walk ignores things like lazy repetitions, so the example is only trusted if the pattern really matches it
+    let whole = regex::Regex::new(&format!("^(?:{})$", pattern)).ok()?;
+    if walk(&hir, &mut out) && !out.is_empty() && whole.is_match(&out) { Some(out) } else { None }
+}
+
+impl RuleSet {
+    /// Main mode token types in the order a matcher taking the first match rather than the longest should try them,
+    /// preferred rules of conflicts first, then fixed strings from longest to shortest, then everything else
+    fn first_match_order(&self) -> Vec<&String> {
+        let mut order: Vec<&String> = self.literals.keys().collect();
+        order.sort_by_key(|rule| {
+            let preferred = self.conflicts.iter().any(|c| &&c.prefer == rule);
+            let fixed_len = fixed_string(&self.literals[*rule].pattern()).map(|s| s.len());
+            (!preferred, fixed_len.is_none(), std::cmp::Reverse(fixed_len))
+        });
+        order
+    }
+
+    /// Generates a basic TextMate grammar (`.tmLanguage.json`) with one match rule per token type
+    ///
+    /// Scopes are `<guessed standard scope>.<token type>.<language>`, e.g. `constant.numeric.number.calc` for a
+    /// `number` rule. TextMate picks the earliest match and then the first pattern rather than the longest match, so
+    /// preferred rules of conflicts come first, then fixed strings from longest to shortest, then everything else.
+    ///
+    /// Only the rules of the main mode are exported, TextMate has no modes to switch between, and native rules are left
+    /// out since they have no pattern.
+    pub fn to_textmate(&self, name: &str, language: &str) -> String {
+        let order: Vec<&String> = self.first_match_order().into_iter().filter(|rule| self.literals[*rule].native().is_none()).collect();
+
+        let mut repository = Map::new();
+        for rule in &order {
+            let pattern = &self.literals[*rule].pattern();
+            // without the longest match, a keyword would also be found inside a longer identifier
+            let word = fixed_string(pattern).is_some_and(|s| s.chars().all(|c| c.is_alphanumeric() || c == '_'));
+            let matcher = if word { format!("\\b{}\\b", pattern) } else { oniguruma(pattern) };
+            repository.insert(rule.to_string(), json!({
+                "match": matcher,
+                "name": format!("{}.{}.{}", Highlight::guess(rule, pattern).textmate_scope(), rule, language)
+            }));
+        }
+        let patterns: Vec<Value> = order.iter().map(|rule| json!({ "include": format!("#{}", rule) })).collect();
+        serde_json::to_string_pretty(&json!({
+            "$schema": "https://raw.githubusercontent.com/martinring/tmlanguage/master/tmlanguage.json",
+            "name": name,
+            "scopeName": format!("source.{}", language),
+            "patterns": patterns,
+            "repository": repository
+        })).unwrap()
+    }
+
+    /// Generates a pest grammar skeleton with an atomic rule per token type, `WHITESPACE` from the whitespace pattern and
+    /// a `tokens` rule lexing a whole source, to prototype parsers in pest next to this lexer
+    ///
+    /// Patterns are converted where pest can express them, ones which can't be, like native rules or patterns with word
+    /// boundaries, are left as comments to write by hand. PEG choices take the first alternative which matches, so
+    /// `token` tries the types in the same order as `to_textmate`.
+    ///
+    /// ```
+    /// let rules = lexer_generator::RuleSet::from_string(String::from(r#"{ "literals": { "number": "[0-9]+", "let": "let", "name": "[a-z_]\\w*" } }"#));
+    /// let pest = rules.to_pest();
+    /// assert!(pest.contains("number = @{ '0'..'9'+ }"));
+    /// assert!(pest.contains("let = @{ \"let\" }"));
+    /// assert!(pest.contains("token = _{ let | name | number }"));
+    /// ```
+    pub fn to_pest(&self) -> String {
+        let mut types: BTreeMap<&String, &Rule> = self.literals.iter().collect();
+        for (name, rule) in self.modes.values().flat_map(|mode| &mode.literals) {
+            types.entry(name).or_insert(rule);
+        }
+
+        let mut out = String::from("// generated from the lexer rules\n\n");
+        if let Some(whitespace) = &self.whitespace {
+            match pest_expression(whitespace) {
+                Some(expression) => out.push_str(&format!("WHITESPACE = _{{ {} }}\n\n", expression)),
+                None => out.push_str(&format!("// WHITESPACE = _{{ /{}/ }}\n\n", whitespace))
+            }
+        }
+        for (name, rule) in &types {
+            let pattern = rule.pattern();
+            match (rule.native(), pest_expression(&pattern)) {
+                (Some(native), _) => out.push_str(&format!("// {} = @{{ native {} }}\n", pest_name(name), native)),
+                (None, Some(expression)) => out.push_str(&format!("{} = @{{ {} }}\n", pest_name(name), expression)),
+                (None, None) => out.push_str(&format!("// {} = @{{ /{}/ }}\n", pest_name(name), pattern))
+            }
+        }
+        let order: Vec<String> = self.first_match_order().into_iter().map(|name| pest_name(name)).collect();
+        out.push_str(&format!("\ntoken = _{{ {} }}\ntokens = {{ SOI ~ token* ~ EOI }}\n", order.join(" | ")));
+        out
+    }
+
+    /// Generates a tree-sitter `highlights.scm` query capturing each token type as a named node, e.g. `(number) @number`
+    ///
+    /// Captures are guessed the same way as the scopes of `to_textmate`, token types without a sensible capture are
+    /// listed as comments so they can be filled in by hand
+    pub fn to_highlights_query(&self) -> String {
+        let mut out = String::from("; generated from the lexer rules\n");
+        for (name, rule) in &self.literals {
+            match Highlight::guess(name, &rule.pattern()).capture() {
+                Some(capture) => out.push_str(&format!("({}) @{}\n", name, capture)),
+                None => out.push_str(&format!("; ({}) has no standard capture\n", name))
+            }
+        }
+        out
+    }
+
+    /// Lists every token type with its pattern, the fixed string it matches if any, and its guessed highlight capture,
+    /// as JSON for tools which need to keep token names consistent with this lexer, like tree-sitter grammars
+    pub fn token_inventory(&self) -> String {
+        let tokens: Vec<Value> = self.literals.iter().map(|(name, rule)| (name, rule.pattern())).map(|(name, pattern)| json!({
+            "name": name,
+            "pattern": pattern,
+            "fixed": fixed_string(&pattern),
+            "capture": Highlight::guess(name, &pattern).capture()
+        })).collect();
+        serde_json::to_string_pretty(&json!({ "tokens": tokens, "whitespace": self.whitespace })).unwrap()
+    }
+
+    /// Reference documentation of the token types as Markdown, one table row per type with its patterns, category,
+    /// an example generated from the pattern where possible and the modes it is lexed in
+    ///
+    /// Types which no rule matches, the whitespace token, inserted semicolons and merged tokens, are listed with how
+    /// they are produced
+    ///
+    /// ```
+    /// let rules = lexer_generator::RuleSet::from_string(String::from(r#"{ "literals": { "number": "[0-9]+", "add": "\\+" } }"#));
+    /// let document = rules.document();
+    /// assert!(document.contains("| `number` | `/[0-9]+/` |  | `0` | main |"));
+    /// assert!(document.contains("| `add` | `+` |  | `+` | main |"));
+    /// ```
+    pub fn document(&self) -> String {
+        let mut types: BTreeMap<&String, Vec<(&str, &Rule)>> = BTreeMap::new();
+        for (name, rule) in &self.literals {
+            types.entry(name).or_default().push((MAIN_MODE, rule));
+        }
+        for (mode, rules) in &self.modes {
+            for (name, rule) in &rules.literals {
+                types.entry(name).or_default().push((mode.as_str(), rule));
+            }
+        }
+
+        let mut out = String::from("# Token types\n\n| Token type | Pattern | Category | Example | Modes |\n| --- | --- | --- | --- | --- |\n");
+        // line breaks and tabs would break the table
+        let visible = |e: String| e.chars().map(|c| if c.is_control() { c.escape_default().to_string() } else { c.to_string() }).collect::<String>();
+        for (name, rules) in &types {
+            let (_, rule) = rules[0];
+            let example = example(&rule.pattern()).map(|e| code(&visible(e))).unwrap_or_default();
+            let modes = rules.iter().map(|(mode, _)| *mode).collect::<Vec<_>>().join(", ");
+            let category = rules.iter().find_map(|(_, rule)| rule.category()).map(|c| c.as_str()).unwrap_or_default();
+            out.push_str(&format!("| {} | {} | {} | {} | {} |\n", code(name), readable(rule), category, example, modes));
+        }
+
+        let mut produced: Vec<(&String, String)> = vec![];
+        if let Some(name) = &self.whitespace_token {
+            produced.push((name, String::from("skipped whitespace")));
+        }
+        if let Some(asi) = &self.asi {
+            let after = asi.after.iter().map(|t| code(t)).collect::<Vec<_>>().join(", ");
+            produced.push((&asi.emit, format!("inserted at the end of a line after {}", after)));
+        }
+        for merge in &self.merge {
+            produced.push((&merge.into, format!("merged from {}", merge.seq.iter().map(|t| code(t)).collect::<Vec<_>>().join(" "))));
+        }
+        for interpolate in self.all_rules().filter_map(|(_, rule)| rule.interpolate()) {
+            produced.push((&interpolate.open_token, format!("starts an expression in a string, {}", code(&format!("/{}/", interpolate.open)))));
+            produced.push((&interpolate.close_token, format!("ends an expression in a string, {}", code(&format!("/{}/", interpolate.close)))));
+        }
+        for (rule, doc) in self.all_rules().filter_map(|(name, rule)| Some((name, rule.doc()?))) {
+            let markers = if doc.suffix.is_empty() {
+                format!("starting with {}", code(&doc.prefix))
+            } else {
+                format!("between {} and {}", code(&doc.prefix), code(&doc.suffix))
+            };
+            let description = format!("{} tokens {}", code(rule), markers);
+            // several comment rules often share one doc comment type
+            match produced.iter_mut().find(|(name, _)| *name == &doc.token) {
+                Some((_, existing)) => existing.push_str(&format!(", or {}", description)),
+                None => produced.push((&doc.token, description))
+            }
+        }
+        for (name, description) in produced.into_iter().filter(|(name, _)| !types.contains_key(name)) {
+            out.push_str(&format!("| {} | {} |  |  |  |\n", code(name), description));
+        }
+        out
+    }
+}
@@ -0,0 +1,61 @@
+//! Finding fenced regions of a host document, like code blocks in Markdown or heredocs, to lex them on their own
+
+use regex::Regex;
+
+use std::ops::Range;
+
+use crate::{Lexer, RuleSet};
+
+#[derive(Clone, Debug, PartialEq)]
+/// A region between an opening and a closing delimiter, found by `fenced_blocks`
+pub struct FencedBlock {
+    /// Text of the opening delimiter's `info` group, like the language of a Markdown code block
+    pub info: Option<String>,
+    /// Byte range of the text between the delimiters
+    pub range: Range<usize>
+}
+
+impl FencedBlock {
+    /// Lexer for the block's text, positions are those of the whole document
+    pub fn lexer(&self, rules: &RuleSet, document: &str) -> Lexer {
+        Lexer::from_rules(rules.clone(), document.to_string()).with_range(self.range.clone())
+    }
+}
+
+/// Every region of `document` between a match of the `open` pattern and the next match of `close` after it, a block
+/// which is never closed runs to the end of the document
+///
+/// Markdown code blocks, with the language as their info:
+///
+/// ```
+/// let document = "text\n```calc\n1 + 2\n```\nmore text\n";
+/// let blocks = lexer_generator::fenced_blocks(document, "(?m)^```(?P<info>[^\\n]*)\\n", "(?m)^```").unwrap();
+/// assert_eq!(blocks[0].info.as_deref(), Some("calc"));
+/// assert_eq!(&document[blocks[0].range.clone()], "1 + 2\n");
+/// ```
+pub fn fenced_blocks(document: &str, open: &str, close: &str) -> Result<Vec<FencedBlock>, regex::Error> {
+    Ok(find_blocks(document, &Regex::new(open)?, &Regex::new(close)?))
+}
+
+/// `fenced_blocks` with compiled patterns
+pub(crate) fn find_blocks(document: &str, open: &Regex, close: &Regex) -> Vec<FencedBlock> {
+    let info_group = open.capture_names().position(|name| name == Some("info"));
+    let mut locations = open.capture_locations();
+    let mut blocks = vec![];
+    let mut pos = 0;
+    while let Some(mat) = open.captures_read_at(&mut locations, document, pos) {
+        let start = mat.end();
+        let info = info_group.and_then(|i| locations.get(i)).map(|(s, e)| document[s..e].to_string());
+        let (end, next) = match close.find_at(document, start) {
+            Some(mat) => (mat.start(), mat.end()),
+            None => (document.len(), document.len())
+        };
+        blocks.push(FencedBlock { info, range: start..end });
+        // empty delimiters would find the same block forever
+        pos = if next > pos { next } else { next + document[next..].chars().next().map_or(1, char::len_utf8) };
+        if pos > document.len() {
+            break;
+        }
+    }
+    blocks
+}
@@ -0,0 +1,162 @@
+//! Re-emitting tokens with consistent spacing, a basic `fmt` tool from a rule set
+
+use serde::{Deserialize, Serialize};
+
+use std::sync::Arc;
+
+use crate::{CompiledRules, Lexer, ParsingError, RuleSet, Token};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+/// Spacing rules of the `"format"` section of a rule set, kinds are token types or categories, see `Formatter`
+pub struct FormatRules {
+    /// Kinds with a space on both sides, like `"operator"`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub space_around: Vec<String>,
+    /// Kinds ending a line, like `"semicolon"`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub newline_after: Vec<String>,
+    /// Openers of `"pairs"` whose contents go on lines of their own, indented, with the closer on a new line
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indent: Vec<String>,
+    /// One level of indentation, four spaces if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub indent_text: Option<String>
+}
+
+/// Space between two tokens, a wider one wins
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Gap {
+    None,
+    Space,
+    Newline,
+    BlankLine
+}
+
+/// Formats sources of a rule set by its `"format"` rules and `"pairs"`
+///
+/// Between tokens the formatter keeps what the source had, collapsed: nothing, one space, a line break or one blank
+/// line. The rules widen that, with spaces around `space_around` kinds, a line break after `newline_after` kinds and
+/// the contents of `indent` pairs on lines of their own, indented by how many such pairs are open. Whitespace tokens
+/// are dropped and every line is indented anew.
+///
+/// ```
+/// use lexer_generator::{Formatter, RuleSet};
+///
+/// let rules = RuleSet::load(r#"{ "version": 2,
+///     "literals": {
+///         "name": "[a-z]+", "number": "[0-9]+", "semicolon": ";", "lbrace": "\\{", "rbrace": "\\}",
+///         "assign": { "pattern": "=", "category": "operator" }
+///     },
+///     "pairs": [["lbrace", "rbrace"]],
+///     "format": { "space_around": ["operator"], "newline_after": ["semicolon"], "indent": ["lbrace"], "indent_text": "  " }
+/// }"#).unwrap();
+/// let formatted = Formatter::new(&rules).format("block {x=1;y   =2;}").unwrap();
+/// assert_eq!(formatted, "block {\n  x = 1;\n  y = 2;\n}\n");
+/// ```
+#[derive(Clone)]
+pub struct Formatter {
+    compiled: Arc<CompiledRules>,
+    pairs: Vec<(String, String)>,
+    whitespace_token: Option<String>,
+    format: FormatRules
+}
+
+impl Formatter {
+    /// A formatter with the `"format"` rules of `rules`, if it has any
+    pub fn new(rules: &RuleSet) -> Self {
+        Self {
+            compiled: Arc::new(CompiledRules::from(rules.clone())),
+            pairs: rules.pairs.clone(),
+            whitespace_token: rules.whitespace_token.clone(),
+            format: rules.format.clone().unwrap_or_default()
+        }
+    }
+
+    pub fn with_space_around(mut self, kind: &str) -> Self {
+        self.format.space_around.push(kind.to_string());
+        self
+    }
+
+    pub fn with_newline_after(mut self, kind: &str) -> Self {
+        self.format.newline_after.push(kind.to_string());
+        self
+    }
+
+    /// Puts the contents of the pair opened by `opener` on lines of their own, indented
+    pub fn with_indent(mut self, opener: &str) -> Self {
+        self.format.indent.push(opener.to_string());
+        self
+    }
+
+    pub fn with_indent_text(mut self, text: &str) -> Self {
+        self.format.indent_text = Some(text.to_string());
+        self
+    }
+
+    /// Lexes and formats `source`, failing at the first error so nothing is lost
+    pub fn format(&self, source: &str) -> Result<String, ParsingError> {
+        let tokens = Lexer::from_compiled(self.compiled.clone(), source.to_string()).collect::<Result<Vec<_>, _>>()?;
+        Ok(self.format_tokens(&tokens))
+    }
+
+    /// Formats tokens lexed before, their raw text is written, ending with a line break
+    pub fn format_tokens(&self, tokens: &[Token]) -> String {
+        let indent_text = self.format.indent_text.as_deref().unwrap_or("    ");
+        let mut formatted = String::new();
+        // closers of the indenting pairs which are open, innermost last
+        let mut open: Vec<&str> = vec![];
+        let mut prev: Option<&Token> = None;
+        for token in tokens.iter().filter(|token| self.whitespace_token.as_ref() != Some(&token.token_type)) {
+            let closes = open.last() == Some(&token.token_type.as_str());
+            if closes {
+                open.pop();
+            }
+            if let Some(prev) = prev {
+                let prev_end_line = prev.line + prev.raw.matches('\n').count();
+                let mut gap = match token.line.saturating_sub(prev_end_line) {
+                    0 if token.span.start > prev.span.end => Gap::Space,
+                    0 => Gap::None,
+                    1 => Gap::Newline,
+                    _ => Gap::BlankLine
+                };
+                if is(prev, &self.format.space_around) || is(token, &self.format.space_around) {
+                    gap = gap.max(Gap::Space);
+                }
+                if is(prev, &self.format.newline_after) || self.indenting(prev).is_some() || closes {
+                    gap = gap.max(Gap::Newline);
+                }
+                match gap {
+                    Gap::None => {}
+                    Gap::Space => formatted.push(' '),
+                    Gap::Newline | Gap::BlankLine => {
+                        formatted.push_str(if gap == Gap::BlankLine { "\n\n" } else { "\n" });
+                        formatted.push_str(&indent_text.repeat(open.len()));
+                    }
+                }
+            }
+            formatted.push_str(&token.raw);
+            if let Some(closer) = self.indenting(token) {
+                open.push(closer);
+            }
+            prev = Some(token);
+        }
+        if !formatted.is_empty() {
+            formatted.push('\n');
+        }
+        formatted
+    }
+
+    /// Closer of the pair `token` opens if its contents are indented
+    fn indenting(&self, token: &Token) -> Option<&str> {
+        if !self.format.indent.contains(&token.token_type) {
+            return None;
+        }
+        self.pairs.iter().find(|(opener, _)| *opener == token.token_type).map(|(_, closer)| closer.as_str())
+    }
+}
+
+/// Returns true if the token's type or category is one of `kinds`
+fn is(token: &Token, kinds: &[String]) -> bool {
+    kinds.iter().any(|kind| *kind == token.token_type || token.category() == Some(kind.as_str()))
+}
@@ -0,0 +1,205 @@
+//! Context free grammars over the token types of a rule set and their LL(1) parse tables
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Lookahead standing for the end of the input in FOLLOW sets and parse tables
+pub const END_OF_INPUT: &str = "$";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+/// The `"grammar"` section of a rule set
+///
+/// ```json
+/// "grammar": {
+///     "start": "expr",
+///     "productions": {
+///         "expr": [["number", "expr_tail"]],
+///         "expr_tail": [["add", "number", "expr_tail"], []]
+///     }
+/// }
+/// ```
+///
+/// Every nonterminal maps to its alternatives, symbols which aren't nonterminals are token types
+pub struct Grammar {
+    pub start: String,
+    pub productions: BTreeMap<String, Vec<Vec<String>>>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Several productions of `nonterminal` apply on the same lookahead, the grammar isn't LL(1)
+pub struct Ll1Conflict {
+    pub nonterminal: String,
+    pub lookahead: String,
+    /// Indices into the nonterminal's alternatives
+    pub productions: Vec<usize>
+}
+
+#[derive(Clone, Debug)]
+/// FIRST and FOLLOW sets and the LL(1) parse table of a grammar
+pub struct Ll1Table {
+    /// FIRST set of every nonterminal, not including the empty string
+    pub first: BTreeMap<String, BTreeSet<String>>,
+    /// FOLLOW set of every nonterminal, `END_OF_INPUT` marks the end of the input
+    pub follow: BTreeMap<String, BTreeSet<String>>,
+    /// Nonterminals which can derive the empty string
+    pub nullable: BTreeSet<String>,
+    /// nonterminal -> lookahead -> index of the alternative to expand, the first one where there is a conflict
+    pub table: BTreeMap<String, BTreeMap<String, usize>>,
+    pub conflicts: Vec<Ll1Conflict>,
+    /// Nonterminals which can derive themselves without a token in front, directly or through other nonterminals,
+    /// no recursive descent parser can expand them
+    pub left_recursive: BTreeSet<String>
+}
+
+impl Ll1Table {
+    /// Alternative of `nonterminal` to expand when the next token is `lookahead`
+    pub fn get(&self, nonterminal: &str, lookahead: &str) -> Option<usize> {
+        self.table.get(nonterminal)?.get(lookahead).copied()
+    }
+
+    /// Returns true if no conflicts were found, i.e. the grammar is LL(1)
+    pub fn is_ll1(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+impl Grammar {
+    /// Returns true if `symbol` is defined by a production rather than being a token type
+    pub fn is_nonterminal(&self, symbol: &str) -> bool {
+        self.productions.contains_key(symbol)
+    }
+
+    /// Every symbol used in a production which isn't a nonterminal
+    pub fn terminals(&self) -> BTreeSet<String> {
+        self.productions.values()
+            .flatten()
+            .flatten()
+            .filter(|s| !self.is_nonterminal(s))
+            .cloned()
+            .collect()
+    }
+
+    /// FIRST set of a sequence of symbols, and whether the whole sequence can derive the empty string
+    fn first_of(&self, symbols: &[String], first: &BTreeMap<String, BTreeSet<String>>, nullable: &BTreeSet<String>) -> (BTreeSet<String>, bool) {
+        let mut set = BTreeSet::new();
+        for symbol in symbols {
+            if !self.is_nonterminal(symbol) {
+                set.insert(symbol.clone());
+                return (set, false);
+            }
+            set.extend(first[symbol].iter().cloned());
+            if !nullable.contains(symbol) {
+                return (set, false);
+            }
+        }
+        (set, true)
+    }
+
+    /// Computes FIRST and FOLLOW sets and the LL(1) parse table, recording every cell with more than one alternative as a conflict
+    pub fn ll1_table(&self) -> Ll1Table {
+        let mut nullable: BTreeSet<String> = BTreeSet::new();
+        let mut first: BTreeMap<String, BTreeSet<String>> = self.productions.keys().map(|n| (n.clone(), BTreeSet::new())).collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (name, alternatives) in &self.productions {
+                for alternative in alternatives {
+                    let (set, empty) = self.first_of(alternative, &first, &nullable);
+                    let entry = first.get_mut(name).unwrap();
+                    let before = entry.len();
+                    entry.extend(set);
+                    changed |= entry.len() != before;
+                    if empty && nullable.insert(name.clone()) {
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let mut follow: BTreeMap<String, BTreeSet<String>> = self.productions.keys().map(|n| (n.clone(), BTreeSet::new())).collect();
+        if let Some(set) = follow.get_mut(&self.start) {
+            set.insert(String::from(END_OF_INPUT));
+        }
+        changed = true;
+        while changed {
+            changed = false;
+            for (name, alternatives) in &self.productions {
+                for alternative in alternatives {
+                    for (i, symbol) in alternative.iter().enumerate() {
+                        if !self.is_nonterminal(symbol) {
+                            continue;
+                        }
+                        let (mut set, empty) = self.first_of(&alternative[i + 1..], &first, &nullable);
+                        if empty {
+                            set.extend(follow[name].iter().cloned());
+                        }
+                        let entry = follow.get_mut(symbol).unwrap();
+                        let before = entry.len();
+                        entry.extend(set);
+                        changed |= entry.len() != before;
+                    }
+                }
+            }
+        }
+
+        let mut cells: BTreeMap<String, BTreeMap<String, Vec<usize>>> = BTreeMap::new();
+        for (name, alternatives) in &self.productions {
+            let row = cells.entry(name.clone()).or_default();
+            for (i, alternative) in alternatives.iter().enumerate() {
+                let (mut set, empty) = self.first_of(alternative, &first, &nullable);
+                if empty {
+                    set.extend(follow[name].iter().cloned());
+                }
+                for lookahead in set {
+                    row.entry(lookahead).or_default().push(i);
+                }
+            }
+        }
+
+        let mut conflicts = vec![];
+        let table = cells.into_iter().map(|(name, row)| {
+            let row = row.into_iter().map(|(lookahead, alternatives)| {
+                if alternatives.len() > 1 {
+                    conflicts.push(Ll1Conflict { nonterminal: name.clone(), lookahead: lookahead.clone(), productions: alternatives.clone() });
+                }
+                (lookahead, alternatives[0])
+            }).collect();
+            (name, row)
+        }).collect();
+
+        let left_recursive = self.left_recursive(&nullable);
+        Ll1Table { first, follow, nullable, table, conflicts, left_recursive }
+    }
+
+    /// Nonterminals reachable from themselves through the leftmost symbols of their alternatives, skipping nullable ones
+    fn left_recursive(&self, nullable: &BTreeSet<String>) -> BTreeSet<String> {
+        let leftmost: BTreeMap<&str, BTreeSet<&str>> = self.productions.iter().map(|(name, alternatives)| {
+            let mut set = BTreeSet::new();
+            for alternative in alternatives {
+                for symbol in alternative.iter().take_while(|s| self.is_nonterminal(s)) {
+                    set.insert(symbol.as_str());
+                    if !nullable.contains(symbol) {
+                        break;
+                    }
+                }
+            }
+            (name.as_str(), set)
+        }).collect();
+
+        self.productions.keys().filter(|name| {
+            let mut seen = BTreeSet::new();
+            let mut stack: Vec<&str> = leftmost[name.as_str()].iter().copied().collect();
+            while let Some(symbol) = stack.pop() {
+                if symbol == name.as_str() {
+                    return true;
+                }
+                if seen.insert(symbol) {
+                    stack.extend(leftmost[symbol].iter().copied());
+                }
+            }
+            false
+        }).cloned().collect()
+    }
+}
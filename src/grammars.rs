@@ -0,0 +1,85 @@
+//! Rule sets bundled with the crate, from the `grammars/` directory, as starting points for common formats
+//!
+//! Each call parses a fresh copy, which can be changed before building a lexer from it.
+
+use crate::RuleSet;
+
+fn load(json: &str) -> RuleSet {
+    RuleSet::load(json).unwrap()
+}
+
+/// JSON, strings are unescaped and stripped of their quotes
+///
+/// ```
+/// use lexer_generator::{assert_tokens, grammars};
+///
+/// assert_tokens!(grammars::json(), r#"{"a\n": [1.5, true]}"#, [
+///     ("lbrace", "{"), ("string", "a\n"), ("colon", ":"), ("lbracket", "["), ("number", "1.5"), ("comma", ","),
+///     ("true", "true"), ("rbracket", "]"), ("rbrace", "}")
+/// ]);
+/// ```
+pub fn json() -> RuleSet {
+    load(include_str!("../grammars/json.json"))
+}
+
+/// INI files, line breaks are `newline` tokens and `=` enters a `value` mode for the rest of the line, a line ending
+/// right after `=` has no `value` token
+///
+/// ```
+/// use lexer_generator::{assert_tokens, grammars};
+///
+/// assert_tokens!(grammars::ini(), "[server]\nhost = example.org ; production\nport=\n", [
+///     ("section", "server"), ("newline", "\n"), ("key", "host"), ("equals", "="), ("value", "example.org"),
+///     ("comment", "; production"), ("newline", "\n"), ("key", "port"), ("equals", "="), ("newline", "\n")
+/// ]);
+/// ```
+pub fn ini() -> RuleSet {
+    load(include_str!("../grammars/ini.json"))
+}
+
+/// CSV, whitespace belongs to the fields and line breaks are `newline` tokens, quoted fields are stripped of their
+/// quotes but doubled quotes inside them are kept
+///
+/// ```
+/// use lexer_generator::{assert_tokens, grammars};
+///
+/// assert_tokens!(grammars::csv(), "a b,\"c,d\"\n1,2", [
+///     ("field", "a b"), ("comma", ","), ("quoted_field", "c,d"), ("newline", "\n"),
+///     ("field", "1"), ("comma", ","), ("field", "2")
+/// ]);
+/// ```
+pub fn csv() -> RuleSet {
+    load(include_str!("../grammars/csv.json"))
+}
+
+/// Arithmetic with `+ - * / ^` and parentheses, operators are in the categories `additive`, `multiplicative` and
+/// `exponent`
+///
+/// ```
+/// use lexer_generator::{assert_tokens, grammars};
+///
+/// assert_tokens!(grammars::arithmetic(), "2 ^ (1.5 - 3)", [
+///     ("number", "2"), ("power", "^"), ("lparen", "("), ("number", "1.5"), ("subtract", "-"), ("number", "3"),
+///     ("rparen", ")")
+/// ]);
+/// ```
+pub fn arithmetic() -> RuleSet {
+    load(include_str!("../grammars/arithmetic.json"))
+}
+
+/// A small C-like language with keywords, comments, character literals and strings, which are lexed in a `string`
+/// mode as text and escapes between quotes, `///` and `/** */` comments are `doc_comment` tokens
+///
+/// ```
+/// use lexer_generator::{assert_tokens, grammars};
+///
+/// assert_tokens!(grammars::c(), "int x = 1; // one\nputs(\"a b\\n\");", [
+///     ("keyword", "int"), ("identifier", "x"), ("operator", "="), ("number", "1"), ("semicolon", ";"),
+///     ("line_comment", "// one"), ("identifier", "puts"), ("lparen", "("), ("quote", "\""), ("string_text", "a b"),
+///     ("escape", "\n"), ("end_quote", "\""), ("rparen", ")"), ("semicolon", ";")
+/// ]);
+/// assert_tokens!(grammars::c(), "/** Adds. */ /// Twice.\n", [("doc_comment", " Adds. "), ("doc_comment", " Twice.")]);
+/// ```
+pub fn c() -> RuleSet {
+    load(include_str!("../grammars/c.json"))
+}
@@ -0,0 +1,694 @@
+//! Converters from other lexer specification formats into a `RuleSet`
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{Conflict, RuleSet, ValidationWarning};
+
+#[derive(Clone, Debug)]
+/// A specification which couldn't be converted, `line` is zero-based like token lines
+pub struct ImportError {
+    pub line: usize,
+    pub message: String
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+fn error<T>(line: usize, message: impl ToString) -> Result<T, ImportError> {
+    Err(ImportError { line, message: message.to_string() })
+}
+
+impl RuleSet {
+    /// Converts the definitions and rules sections of a flex `.l` file
+    ///
+    /// A rule whose action contains `return NAME` becomes the rule `NAME`, several rules returning the same name are
+    /// joined into one alternation which tries them in order, where flex would take the longest of them, so more
+    /// specific patterns should come first. Rules whose action does nothing are treated as whitespace, other C actions are
+    /// ignored along with their rules, as are start conditions and `<<EOF>>` rules. flex prefers the earlier of two
+    /// rules matching the same text, so a conflict is declared wherever that decides between two converted rules.
+    pub fn from_flex(spec: &str) -> Result<RuleSet, ImportError> {
+        let mut definitions: HashMap<String, String> = HashMap::new();
+        let mut order: Vec<String> = vec![];
+        let mut patterns: HashMap<String, Vec<String>> = HashMap::new();
+        let mut whitespace: Vec<String> = vec![];
+        let mut section = 0;
+        let mut in_code = false;
+
+        for (n, line) in spec.lines().enumerate() {
+            let trimmed = line.trim();
+            if line.starts_with("%%") {
+                section += 1;
+                if section == 2 {
+                    break; // user code
+                }
+                continue;
+            }
+            if line.starts_with("%{") {
+                in_code = true;
+                continue;
+            }
+            if line.starts_with("%}") {
+                in_code = false;
+                continue;
+            }
+            if in_code || trimmed.is_empty() || line.starts_with(char::is_whitespace) || trimmed.starts_with("/*") {
+                continue;
+            }
+
+            if section == 0 {
+                if line.starts_with('%') {
+                    continue; // %option, %x, ...
+                }
+                let (name, definition) = match line.split_once(char::is_whitespace) {
+                    Some(parts) => parts,
+                    None => return error(n, format!("definition `{}` has no pattern", line))
+                };
+                let pattern = flex_pattern(definition.trim(), &definitions).or_else(|e| error(n, e))?;
+                definitions.insert(name.to_string(), pattern);
+            } else {
+                if line.starts_with('<') {
+                    continue; // start conditions and <<EOF>>
+                }
+                let (raw, action) = split_rule(line);
+                let pattern = flex_pattern(raw, &definitions).or_else(|e| error(n, e))?;
+                match returned_name(action) {
+                    Some(name) => {
+                        if !patterns.contains_key(&name) {
+                            order.push(name.clone());
+                        }
+                        patterns.entry(name).or_default().push(pattern);
+                    }
+                    None if is_empty_action(action) => whitespace.push(pattern),
+                    None => {}
+                }
+            }
+        }
+        if section == 0 {
+            return error(0, "missing %% before the rules section");
+        }
+
+        Ok(ordered_rules(patterns, &order, &whitespace))
+    }
+}
+
+/// Builds a rule set where ties are won by the rule declared first, like in flex and ANTLR
+fn ordered_rules(patterns: HashMap<String, Vec<String>>, order: &[String], whitespace: &[String]) -> RuleSet {
+    let mut rules = RuleSet {
+        version: Some(RuleSet::LATEST_VERSION),
+        literals: patterns.into_iter().map(|(name, p)| (name, alternation(&p).into())).collect::<BTreeMap<_, _>>(),
+        whitespace: if whitespace.is_empty() { None } else { Some(alternation(whitespace)) },
+        ..Default::default()
+    };
+    for warning in rules.validate() {
+        if let ValidationWarning::AmbiguousRules(a, b, _) = warning {
+            let (prefer, over) = if order.iter().position(|n| n == &a) < order.iter().position(|n| n == &b) { (a, b) } else { (b, a) };
+            rules.conflicts.push(Conflict { prefer, over, ignore_length: false });
+        }
+    }
+    rules
+}
+
+fn alternation(patterns: &[String]) -> String {
+    match patterns {
+        [single] => single.clone(),
+        _ => patterns.iter().map(|p| format!("(?:{})", p)).collect::<Vec<_>>().join("|")
+    }
+}
+
+/// Splits a rule line into its pattern and action, the pattern ends at the first whitespace outside quotes and classes
+fn split_rule(line: &str) -> (&str, &str) {
+    let mut in_quote = false;
+    let mut in_class = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '"' if !in_class => in_quote = !in_quote,
+            '[' if !in_quote => in_class = true,
+            ']' if !in_quote => in_class = false,
+            c if c.is_whitespace() && !in_quote && !in_class => return (&line[..i], line[i..].trim()),
+            _ => {}
+        }
+    }
+    (line, "")
+}
+
+fn returned_name(action: &str) -> Option<String> {
+    let start = action.find("return")? + "return".len();
+    let name: String = action[start..].trim_start().chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect();
+    if name.is_empty() { None } else { Some(name) }
+}
+
+fn is_empty_action(action: &str) -> bool {
+    let mut rest = action.to_string();
+    while let Some(start) = rest.find("/*") {
+        match rest[start..].find("*/") {
+            Some(end) => rest.replace_range(start..start + end + 2, ""),
+            None => rest.truncate(start)
+        }
+    }
+    rest.chars().all(|c| c.is_whitespace() || "{};".contains(c))
+}
+
+/// Translates a flex pattern into `regex` syntax, expanding `{name}` definitions and quoted strings
+fn flex_pattern(pattern: &str, definitions: &HashMap<String, String>) -> Result<String, String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '"' => {
+                let mut literal = String::new();
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' && i + 1 < chars.len() {
+                        i += 1;
+                        literal.push(unescape_c(chars[i]));
+                    } else {
+                        literal.push(chars[i]);
+                    }
+                    i += 1;
+                }
+                if i == chars.len() {
+                    return Err(format!("unterminated string in `{}`", pattern));
+                }
+                out.push_str(&regex::escape(&literal));
+            }
+            '{' if chars.get(i + 1).is_some_and(|c| c.is_alphabetic() || *c == '_') => {
+                let end = match chars[i..].iter().position(|c| *c == '}') {
+                    Some(end) => i + end,
+                    None => return Err(format!("unterminated definition reference in `{}`", pattern))
+                };
+                let name: String = chars[i + 1..end].iter().collect();
+                match definitions.get(&name) {
+                    Some(definition) => out.push_str(&format!("(?:{})", definition)),
+                    None => return Err(format!("undefined definition `{}`", name))
+                }
+                i = end;
+            }
+            '[' => {
+                // classes are compatible, copy up to the closing bracket
+                let start = i;
+                i += 1;
+                if chars.get(i) == Some(&'^') {
+                    i += 1;
+                }
+                if chars.get(i) == Some(&']') {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i] != ']' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    } else if chars[i] == '[' && chars.get(i + 1) == Some(&':') {
+                        // [:alpha:] and friends are supported by regex too
+                        if let Some(end) = chars[i..].windows(2).position(|w| w == [':', ']']) {
+                            i += end + 1;
+                        }
+                    }
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return Err(format!("unterminated class in `{}`", pattern));
+                }
+                out.extend(&chars[start..=i]);
+            }
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                let c = chars[i];
+                if c.is_alphanumeric() {
+                    out.push('\\');
+                    out.push(c);
+                } else {
+                    out.push_str(&regex::escape(&c.to_string()));
+                }
+            }
+            '/' => return Err(format!("trailing context isn't supported in `{}`", pattern)),
+            c => out.push(c)
+        }
+        i += 1;
+    }
+    Ok(out)
+}
+
+fn unescape_c(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        c => c
+    }
+}
+
+impl RuleSet {
+    /// Best-effort conversion of the lexer rules of an ANTLR `.g4` grammar
+    ///
+    /// String literals, `..` ranges, character sets, `~` negation of sets, `.`, the `* + ?` operators and references to
+    /// other rules (including fragments) are converted. Rules with `-> skip` or `-> channel(...)` become whitespace and
+    /// `-> type(NAME)` renames a rule. Everything else, like actions, predicates, modes and parser rules, is left out and
+    /// listed in the returned report, the error is only for grammars which can't be read at all.
+    pub fn from_antlr(grammar: &str) -> Result<(RuleSet, Vec<ImportError>), ImportError> {
+        let tokens = antlr_tokens(grammar)?;
+        let mut report = vec![];
+        let mut definitions: Vec<AntlrRule> = vec![];
+        let mut mode: Option<String> = None;
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let (line, tok) = (tokens[i].0, &tokens[i].1);
+            match tok {
+                AntlrTok::Ident(word) if matches!(word.as_str(), "lexer" | "parser" | "grammar" | "import") => {
+                    if word == "import" {
+                        report.push(ImportError { line, message: String::from("imported grammars aren't followed") });
+                    }
+                    i = skip_past(&tokens, i, ";");
+                }
+                AntlrTok::Ident(word) if word == "mode" => {
+                    mode = tokens.get(i + 1).map(|t| t.1.to_string());
+                    i = skip_past(&tokens, i, ";");
+                }
+                AntlrTok::Ident(word) if matches!(word.as_str(), "options" | "channels" | "tokens") => i += 2,
+                AntlrTok::Punct("@") => i += 3, // @header { ... }
+                AntlrTok::Ident(_) => {
+                    let fragment = tok.is("fragment");
+                    let start = if fragment { i + 1 } else { i };
+                    let name = match tokens.get(start) {
+                        Some(t) => t.1.to_string(),
+                        None => return error(line, "unexpected end of input after `fragment`")
+                    };
+                    if !tokens.get(start + 1).is_some_and(|t| t.1.is(":")) {
+                        return error(line, format!("expected `:` after `{}`", name));
+                    }
+                    let end = skip_past(&tokens, start, ";");
+                    if !tokens[end - 1].1.is(";") {
+                        return error(line, format!("rule `{}` has no `;` at its end", name));
+                    }
+                    let body = &tokens[start + 2..end - 1];
+                    i = end;
+                    if !name.starts_with(char::is_uppercase) {
+                        report.push(ImportError { line, message: format!("parser rule `{}` skipped", name) });
+                        continue;
+                    }
+                    if let Some(mode) = &mode {
+                        report.push(ImportError { line, message: format!("rule `{}` in mode `{}` skipped", name, mode) });
+                        continue;
+                    }
+                    let arrow = body.iter().position(|t| t.1.is("->")).unwrap_or(body.len());
+                    let mut parser = AntlrParser { tokens: &body[..arrow], pos: 0, report: &mut report };
+                    let node = parser.alternatives();
+                    if let Some((line, tok)) = body[..arrow].get(parser.pos) {
+                        parser.report.push(ImportError { line: *line, message: format!("unexpected `{}` in `{}`, the rest of the rule dropped", tok, name) });
+                    }
+                    let commands = body.get(arrow + 1..).unwrap_or(&[]).split(|t| t.1.is(",")).map(|c| c.to_vec()).collect();
+                    definitions.push(AntlrRule { name, line, fragment, node, commands });
+                }
+                _ => return error(line, format!("unexpected `{}`", tok))
+            }
+        }
+
+        let mut order = vec![];
+        let mut patterns: HashMap<String, Vec<String>> = HashMap::new();
+        let mut whitespace = vec![];
+        for rule in definitions.iter().filter(|r| !r.fragment) {
+            let pattern = match antlr_regex(&rule.node, &definitions, &mut vec![rule.name.clone()]) {
+                Ok(p) if p.is_empty() => {
+                    report.push(ImportError { line: rule.line, message: format!("rule `{}` skipped: nothing of it was converted", rule.name) });
+                    continue;
+                }
+                Ok(p) => p,
+                Err(message) => {
+                    report.push(ImportError { line: rule.line, message: format!("rule `{}` skipped: {}", rule.name, message) });
+                    continue;
+                }
+            };
+            let mut name = rule.name.clone();
+            let mut skipped = false;
+            for command in &rule.commands {
+                match command.first().map(|t| t.1.to_string()).as_deref() {
+                    None => {}
+                    Some("skip") | Some("channel") => skipped = true,
+                    Some("type") => if let Some(t) = command.get(2) { name = t.1.to_string() },
+                    Some(other) => report.push(ImportError { line: rule.line, message: format!("command `{}` of `{}` ignored", other, rule.name) })
+                }
+            }
+            if skipped {
+                whitespace.push(pattern);
+            } else {
+                if !patterns.contains_key(&name) {
+                    order.push(name.clone());
+                }
+                patterns.entry(name).or_default().push(pattern);
+            }
+        }
+        Ok((ordered_rules(patterns, &order, &whitespace), report))
+    }
+}
+
+struct AntlrRule {
+    name: String,
+    line: usize,
+    fragment: bool,
+    node: AntlrNode,
+    commands: Vec<Vec<(usize, AntlrTok)>>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum AntlrTok {
+    Ident(String),
+    /// Unescaped contents of a 'literal'
+    Str(String),
+    /// Ranges of a [set]
+    Set(Vec<(char, char)>),
+    Punct(&'static str),
+    Action
+}
+
+impl AntlrTok {
+    fn is(&self, text: &str) -> bool {
+        match self {
+            AntlrTok::Ident(word) => word == text,
+            AntlrTok::Punct(p) => *p == text,
+            _ => false
+        }
+    }
+}
+
+impl std::fmt::Display for AntlrTok {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AntlrTok::Ident(word) => write!(f, "{}", word),
+            AntlrTok::Str(s) => write!(f, "'{}'", s),
+            AntlrTok::Set(_) => write!(f, "[...]"),
+            AntlrTok::Punct(p) => write!(f, "{}", p),
+            AntlrTok::Action => write!(f, "{{...}}")
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum AntlrNode {
+    Seq(Vec<AntlrNode>),
+    Alt(Vec<AntlrNode>),
+    Lit(String),
+    Set(Vec<(char, char)>, bool),
+    Any,
+    Ref(String),
+    Repeat(Box<AntlrNode>, &'static str)
+}
+
+fn skip_past(tokens: &[(usize, AntlrTok)], from: usize, punct: &str) -> usize {
+    tokens[from..].iter().position(|t| t.1.is(punct)).map_or(tokens.len(), |p| from + p + 1)
+}
+
+fn antlr_tokens(grammar: &str) -> Result<Vec<(usize, AntlrTok)>, ImportError> {
+    const PUNCTS: [&str; 15] = ["->", "..", ":", ";", "|", "(", ")", "*", "+", "?", "~", ".", ",", "@", "#"];
+    let chars: Vec<char> = grammar.chars().collect();
+    let mut tokens = vec![];
+    let mut line = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if rest == "//" {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if rest == "/*" {
+            let start = line;
+            i += 2;
+            while i + 1 < chars.len() && !(chars[i] == '*' && chars[i + 1] == '/') {
+                line += (chars[i] == '\n') as usize;
+                i += 1;
+            }
+            if i + 1 >= chars.len() {
+                return error(start, "unterminated comment");
+            }
+            i += 2;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((line, AntlrTok::Ident(chars[start..i].iter().collect())));
+        } else if c == '\'' {
+            let mut literal = String::new();
+            i += 1;
+            while i < chars.len() && chars[i] != '\'' {
+                literal.push(antlr_char(&chars, &mut i).ok_or(ImportError { line, message: String::from("bad escape") })?);
+            }
+            if i == chars.len() {
+                return error(line, "unterminated string literal");
+            }
+            i += 1;
+            tokens.push((line, AntlrTok::Str(literal)));
+        } else if c == '[' {
+            let mut ranges = vec![];
+            i += 1;
+            while i < chars.len() && chars[i] != ']' {
+                let lo = antlr_char(&chars, &mut i).ok_or(ImportError { line, message: String::from("bad escape") })?;
+                if chars.get(i) == Some(&'-') && chars.get(i + 1).is_some_and(|c| *c != ']') {
+                    i += 1;
+                    let hi = antlr_char(&chars, &mut i).ok_or(ImportError { line, message: String::from("bad escape") })?;
+                    ranges.push((lo, hi));
+                } else {
+                    ranges.push((lo, lo));
+                }
+            }
+            if i == chars.len() {
+                return error(line, "unterminated character set");
+            }
+            i += 1;
+            tokens.push((line, AntlrTok::Set(ranges)));
+        } else if c == '{' {
+            let start = line;
+            let mut depth = 0;
+            while i < chars.len() {
+                match chars[i] {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    '\n' => line += 1,
+                    _ => {}
+                }
+                i += 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            if depth > 0 {
+                return error(start, "unterminated action");
+            }
+            if chars.get(i) == Some(&'?') {
+                i += 1; // predicate
+            }
+            tokens.push((line, AntlrTok::Action));
+        } else if let Some(p) = PUNCTS.iter().find(|p| rest.starts_with(**p)) {
+            i += p.len();
+            tokens.push((line, AntlrTok::Punct(p)));
+        } else {
+            return error(line, format!("unexpected character `{}`", c));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads one possibly escaped character of a literal or set
+fn antlr_char(chars: &[char], i: &mut usize) -> Option<char> {
+    let c = chars[*i];
+    *i += 1;
+    if c != '\\' {
+        return Some(c);
+    }
+    let escaped = *chars.get(*i)?;
+    *i += 1;
+    Some(match escaped {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        'b' => '\u{8}',
+        'f' => '\u{c}',
+        'u' => {
+            let braced = chars.get(*i) == Some(&'{');
+            let start = if braced { *i + 1 } else { *i };
+            let end = if braced { start + chars[start..].iter().position(|c| *c == '}')? } else { (start + 4).min(chars.len()) };
+            let code: String = chars[start..end].iter().collect();
+            *i = if braced { end + 1 } else { end };
+            char::from_u32(u32::from_str_radix(&code, 16).ok()?)?
+        }
+        c => c
+    })
+}
+
+struct AntlrParser<'a> {
+    tokens: &'a [(usize, AntlrTok)],
+    pos: usize,
+    report: &'a mut Vec<ImportError>
+}
+
+impl AntlrParser<'_> {
+    fn peek(&self) -> Option<&AntlrTok> {
+        self.tokens.get(self.pos).map(|t| &t.1)
+    }
+
+    fn alternatives(&mut self) -> AntlrNode {
+        let mut alts = vec![self.sequence()];
+        while self.peek().is_some_and(|t| t.is("|")) {
+            self.pos += 1;
+            alts.push(self.sequence());
+        }
+        if alts.len() == 1 { alts.pop().unwrap() } else { AntlrNode::Alt(alts) }
+    }
+
+    fn sequence(&mut self) -> AntlrNode {
+        let mut items = vec![];
+        while let Some(tok) = self.peek() {
+            if tok.is("|") || tok.is(")") {
+                break;
+            }
+            if let Some(mut item) = self.atom() {
+                while let Some(AntlrTok::Punct(op @ ("*" | "+" | "?"))) = self.peek() {
+                    let mut op: &'static str = op;
+                    self.pos += 1;
+                    if self.peek().is_some_and(|t| t.is("?")) {
+                        self.pos += 1;
+                        op = match op { "*" => "*?", "+" => "+?", _ => "??" };
+                    }
+                    item = AntlrNode::Repeat(Box::new(item), op);
+                }
+                items.push(item);
+            }
+        }
+        if items.len() == 1 { items.pop().unwrap() } else { AntlrNode::Seq(items) }
+    }
+
+    fn atom(&mut self) -> Option<AntlrNode> {
+        let (line, tok) = self.tokens.get(self.pos)?.clone();
+        self.pos += 1;
+        match tok {
+            AntlrTok::Str(lo) => {
+                if self.peek().is_some_and(|t| t.is("..")) {
+                    if let Some((_, AntlrTok::Str(hi))) = self.tokens.get(self.pos + 1).cloned() {
+                        self.pos += 2;
+                        if let (Some(lo), Some(hi)) = (single(&lo), single(&hi)) {
+                            return Some(AntlrNode::Set(vec![(lo, hi)], false));
+                        }
+                    }
+                }
+                Some(AntlrNode::Lit(lo))
+            }
+            AntlrTok::Set(ranges) => Some(AntlrNode::Set(ranges, false)),
+            AntlrTok::Punct(".") => Some(AntlrNode::Any),
+            AntlrTok::Ident(name) => Some(AntlrNode::Ref(name)),
+            AntlrTok::Punct("(") => {
+                let inner = self.alternatives();
+                if self.peek().is_none() {
+                    self.report.push(ImportError { line, message: String::from("`(` never closed, the group is dropped") });
+                    return None;
+                }
+                self.pos += 1; // )
+                Some(inner)
+            }
+            AntlrTok::Punct("~") => {
+                if self.peek().is_none() {
+                    self.report.push(ImportError { line, message: String::from("`~` with nothing after it dropped") });
+                    return None;
+                }
+                let inner = self.atom()?;
+                match negatable(&inner) {
+                    Some(ranges) => Some(AntlrNode::Set(ranges, true)),
+                    None => {
+                        self.report.push(ImportError { line, message: String::from("`~` only converts for sets of single characters") });
+                        None
+                    }
+                }
+            }
+            AntlrTok::Action => {
+                self.report.push(ImportError { line, message: String::from("action or predicate dropped") });
+                None
+            }
+            other => {
+                self.report.push(ImportError { line, message: format!("`{}` dropped", other) });
+                None
+            }
+        }
+    }
+}
+
+fn single(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None
+    }
+}
+
+/// Ranges of a node which is a set of single characters, the only thing `~` can be applied to
+fn negatable(node: &AntlrNode) -> Option<Vec<(char, char)>> {
+    match node {
+        AntlrNode::Set(ranges, false) => Some(ranges.clone()),
+        AntlrNode::Lit(s) => single(s).map(|c| vec![(c, c)]),
+        AntlrNode::Alt(alts) => {
+            let mut ranges = vec![];
+            for alt in alts {
+                ranges.extend(negatable(alt)?);
+            }
+            Some(ranges)
+        }
+        _ => None
+    }
+}
+
+fn antlr_regex(node: &AntlrNode, rules: &[AntlrRule], stack: &mut Vec<String>) -> Result<String, String> {
+    Ok(match node {
+        AntlrNode::Seq(items) => {
+            let mut out = String::new();
+            for item in items {
+                out.push_str(&antlr_regex(item, rules, stack)?);
+            }
+            out
+        }
+        AntlrNode::Alt(alts) => {
+            let mut out = vec![];
+            for alt in alts {
+                out.push(antlr_regex(alt, rules, stack)?);
+            }
+            format!("(?:{})", out.join("|"))
+        }
+        AntlrNode::Lit(s) => regex::escape(s),
+        AntlrNode::Set(ranges, negated) => {
+            let mut out = String::from(if *negated { "[^" } else { "[" });
+            for (lo, hi) in ranges {
+                out.push_str(&regex::escape(&lo.to_string()));
+                if lo != hi {
+                    out.push('-');
+                    out.push_str(&regex::escape(&hi.to_string()));
+                }
+            }
+            out.push(']');
+            out
+        }
+        AntlrNode::Any => String::from("(?s:.)"),
+        AntlrNode::Repeat(inner, op) => format!("(?:{}){}", antlr_regex(inner, rules, stack)?, op),
+        AntlrNode::Ref(name) => {
+            if stack.contains(name) {
+                return Err(format!("`{}` is recursive", name));
+            }
+            let rule = rules.iter().find(|r| &r.name == name).ok_or(format!("`{}` isn't defined", name))?;
+            stack.push(name.clone());
+            let inner = antlr_regex(&rule.node, rules, stack)?;
+            stack.pop();
+            format!("(?:{})", inner)
+        }
+    })
+}
@@ -0,0 +1,102 @@
+//! Lexing the regions of a host document written in an embedded language, passing the rest through
+
+use regex::Regex;
+
+use std::sync::Arc;
+
+use crate::fenced::find_blocks;
+use crate::{CompiledRules, Lexer, LexerState, ParsingError, Token, TokenOrigin};
+
+/// Lexes only the islands of a host document, the text between a match of a start pattern and the next match of an
+/// end pattern, like SQL inside strings or directives inside HTML comments
+///
+/// The text around the islands, including the delimiters, becomes one token of the host type for every stretch,
+/// `"raw"` unless set with `with_host_token`, or is skipped with `skip_host`. Positions are those of the whole
+/// document, an island which is never closed runs to its end.
+///
+/// ```
+/// use lexer_generator::{CompiledRules, Islands};
+/// use std::sync::Arc;
+///
+/// let rules = Arc::new(CompiledRules::from_json(r#"{ "literals": { "name": "[a-z]+", "number": "[0-9]+" } }"#).unwrap());
+/// let islands = Islands::new("<!--@", "-->", rules).unwrap();
+/// let tokens = islands.lex("<p>text</p><!--@ include 3 --><p>more</p>").unwrap();
+/// let types: Vec<_> = tokens.iter().map(|token| (token.token_type.as_str(), token.raw.as_str())).collect();
+/// assert_eq!(types, [("raw", "<p>text</p><!--@"), ("name", "include"), ("number", "3"), ("raw", "--><p>more</p>")]);
+/// ```
+#[derive(Clone)]
+pub struct Islands {
+    open: Regex,
+    close: Regex,
+    rules: Arc<CompiledRules>,
+    host: Option<String>
+}
+
+impl Islands {
+    /// Islands between matches of the `open` and `close` patterns, lexed with `rules`
+    pub fn new(open: &str, close: &str, rules: Arc<CompiledRules>) -> Result<Self, regex::Error> {
+        Ok(Self { open: Regex::new(open)?, close: Regex::new(close)?, rules, host: Some(String::from("raw")) })
+    }
+
+    /// Type of the tokens of the text outside the islands
+    pub fn with_host_token(mut self, token_type: &str) -> Self {
+        self.host = Some(token_type.to_string());
+        self
+    }
+
+    /// Drops the text outside the islands instead of making tokens of it
+    pub fn skip_host(mut self) -> Self {
+        self.host = None;
+        self
+    }
+
+    /// The tokens of the document in order, stopping at the first error of an island
+    ///
+    /// Every island starts in the main mode with nothing open, like a source of its own.
+    pub fn lex(&self, document: &str) -> Result<Vec<Token>, ParsingError> {
+        let mut lexer = Lexer::from_compiled(self.rules.clone(), document.to_string());
+        if let Some(host) = &self.host {
+            lexer.add_type(host);
+        }
+        let fresh = lexer.checkpoint();
+        let mut tokens = vec![];
+        let mut pos = 0;
+        for block in find_blocks(document, &self.open, &self.close) {
+            lexer = self.host_token(lexer, &fresh, pos, block.range.start, &mut tokens);
+            lexer.restore(fresh.clone());
+            lexer = lexer.with_range(block.range.clone());
+            for token in lexer.by_ref() {
+                tokens.push(token?);
+            }
+            pos = block.range.end;
+        }
+        self.host_token(lexer, &fresh, pos, document.len(), &mut tokens);
+        Ok(tokens)
+    }
+
+    /// Adds the token of the host text from `start` to `end` unless it is empty or skipped
+    fn host_token(&self, mut lexer: Lexer, fresh: &LexerState, start: usize, end: usize, tokens: &mut Vec<Token>) -> Lexer {
+        let host = match &self.host {
+            Some(host) if start < end => host,
+            _ => return lexer
+        };
+        lexer.restore(fresh.clone());
+        let lexer = lexer.with_range(start..end);
+        let location = lexer.location();
+        let raw = lexer.source[start..end].to_string();
+        tokens.push(Token {
+            token_type: host.clone(),
+            type_id: lexer.rules.ids[host.as_str()],
+            value: raw.clone(),
+            raw,
+            line: location.line,
+            column: location.column,
+            span: start..end,
+            source_name: None,
+            category: None,
+            sub_kind: None,
+            origin: TokenOrigin::Source
+        });
+        lexer
+    }
+}
@@ -0,0 +1,57 @@
+//! External lexer for LALRPOP parsers, only built with the `lalrpop` feature
+
+use crate::{Lexer, ParsingError, Token};
+
+/// A lexer yielding the `(start, token, end)` triples of a LALRPOP external lexer, with byte offsets as locations
+/// and `ParsingError` as the error type
+///
+/// LALRPOP matches terminals with Rust patterns, which can't match a `String`, so `map` turns each token into the
+/// grammar's own token type, typically an enum. See `examples/lalrpop/calculator.lalrpop` for a whole grammar.
+///
+/// ```
+/// use lexer_generator::{grammars, LalrpopLexer, Lexer, Token};
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Tok { Number(f64), Add, Other(String) }
+///
+/// let lexer = Lexer::from_rules(grammars::arithmetic(), String::from("1 + 2"));
+/// let tokens: Vec<_> = LalrpopLexer::new(lexer, |token: Token| match token.token_type.as_str() {
+///     "number" => Tok::Number(token.value.parse().unwrap()),
+///     "add" => Tok::Add,
+///     _ => Tok::Other(token.token_type)
+/// }).map(Result::unwrap).collect();
+/// assert_eq!(tokens, [(0, Tok::Number(1.0), 1), (2, Tok::Add, 3), (4, Tok::Number(2.0), 5)]);
+/// ```
+pub struct LalrpopLexer<F> {
+    lexer: Lexer,
+    map: F
+}
+
+impl<T, F: FnMut(Token) -> T> LalrpopLexer<F> {
+    pub fn new(lexer: Lexer, map: F) -> Self {
+        Self { lexer, map }
+    }
+
+    /// The lexer, e.g. for its warnings once parsing is done
+    pub fn lexer(&self) -> &Lexer {
+        &self.lexer
+    }
+
+    pub fn into_lexer(self) -> Lexer {
+        self.lexer
+    }
+}
+
+impl<T, F: FnMut(Token) -> T> Iterator for LalrpopLexer<F> {
+    type Item = Result<(usize, T, usize), ParsingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.lexer.next()?;
+        Some(token.map(|token| {
+            let (start, end) = (token.span.start, token.span.end);
+            (start, (self.map)(token), end)
+        }))
+    }
+}
+
+impl<T, F: FnMut(Token) -> T> std::iter::FusedIterator for LalrpopLexer<F> {}
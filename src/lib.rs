@@ -7,7 +7,7 @@
 //! Potential code one might use to lex tokens for a calculator
 //! 
 //! ```key.json```:
-//! ```
+//! ```json
 //! {
 //!     "literals": {
 //!         "number": "[0-9]*(\\.[0-9]*){0, 1}",
@@ -20,7 +20,9 @@
 //! }
 //! ```
 //! ```main.rs```:
-//! ```
+//! ```no_run
+//! use lexer_generator::Lexer;
+//!
 //! let json: String = std::fs::read_to_string("key.json").unwrap();
 //! let source: String = String::from("123 + 456 * 789");
 //! 
@@ -28,184 +30,3550 @@
 //! // parsing, runtime, whatever one would want to do with their tokens
 //! ```
 //! 
-//! ```
+//! ```text
 //! "123 + 456 * 789" -> Token("number", "123"), Token("add", "*"), Token("number", "456"), Token("multiply", "*"), Token("number", "789") // ignoring line position and the incremental nature of the lexer
 //! ```
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use regex::*;
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
+
+#[cfg(feature = "postcard")]
+mod cache;
+#[cfg(feature = "chumsky")]
+mod chumsky_stream;
+mod cursor;
+mod diff;
+mod export;
+mod fenced;
+mod formatter;
+mod grammar;
+pub mod grammars;
+mod import;
+mod islands;
+mod loader;
+#[cfg(feature = "lalrpop")]
+mod lalrpop_lexer;
+#[cfg(feature = "nom")]
+mod nom_tokens;
+mod parser;
+#[cfg(feature = "notify")]
+mod reload;
+mod rewrite;
+#[cfg(feature = "sample")]
+mod sample;
+mod scanner;
+mod schema;
+pub mod preprocess;
+pub mod testing;
+#[cfg(feature = "futures")]
+mod stream;
+mod token_tree;
+#[cfg(feature = "postcard")]
+pub use cache::{decode_tokens, encode_tokens, load_cached_tokens, save_cached_tokens, CacheError};
+#[cfg(feature = "chumsky")]
+pub use chumsky_stream::chumsky_stream;
+pub use cursor::{CursorPos, TokenCursor};
+pub use diff::{TokenChange, TokenDiff};
+pub use fenced::{fenced_blocks, FencedBlock};
+pub use formatter::{FormatRules, Formatter};
+pub use grammar::{Grammar, Ll1Conflict, Ll1Table, END_OF_INPUT};
+pub use import::ImportError;
+pub use islands::Islands;
+pub use loader::RuleSetLoader;
+#[cfg(feature = "lalrpop")]
+pub use lalrpop_lexer::LalrpopLexer;
+#[cfg(feature = "nom")]
+pub use nom_tokens::{token_kind, TokenSlice};
+pub use parser::{Cst, ParseError, Parser};
+#[cfg(feature = "notify")]
+pub use reload::{ReloadError, RulesWatcher};
+pub use rewrite::{RewriteError, Rewriter};
+#[cfg(feature = "sample")]
+pub use sample::Sampler;
+pub use scanner::{RuleMatcher, Scanner};
+pub use schema::{validate_json, SchemaError};
+#[cfg(feature = "futures")]
+pub use stream::{LexError, TokenStream};
+pub use token_tree::{token_trees, Delimiter, TokenTree};
+
+/// Debug event about a matching decision, only emitted with the `log` feature
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::debug!(target: "lexer_generator", $($arg)*);
+    };
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+/// Rule set parsed from JSON, before any patterns are compiled
+pub struct RuleSet {
+    /// Schema version the rule set is written for, 1 if missing, see `RuleSet::load`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<u32>,
+    literals: BTreeMap<String, Rule>,
+    /// Pattern skipped between tokens, `\s+` if missing, `null` skips nothing
+    #[serde(default = "default_whitespace")]
+    whitespace: Option<String>,
+    /// Emit whitespace as tokens of this type instead of skipping it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    whitespace_token: Option<String>,
+    /// Where whitespace and a rule both match, lex the rule instead of skipping the whitespace, only checked where a
+    /// whitespace match would start
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    prefer_rules_over_whitespace: bool,
+    /// Modes with their own rules and whitespace, entered and left by rules with `push` and `pop`, the top level
+    /// `literals` and `whitespace` are the `main` mode
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    modes: BTreeMap<String, Mode>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    conflicts: Vec<Conflict>,
+    /// (opener, closer) token types which have to be balanced, like `["lparen", "rparen"]`, see `Lexer::depth`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pairs: Vec<(String, String)>,
+    /// Terminators inserted at line ends, like Go's semicolons
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    asi: Option<Asi>,
+    /// Sequences of tokens joined into one token after lexing, tried in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    merge: Vec<Merge>,
+    /// Token types whose runs are joined into one token, like consecutive newlines, before merging
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    coalesce: Vec<String>,
+    /// Spacing rules of the `Formatter`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format: Option<FormatRules>,
+    /// Pattern of `#line` style directives, with a `line` group and an optional `file` group
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    line_directive: Option<String>,
+    /// Skip a UTF-8 byte order mark at the start of the source
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    skip_bom: bool,
+    /// Skip a `#!` line at the start of the source
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    skip_shebang: bool,
+    /// Most bytes `Lexer::push_str` holds back for a token which may go on in the next chunk, more is a
+    /// `ParsingError::TokenTooLong`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_token_bytes: Option<usize>,
+    /// Most errors the lexer reports, the next one ends lexing with a `ParsingError::TooManyErrors`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_errors: Option<usize>,
+    /// How much input is discarded after text no rule matches
+    #[serde(default, skip_serializing_if = "Recover::is_default")]
+    recover: Recover,
+    /// Compiled size in bytes above which `validate` reports a rule's pattern as too large, `DEFAULT_MAX_PATTERN_SIZE`
+    /// if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_pattern_size: Option<usize>,
+    /// Productions over the token types, for building parse tables
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    grammar: Option<Grammar>
+}
+
+fn default_whitespace() -> Option<String> {
+    Some(String::from("\\s+"))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A rule as `RuleSet::rules` lists it
+pub struct RuleInfo<'a> {
+    /// Mode the rule is lexed in, `MAIN_MODE` for the top level `literals`
+    pub mode: &'a str,
+    pub name: &'a str,
+    /// The rule as written, with its options
+    pub rule: &'a Rule,
+    /// The regex the rule matches with, empty for native rules
+    pub pattern: String,
+    /// The only text the pattern matches, like `if` or `+=`, if it matches one
+    pub fixed: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+/// How much input the lexer discards after a `ParsingError::UnrecognizedPatternError` or a
+/// `ParsingError::TokenTooLong`, the `"recover"` of a rule set
+pub enum Recover {
+    /// The character no rule matched, or the first `max_token_bytes` of a token too long
+    #[default]
+    NextChar,
+    /// The rest of the line, for formats like logs which are best resynchronized at line breaks, the line break is
+    /// then lexed as usual
+    NextLine,
+    /// Nothing, lexing ends at the error and every later token is `EndOfFileError`
+    None
+}
+
+impl Recover {
+    fn is_default(&self) -> bool {
+        *self == Recover::default()
+    }
+}
+
+/// Name of the mode made of the top level `literals` and `whitespace`, the lexer starts in it
+pub const MAIN_MODE: &str = "main";
+
+/// Type of the tokens `Lexer::tokenize_all_errors` puts where the source couldn't be lexed
+pub const ERROR_TOKEN: &str = "error";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+/// A lexing mode, like the inside of a string literal where other rules apply and whitespace is part of the text
+///
+/// ```json
+/// "literals": { "quote": { "literal": "\"", "push": "string" }, ... },
+/// "modes": {
+///     "string": {
+///         "literals": { "text": "[^\"\\\\]+", "escape": "\\\\.", "end_quote": { "literal": "\"", "pop": true } },
+///         "whitespace": null
+///     }
+/// }
+/// ```
+///
+/// Conflicts apply in every mode, token types are shared, so a name used in several modes is the same type
+pub struct Mode {
+    pub literals: BTreeMap<String, Rule>,
+    /// Pattern skipped between tokens in this mode, `\s+` if missing, `null` skips nothing
+    #[serde(default = "default_whitespace")]
+    pub whitespace: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+/// Explicit resolution between two rules, `{ "prefer": "a", "over": "b" }`
+///
+/// By default the preference only applies when both rules match the same span, `"ignore_length": true`
+/// makes `prefer` win whenever both rules match, even if `over` would match more text
+///
+/// Of the rules matching at a position, the longest match wins unless a conflict decides otherwise, and of equally
+/// long matches nothing decides between, the rule whose name sorts first, so the same rules always lex the same way:
+///
+/// ```
+/// use lexer_generator::assert_tokens;
+///
+/// let rules = r#"{ "literals": { "word": "[a-z]+", "keyword": "if|else" } }"#;
+/// assert_tokens!(rules, "if iffy", [("keyword", "if"), ("word", "iffy")]);
+/// ```
+pub struct Conflict {
+    pub prefer: String,
+    pub over: String,
+    #[serde(default)]
+    pub ignore_length: bool
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+/// Automatic semicolon insertion, `{ "after": ["identifier", "number", "rparen"], "emit": "semicolon" }`
+///
+/// When a line ends right after a token of one of the `after` types, an empty `emit` token is inserted after it, also
+/// at the end of the source. Only line breaks in skipped whitespace count, not those in tokens like comments or
+/// `whitespace_token` tokens.
+pub struct Asi {
+    pub after: Vec<String>,
+    pub emit: String
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+/// Tokens joined after lexing, `{ "seq": ["gt", "gt"], "into": "shr", "adjacent_only": true }`
+///
+/// The merged token spans from the first token to the last, its value is their values joined. With `adjacent_only`
+/// the tokens may not have anything between them, so `> >` stays two tokens.
+pub struct Merge {
+    pub seq: Vec<String>,
+    pub into: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub adjacent_only: bool
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+/// Expressions embedded in a string, `{ "open": "\\$\\{", "close": "\\}" }` on the rule which pushes the string's mode
+///
+/// While that mode is the current one, a match of `open` is lexed as an `open_token` before any other rule and enters
+/// `mode` for the expression. A match of `close` ends the expression with a `close_token`, unless a delimiter of
+/// `"pairs"` opened inside the expression is still open, and lexing goes on in the string's mode.
+pub struct Interpolate {
+    pub open: String,
+    pub close: String,
+    /// Mode of the embedded expressions, the main mode if missing
+    #[serde(default = "default_interpolation_mode")]
+    pub mode: String,
+    #[serde(default = "default_open_token")]
+    pub open_token: String,
+    #[serde(default = "default_close_token")]
+    pub close_token: String
+}
+
+fn default_interpolation_mode() -> String {
+    String::from(MAIN_MODE)
+}
+
+fn default_open_token() -> String {
+    String::from("interpolation_open")
+}
+
+fn default_close_token() -> String {
+    String::from("interpolation_close")
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+/// Doc comments among a comment rule's tokens, `{ "prefix": "/**", "suffix": "*/" }`
+///
+/// Tokens of the rule starting with `prefix` and ending with `suffix` are `token` tokens instead, with the markers
+/// stripped from their value, so doc extraction tools can tell them from other comments
+pub struct DocComment {
+    pub prefix: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub suffix: String,
+    #[serde(default = "default_doc_token")]
+    pub token: String
+}
+
+fn default_doc_token() -> String {
+    String::from("doc_comment")
+}
+
+impl DocComment {
+    /// The text between the markers if `raw` is a doc comment, a comment which is only the markers, like `/**/`, isn't
+    fn strip<'a>(&self, raw: &'a str) -> Option<&'a str> {
+        raw.strip_prefix(self.prefix.as_str())?.strip_suffix(self.suffix.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+/// Format of numeric literals, `{ "radixes": ["hex", "bin"], "separator": "_", "float": true, "exponent": true }`
+///
+/// Decimal integers are always allowed. Tokens of the rule have the sub-kind `int`, `int_hex`, `int_oct`, `int_bin`
+/// or `float`
+pub struct NumberFormat {
+    /// Prefixed integers besides decimal ones, `hex` for `0x1f`, `oct` for `0o17` and `bin` for `0b101`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub radixes: Vec<String>,
+    /// Separator allowed between digits, like `_` in `1_000`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub separator: Option<String>,
+    /// Decimal fractions, `1.5`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub float: bool,
+    /// Decimal exponents, `1e10` and `1.5E-3` with `float`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exponent: bool,
+    /// Type suffixes allowed after any literal, like `u8` or `f64`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suffixes: Vec<String>
+}
+
+impl NumberFormat {
+    /// Sub-kinds in the order they are tried, each is a group of `pattern`
+    const SUB_KINDS: [&'static str; 5] = ["int_hex", "int_oct", "int_bin", "float", "int"];
+
+    /// The format as a regex with a named group for every sub-kind
+    pub fn pattern(&self) -> String {
+        let digits = |class: &str| match &self.separator {
+            Some(separator) => format!("{c}(?:(?:{s})?{c})*", c = class, s = regex::escape(separator)),
+            None => format!("{}+", class)
+        };
+        let decimal = digits("[0-9]");
+        let exponent = format!("[eE][+-]?{}", decimal);
+        let mut kinds = vec![];
+        for (radix, kind, prefix, class) in [("hex", "int_hex", "0[xX]", "[0-9a-fA-F]"), ("oct", "int_oct", "0[oO]", "[0-7]"), ("bin", "int_bin", "0[bB]", "[01]")] {
+            if self.radixes.iter().any(|r| r == radix) {
+                kinds.push(format!("(?P<{}>{}{})", kind, prefix, digits(class)));
+            }
+        }
+        let fraction = format!("{d}\\.{d}", d = decimal);
+        let float = match (self.float, self.exponent) {
+            (true, true) => Some(format!("{f}(?:{e})?|{d}{e}", f = fraction, e = exponent, d = decimal)),
+            (true, false) => Some(fraction),
+            (false, true) => Some(format!("{}{}", decimal, exponent)),
+            (false, false) => None
+        };
+        if let Some(float) = float {
+            kinds.push(format!("(?P<float>{})", float));
+        }
+        kinds.push(format!("(?P<int>{})", decimal));
+        let mut suffixes: Vec<&String> = self.suffixes.iter().collect();
+        // the longest suffix is tried first, so `u16` isn't cut short by `u`
+        suffixes.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        let suffix = if suffixes.is_empty() {
+            String::new()
+        } else {
+            format!("(?:{})?", suffixes.iter().map(|s| regex::escape(s)).collect::<Vec<_>>().join("|"))
+        };
+        format!("(?:{}){}", kinds.join("|"), suffix)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+/// Pattern of a rule, one regex, an array of them or an object with options
+// rules are only built when a rule set is loaded, boxing the options would only make them clumsier to write
+#[allow(clippy::large_enum_variant)]
+pub enum Rule {
+    Pattern(String),
+    /// `"number": ["0x[0-9a-fA-F]+", "[0-9]+\\.[0-9]+", "[0-9]+"]`, the alternatives are tried in order and the first one
+    /// matching wins, even if a later one would match more text
+    Alternatives(Vec<String>),
+    Options(RuleOptions)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+/// Object form of a rule, `{ "literal": "+" }` or `{ "pattern": "\"[^\"]*\"", "strip": ["\"", "\""] }`
+///
+/// The cleaning options make a token's `value` differ from its `raw` text, they are applied in the order trim, strip, unescape
+pub struct RuleOptions {
+    /// A pattern or an array of alternatives, like a rule without options
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<Box<Rule>>,
+    /// Text matched verbatim, without having to escape it, instead of a pattern
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub literal: Option<String>,
+    /// Name of a `RuleMatcher` registered with `LexerBuilder::register_matcher`, matching the rule in code instead
+    /// of a pattern
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub native: Option<String>,
+    /// Numeric literals in the described format instead of a pattern, see `Token::sub_kind`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<NumberFormat>,
+    /// Text closing the token, built from the groups of the opening match like `"\"${hashes}"`, the token runs from
+    /// the opening match to the end of the first closing text after it, for heredocs and raw strings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+    /// Expressions embedded in the string whose mode the rule pushes, like `${name}` in template literals
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpolate: Option<Interpolate>,
+    /// Tokens of the rule which are doc comments, like `///` ones among `//` comments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc: Option<DocComment>,
+    /// Remove whitespace around the value
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub trim: bool,
+    /// Remove this prefix and suffix from the value, like quotes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strip: Option<(String, String)>,
+    /// Decode backslash escapes in the value, `\n`, `\t`, `\r`, `\0`, `\xHH`, `\u{H...}`, `\uHHHH` and escaped
+    /// punctuation like `\"`, any other escape is a `ParsingError::InvalidEscape`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unescape: bool,
+    /// More escapes for `unescape`, from the character after the backslash to its text, like `{ "b": "\u0008" }`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub escapes: BTreeMap<String, String>,
+    /// Rewrite the value after the other cleaning, like lowercasing identifiers of case-insensitive languages for
+    /// symbol tables, `raw` keeps the text as written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<Normalize>,
+    /// Enter this mode after the token, until a rule with `pop` leaves it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub push: Option<String>,
+    /// Go back to the mode the current one was pushed from, before entering the `push` mode if there is one
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pop: bool,
+    /// Coarse class of the token type, like `"operator"` or `"keyword"`, see `Token::category`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// Language variants the rule belongs to, like `"experimental"`, a rule with tags is only lexed when one of them
+    /// is enabled with `RuleSet::with_features`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Only try the rule right after a token of one of these types, never at the start of the source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when_prev_is: Option<Vec<String>>,
+    /// Never try the rule right after a token of one of these types, like a `/regex/` literal after an identifier
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub when_prev_is_not: Vec<String>,
+    /// Only try the rule at the beginning of a line, in column 0, like `#include`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bol: bool,
+    /// Only match if the match ends at the end of a line or of the source
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub eol: bool,
+    /// Only match if the match ends at the end of the source, like a trailer
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub eof_only: bool,
+    /// Most characters a token of the rule may have, a longer match is a `ParsingError::MaxLenExceeded`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_len: Option<usize>,
+    /// Cut the value of a token longer than `max_len` instead, recording a `TruncationWarning`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncate: bool
+}
+
+impl Rule {
+    /// The rule as a single regex
+    pub fn pattern(&self) -> String {
+        match self {
+            Rule::Pattern(pattern) => pattern.clone(),
+            Rule::Alternatives(patterns) => patterns.iter().map(|p| format!("(?:{})", p)).collect::<Vec<_>>().join("|"),
+            Rule::Options(options) => match (&options.pattern, &options.literal, &options.number) {
+                (Some(pattern), _, _) => pattern.pattern(),
+                (None, Some(literal), _) => regex::escape(literal),
+                (None, None, Some(number)) => number.pattern(),
+                (None, None, None) => String::new()
+            }
+        }
+    }
+
+    /// Name of the native matcher of the rule, if it has one
+    fn native(&self) -> Option<&String> {
+        match self {
+            Rule::Options(options) => options.native.as_ref(),
+            _ => None
+        }
+    }
+
+    /// Options changing the token's value, if the rule has any
+    fn cleaning(&self) -> Option<&RuleOptions> {
+        match self {
+            Rule::Options(options) if options.trim || options.strip.is_some() || options.unescape || options.normalize.is_some() => Some(options),
+            _ => None
+        }
+    }
+
+    /// Category of the rule's tokens, if it declares one
+    fn category(&self) -> Option<&String> {
+        match self {
+            Rule::Options(options) => options.category.as_ref(),
+            _ => None
+        }
+    }
+
+    /// Options restricting which token the rule may follow, if the rule has any
+    fn predicate(&self) -> Option<&RuleOptions> {
+        match self {
+            Rule::Options(options) if options.when_prev_is.is_some() || !options.when_prev_is_not.is_empty() => Some(options),
+            _ => None
+        }
+    }
+
+    /// Interpolation of the string the rule starts, if it has one
+    fn interpolate(&self) -> Option<&Interpolate> {
+        match self {
+            Rule::Options(options) => options.interpolate.as_ref(),
+            _ => None
+        }
+    }
+
+    /// Doc comments among the rule's tokens, if it tells them apart
+    fn doc(&self) -> Option<&DocComment> {
+        match self {
+            Rule::Options(options) => options.doc.as_ref(),
+            _ => None
+        }
+    }
+
+    /// Tags of the rule, see `RuleOptions::tags`
+    fn tags(&self) -> &[String] {
+        match self {
+            Rule::Options(options) => &options.tags,
+            _ => &[]
+        }
+    }
+
+    /// Options changing the mode after the token, if the rule has any
+    fn transition(&self) -> Option<&RuleOptions> {
+        match self {
+            Rule::Options(options) if options.push.is_some() || options.pop => Some(options),
+            _ => None
+        }
+    }
+}
+
+impl RuleOptions {
+    /// The cleaned value of a token's raw text
+    ///
+    /// An invalid escape is returned as its byte range in `raw`
+    fn clean(&self, raw: &str) -> Result<String, Range<usize>> {
+        let mut value = if self.trim { raw.trim() } else { raw };
+        if let Some((prefix, suffix)) = &self.strip {
+            value = value.strip_prefix(prefix.as_str()).unwrap_or(value);
+            value = value.strip_suffix(suffix.as_str()).unwrap_or(value);
+        }
+        let value = if self.unescape {
+            // value is a slice of raw
+            let offset = value.as_ptr() as usize - raw.as_ptr() as usize;
+            unescape(value, &self.escapes).map_err(|range| range.start + offset..range.end + offset)?
+        } else {
+            value.to_string()
+        };
+        Ok(match self.normalize {
+            Some(normalize) => normalize.apply(value),
+            None => value
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+/// How a rule's `normalize` rewrites the values of its tokens
+///
+/// ```
+/// use lexer_generator::assert_tokens;
+///
+/// let rules = r#"{ "literals": { "identifier": { "pattern": "[A-Za-z]+", "normalize": "lower" } } }"#;
+/// assert_tokens!(rules, "BEGIN", [("identifier", "begin")]);
+/// ```
+pub enum Normalize {
+    Lower,
+    Upper,
+    /// Unicode normalization form C, composing characters like `e` and a combining accent into `é`, only with the
+    /// `unicode-normalization` feature
+    Nfc
+}
+
+impl Normalize {
+    fn apply(self, value: String) -> String {
+        match self {
+            Normalize::Lower => value.to_lowercase(),
+            Normalize::Upper => value.to_uppercase(),
+            #[cfg(feature = "unicode-normalization")]
+            Normalize::Nfc => {
+                use unicode_normalization::UnicodeNormalization;
+                value.as_str().nfc().collect()
+            }
+            // `validate` reports it
+            #[cfg(not(feature = "unicode-normalization"))]
+            Normalize::Nfc => value
+        }
+    }
+}
+
+/// Decodes backslash escapes, or returns the byte range of the first invalid one
+///
+/// `escapes` maps characters after a backslash to their text, before the standard escapes are tried
+fn unescape(s: &str, escapes: &BTreeMap<String, String>) -> Result<String, Range<usize>> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let (at, escaped) = match chars.next() {
+            Some(next) => next,
+            None => return Err(start..s.len())
+        };
+        let after = at + escaped.len_utf8();
+        let rest = &s[after..];
+        if let Some(text) = escapes.get(&s[at..after]) {
+            out.push_str(text);
+            continue;
+        }
+        let hex = |digits: &str| u32::from_str_radix(digits, 16).ok().filter(|_| digits.chars().all(|c| c.is_ascii_hexdigit()));
+        let (decoded, len) = match escaped {
+            'n' => (Some('\n'), 0),
+            't' => (Some('\t'), 0),
+            'r' => (Some('\r'), 0),
+            '0' => (Some('\0'), 0),
+            'x' => match rest.get(..2).and_then(hex) {
+                Some(b) if b < 0x80 => (char::from_u32(b), 2),
+                _ => (None, rest.chars().take(2).map(char::len_utf8).sum())
+            },
+            'u' if rest.starts_with('{') => match rest.find('}') {
+                Some(close) => (hex(&rest[1..close]).filter(|_| close > 1 && close <= 7).and_then(char::from_u32), close + 1),
+                None => (None, 1)
+            },
+            // `\uXXXX` as in JSON, Java and JavaScript
+            'u' => match rest.get(..4).and_then(hex) {
+                Some(code) => (char::from_u32(code), 4),
+                None => (None, rest.chars().take_while(char::is_ascii_hexdigit).count().min(4))
+            },
+            c if c.is_ascii_punctuation() => (Some(c), 0),
+            _ => (None, 0)
+        };
+        match decoded {
+            Some(decoded) => out.push(decoded),
+            None => return Err(start..after + len)
+        }
+        for _ in rest[..len].chars() {
+            chars.next();
+        }
+    }
+    Ok(out)
+}
+
+impl From<String> for Rule {
+    fn from(pattern: String) -> Self {
+        Rule::Pattern(pattern)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Reasons `RuleSet::load` and the conversions into a `RuleSet` reject a rule set
+pub enum LoadError {
+    /// The JSON doesn't describe a rule set
+    Syntax(String),
+    /// Every place the JSON doesn't follow `RuleSet::JSON_SCHEMA`
+    Schema(Vec<SchemaError>),
+    /// The declared version isn't known to this crate
+    UnsupportedVersion(u32),
+    /// A feature of `version` is used in a rule set declared as `declared`
+    FeatureNotInVersion { feature: String, version: u32, declared: u32 },
+    /// `RuleSetLoader::remove_rule` names a rule the rule set doesn't have
+    UnknownRule(String),
+    /// (file, error) the rule set file couldn't be read
+    Io(PathBuf, String)
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Syntax(e) => write!(f, "invalid rule set: {}", e),
+            LoadError::Schema(errors) => write!(f, "invalid rule set: {}", errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ")),
+            LoadError::UnsupportedVersion(v) => write!(f, "unsupported rule set version {}, the latest is {}", v, RuleSet::LATEST_VERSION),
+            LoadError::FeatureNotInVersion { feature, version, declared } => write!(f, "this grammar uses v{} feature {} but is declared v{}", version, feature, declared),
+            LoadError::UnknownRule(rule) => write!(f, "unknown rule {}", rule),
+            LoadError::Io(path, e) => write!(f, "{}: {}", path.display(), e)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Reasons `Lexer::try_from_args` and `Lexer::try_from_pairs` reject their rules
+pub enum ArgsError {
+    /// (rule, regex error)
+    InvalidPattern(String, String),
+    /// The whitespace pattern isn't a valid regex
+    InvalidWhitespace(String),
+    /// A rule name is given more than once
+    DuplicateRule(String)
+}
+
+impl std::fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgsError::InvalidPattern(rule, e) => write!(f, "invalid pattern of rule {}: {}", rule, e),
+            ArgsError::InvalidWhitespace(e) => write!(f, "invalid whitespace pattern: {}", e),
+            ArgsError::DuplicateRule(rule) => write!(f, "rule {} is defined more than once", rule)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Problems found by `RuleSet::validate`, none of them prevent a lexer from being built
+pub enum ValidationWarning {
+    /// (rule, regex error)
+    InvalidPattern(String, String),
+    /// A conflict, a pair, `asi`, `merge`, a `when_prev_is` list or the grammar refers to a rule which isn't defined
+    UnknownRule(String),
+    /// (rule, rule, sample) both rules match the whole sample and no conflict decides between them
+    AmbiguousRules(String, String, String),
+    /// (rule, sample) whitespace and the rule both match at the start of the sample, so the rule never matches there
+    /// unless `prefer_rules_over_whitespace` is set
+    WhitespaceOverlap(String, String),
+    /// (rule, mode) the rule pushes a mode which isn't defined, or its `interpolate` does
+    UnknownMode(String, String),
+    /// (rule, limit) the rule's pattern compiles to more than `max_pattern_size` bytes, which makes building a lexer
+    /// slow and memory hungry, typically from large counted repetitions like `\w{1000}`
+    PatternTooLarge(String, usize),
+    /// (rule, problem) an option of the rule can't take effect as written, the lexer ignores it
+    InvalidOption(String, String),
+    /// (rule, feature) the rule uses an option of a cargo feature which isn't enabled, the lexer ignores the option
+    MissingFeature { rule: String, feature: String },
+    /// (opener) an `indent` of the `"format"` section which isn't the opener of a pair, `Formatter` never indents
+    /// for it, lexing doesn't use the section
+    FormatIndent(String),
+}
+
+impl std::fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationWarning::InvalidPattern(rule, e) => write!(f, "invalid pattern of rule {}: {}", rule, e),
+            ValidationWarning::UnknownRule(rule) => write!(f, "unknown rule {}", rule),
+            ValidationWarning::AmbiguousRules(a, b, sample) => write!(f, "rules {} and {} both match {:?} and no conflict decides between them", a, b, sample),
+            ValidationWarning::WhitespaceOverlap(rule, sample) => write!(f, "whitespace and rule {} both match at the start of {:?}", rule, sample),
+            ValidationWarning::UnknownMode(rule, mode) => write!(f, "rule {} enters unknown mode {}", rule, mode),
+            ValidationWarning::PatternTooLarge(rule, limit) => write!(f, "pattern of rule {} compiles to more than {} bytes", rule, limit),
+            ValidationWarning::InvalidOption(rule, problem) => write!(f, "ignored option of rule {}: {}", rule, problem),
+            ValidationWarning::MissingFeature { rule, feature } => write!(f, "rule {} needs the {} feature", rule, feature),
+            ValidationWarning::FormatIndent(opener) => write!(f, "format indent {} isn't the opener of a pair", opener)
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl RuleSet {
+    /// Latest schema version, rule sets declare theirs with `"version": 2`
+    ///
+    /// Version 1 is the original shape of only `literals` and `whitespace` with string patterns, everything else needs 2
+    pub const LATEST_VERSION: u32 = 2;
+
+    /// Compiled pattern size above which `validate` reports a rule, unless the rule set sets `max_pattern_size`
+    pub const DEFAULT_MAX_PATTERN_SIZE: usize = 1 << 20;
+
+    /// JSON Schema of rule set files, for editor completion and linting grammars, see also `validate_json`
+    pub const JSON_SCHEMA: &'static str = schema::JSON_SCHEMA;
+
+    /// Parses a rule set from JSON
+    pub fn from_string(json: String) -> Self {
+        serde_json::from_str::<RuleSet>(&json).unwrap()
+    }
+
+    /// Parses a rule set from JSON and checks it against its declared version, a rule set without `"version"` is
+    /// version 1 and can't use features added later
+    ///
+    /// Unlike `from_string` this doesn't panic, every place the JSON doesn't follow the schema is reported at once,
+    /// with suggestions for misspelled keys, and grammars meant for a newer version of this crate are caught
+    pub fn load(json: &str) -> Result<Self, LoadError> {
+        let problems = validate_json(json);
+        if !problems.is_empty() {
+            return Err(LoadError::Schema(problems));
+        }
+        let ruleset = serde_json::from_str::<RuleSet>(json).map_err(|e| LoadError::Syntax(e.to_string()))?;
+        let declared = ruleset.version.unwrap_or(1);
+        if declared == 0 || declared > Self::LATEST_VERSION {
+            return Err(LoadError::UnsupportedVersion(declared));
+        }
+        if let Some((feature, version)) = ruleset.features().into_iter().find(|(_, version)| *version > declared) {
+            return Err(LoadError::FeatureNotInVersion { feature: feature.to_string(), version, declared });
+        }
+        Ok(ruleset)
+    }
+
+    /// Features the rule set uses with the version which introduced them
+    fn features(&self) -> Vec<(&'static str, u32)> {
+        let mut features = vec![];
+        let mut used = |feature, yes: bool| if yes { features.push((feature, 2)) };
+        used("conflicts", !self.conflicts.is_empty());
+        used("line_directive", self.line_directive.is_some());
+        used("pairs", !self.pairs.is_empty());
+        used("asi", self.asi.is_some());
+        used("merge", !self.merge.is_empty());
+        used("coalesce", !self.coalesce.is_empty());
+        used("format", self.format.is_some());
+        used("whitespace: null", self.whitespace.is_none());
+        used("whitespace_token", self.whitespace_token.is_some());
+        used("prefer_rules_over_whitespace", self.prefer_rules_over_whitespace);
+        used("modes", !self.modes.is_empty());
+        used("skip_bom", self.skip_bom);
+        used("skip_shebang", self.skip_shebang);
+        used("max_token_bytes", self.max_token_bytes.is_some());
+        used("max_errors", self.max_errors.is_some());
+        used("recover", !self.recover.is_default());
+        used("max_pattern_size", self.max_pattern_size.is_some());
+        used("grammar", self.grammar.is_some());
+        used("rule alternatives", self.all_rules().any(|(_, r)| matches!(r, Rule::Alternatives(_))));
+        used("rule options", self.all_rules().any(|(_, r)| matches!(r, Rule::Options(_))));
+        features
+    }
+
+    /// Every rule of every mode with its name, the main mode's first
+    fn all_rules(&self) -> impl Iterator<Item = (&String, &Rule)> {
+        self.literals.iter().chain(self.modes.values().flat_map(|mode| mode.literals.iter()))
+    }
+
+    /// The rule set of the language variant with the tags in `enabled`: rules with tags of which none is enabled are
+    /// removed and the tags of the others cleared, rules without tags are kept
+    ///
+    /// Compiling a rule set drops rules with tags, so variants which aren't selected here are left out.
+    ///
+    /// ```
+    /// use lexer_generator::{assert_tokens, RuleSet};
+    ///
+    /// let json = r#"{ "version": 2, "literals": {
+    ///     "name": "[a-z]+",
+    ///     "pipe": { "pattern": "\\|>", "tags": ["experimental"] },
+    ///     "bar": "\\|", "gt": ">"
+    /// } }"#;
+    /// let stable = RuleSet::load(json).unwrap();
+    /// assert_tokens!(stable.clone(), "a |> b", [("name", "a"), ("bar", "|"), ("gt", ">"), ("name", "b")]);
+    /// assert_tokens!(stable.with_features(&["experimental"]), "a |> b", [("name", "a"), ("pipe", "|>"), ("name", "b")]);
+    /// ```
+    pub fn with_features(mut self, enabled: &[&str]) -> Self {
+        let modes = std::iter::once(&mut self.literals).chain(self.modes.values_mut().map(|mode| &mut mode.literals));
+        for literals in modes {
+            literals.retain(|_, rule| rule.tags().is_empty() || rule.tags().iter().any(|tag| enabled.contains(&tag.as_str())));
+            for rule in literals.values_mut() {
+                if let Rule::Options(options) = rule {
+                    options.tags.clear();
+                }
+            }
+        }
+        self
+    }
+
+    /// Serializes the rule set back to JSON, rules are sorted by name
+    pub fn to_json_pretty(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Hash of the rule set, the same across runs and builds, which tells whether tokens were lexed with these rules
+    pub fn fingerprint(&self) -> u64 {
+        // FNV-1a of the JSON, whose maps are sorted
+        serde_json::to_string(self).unwrap().bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+    }
+
+    /// The grammar section, if the rule set has one
+    pub fn grammar(&self) -> Option<&Grammar> {
+        self.grammar.as_ref()
+    }
+
+    /// Declared schema version, 1 if missing
+    pub fn version(&self) -> u32 {
+        self.version.unwrap_or(1)
+    }
+
+    /// The main mode's rules, sorted by name
+    pub fn literals(&self) -> &BTreeMap<String, Rule> {
+        &self.literals
+    }
+
+    /// The main mode's whitespace pattern, None if nothing is skipped
+    pub fn whitespace(&self) -> Option<&str> {
+        self.whitespace.as_deref()
+    }
+
+    pub fn whitespace_token(&self) -> Option<&str> {
+        self.whitespace_token.as_deref()
+    }
+
+    /// The modes besides the main one, sorted by name
+    pub fn modes(&self) -> &BTreeMap<String, Mode> {
+        &self.modes
+    }
+
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    pub fn pairs(&self) -> &[(String, String)] {
+        &self.pairs
+    }
+
+    pub fn asi(&self) -> Option<&Asi> {
+        self.asi.as_ref()
+    }
+
+    pub fn merges(&self) -> &[Merge] {
+        &self.merge
+    }
+
+    pub fn coalesce(&self) -> &[String] {
+        &self.coalesce
+    }
+
+    pub fn format(&self) -> Option<&FormatRules> {
+        self.format.as_ref()
+    }
+
+    /// Every rule of every mode, the main mode's first and then the other modes by name, each mode's rules by name,
+    /// so tools like doc generators and exporters see them in the same order on every run
+    ///
+    /// ```
+    /// let rules = lexer_generator::RuleSet::from_string(String::from(r#"{
+    ///     "literals": { "quote": { "literal": "\"", "push": "string" }, "if": "if", "name": "[a-z]+" },
+    ///     "modes": { "string": { "literals": { "text": "[^\"]+" } } }
+    /// }"#));
+    /// let listed: Vec<(&str, &str, Option<String>)> = rules.rules().into_iter().map(|r| (r.mode, r.name, r.fixed)).collect();
+    /// assert_eq!(listed, [
+    ///     ("main", "if", Some(String::from("if"))),
+    ///     ("main", "name", None),
+    ///     ("main", "quote", Some(String::from("\""))),
+    ///     ("string", "text", None)
+    /// ]);
+    /// assert_eq!(rules.keywords(), [("if", String::from("if"))]);
+    /// ```
+    pub fn rules(&self) -> Vec<RuleInfo<'_>> {
+        let main = self.literals.iter().map(|rule| (MAIN_MODE, rule));
+        let modes = self.modes.iter().flat_map(|(mode, rules)| rules.literals.iter().map(move |rule| (mode.as_str(), rule)));
+        main.chain(modes).map(|(mode, (name, rule))| {
+            let pattern = rule.pattern();
+            RuleInfo { mode, name, rule, fixed: fixed_string(&pattern), pattern }
+        }).collect()
+    }
+
+    /// (rule, word) of every rule matching a single word of letters, digits and underscores, like `"if": "if"`, in the
+    /// order of `rules`
+    pub fn keywords(&self) -> Vec<(&str, String)> {
+        self.rules().into_iter()
+            .filter_map(|info| Some((info.name, info.fixed.filter(|s| s.chars().all(|c| c.is_alphanumeric() || c == '_'))?)))
+            .collect()
+    }
+
+    #[cfg(feature = "toml")]
+    /// Serializes the rule set to TOML, rules are sorted by name
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string_pretty(self)
+    }
+
+    /// Checks the rule set for mistakes which would otherwise only show up while lexing
+    ///
+    /// Ambiguities are found by taking every rule whose pattern is a fixed string and checking whether any other
+    /// rule matches the whole string too, e.g. a keyword `"if"` and an identifier `"[a-z]+"`
+    pub fn validate(&self) -> Vec<ValidationWarning> {
+        let mut warnings = vec![];
+
+        for conflict in &self.conflicts {
+            for name in [&conflict.prefer, &conflict.over] {
+                if !self.all_rules().any(|(n, _)| n == name) {
+                    warnings.push(ValidationWarning::UnknownRule(name.clone()));
+                }
+            }
+        }
+
+        let paired = self.pairs.iter().flat_map(|(opener, closer)| [opener, closer]);
+        let merged = self.merge.iter().flat_map(|merge| &merge.seq);
+        let previous = self.all_rules().filter_map(|(_, rule)| rule.predicate()).flat_map(|p| p.when_prev_is.iter().flatten().chain(&p.when_prev_is_not));
+        for name in paired.chain(self.asi.iter().flat_map(|asi| &asi.after)).chain(merged).chain(previous) {
+            let doc = self.all_rules().filter_map(|(_, rule)| rule.doc()).any(|doc| &doc.token == name);
+            if !self.all_rules().any(|(n, _)| n == name) && !doc {
+                warnings.push(ValidationWarning::UnknownRule(name.clone()));
+            }
+        }
+
+        for opener in self.format.iter().flat_map(|format| &format.indent) {
+            if !self.pairs.iter().any(|(o, _)| o == opener) {
+                warnings.push(ValidationWarning::FormatIndent(opener.clone()));
+            }
+        }
+
+        for name in &self.coalesce {
+            let special = self.whitespace_token.as_ref() == Some(name)
+                || self.asi.as_ref().is_some_and(|asi| &asi.emit == name)
+                || self.merge.iter().any(|merge| &merge.into == name);
+            if !self.all_rules().any(|(n, _)| n == name) && !special {
+                warnings.push(ValidationWarning::UnknownRule(name.clone()));
+            }
+        }
+
+        if let Some(grammar) = &self.grammar {
+            if !grammar.is_nonterminal(&grammar.start) {
+                warnings.push(ValidationWarning::UnknownRule(grammar.start.clone()));
+            }
+            for terminal in grammar.terminals() {
+                let special = self.whitespace_token.as_ref() == Some(&terminal)
+                    || self.asi.as_ref().is_some_and(|asi| asi.emit == terminal)
+                    || self.merge.iter().any(|merge| merge.into == terminal)
+                    || self.all_rules().filter_map(|(_, rule)| rule.interpolate()).any(|i| i.open_token == terminal || i.close_token == terminal)
+                    || self.all_rules().filter_map(|(_, rule)| rule.doc()).any(|doc| doc.token == terminal);
+                if !self.all_rules().any(|(n, _)| n == &terminal) && !special {
+                    warnings.push(ValidationWarning::UnknownRule(terminal));
+                }
+            }
+        }
+
+        if let Some(Err(e)) = self.line_directive.as_ref().map(|p| Regex::new(p)) {
+            warnings.push(ValidationWarning::InvalidPattern(String::from("line_directive"), e.to_string()));
+        }
+
+        for (name, rule) in self.all_rules() {
+            if let Some(mode) = rule.transition().and_then(|t| t.push.as_ref()) {
+                if mode != MAIN_MODE && !self.modes.contains_key(mode) {
+                    warnings.push(ValidationWarning::UnknownMode(name.clone(), mode.clone()));
+                }
+            }
+            if cfg!(not(feature = "unicode-normalization")) && matches!(rule, Rule::Options(options) if options.normalize == Some(Normalize::Nfc)) {
+                warnings.push(ValidationWarning::MissingFeature { rule: name.clone(), feature: String::from("unicode-normalization") });
+            }
+            if matches!(rule, Rule::Options(options) if options.truncate && options.max_len.is_none()) {
+                warnings.push(ValidationWarning::InvalidOption(name.clone(), String::from("truncate needs a max_len")));
+            }
+            if let Some(interpolate) = rule.interpolate() {
+                if rule.transition().is_none_or(|t| t.push.is_none()) {
+                    warnings.push(ValidationWarning::InvalidOption(name.clone(), String::from("interpolate needs a push of the string's mode")));
+                }
+                if interpolate.mode != MAIN_MODE && !self.modes.contains_key(&interpolate.mode) {
+                    warnings.push(ValidationWarning::UnknownMode(name.clone(), interpolate.mode.clone()));
+                }
+                for pattern in [&interpolate.open, &interpolate.close] {
+                    if let Err(e) = Regex::new(pattern) {
+                        warnings.push(ValidationWarning::InvalidPattern(name.clone(), e.to_string()));
+                    }
+                }
+            }
+        }
+
+        self.validate_mode(&self.literals, self.whitespace.as_ref(), "whitespace", &mut warnings);
+        for (name, mode) in &self.modes {
+            self.validate_mode(&mode.literals, mode.whitespace.as_ref(), &format!("{}.whitespace", name), &mut warnings);
+        }
+        warnings
+    }
+
+    /// Checks the patterns of one mode, and the ambiguities and whitespace overlaps between its rules
+    fn validate_mode(&self, literals: &BTreeMap<String, Rule>, whitespace: Option<&String>, whitespace_name: &str, warnings: &mut Vec<ValidationWarning>) {
+        let mut names: Vec<&String> = literals.keys().collect();
+        names.sort();
+
+        if let Some(Err(e)) = whitespace.map(|p| Regex::new(p)) {
+            warnings.push(ValidationWarning::InvalidPattern(whitespace_name.to_string(), e.to_string()));
+        }
+
+        for name in &names {
+            if let Rule::Options(options) = &literals[*name] {
+                if [options.pattern.is_some(), options.literal.is_some(), options.number.is_some(), options.native.is_some()].iter().filter(|set| **set).count() != 1 {
+                    warnings.push(ValidationWarning::InvalidPattern((*name).clone(), String::from("needs one of a pattern, a literal, a number or a native matcher")));
+                }
+            }
+        }
+
+        // limits above the default of `regex` would pass patterns the lexer can't build
+        let limit = self.max_pattern_size.unwrap_or(Self::DEFAULT_MAX_PATTERN_SIZE).min(REGEX_SIZE_LIMIT);
+        let mut anchored: Vec<(&String, Regex)> = vec![];
+        for name in &names {
+            let pattern = format!("^(?:{})$", literals[*name].pattern());
+            // building with the lint's limit costs nothing extra for the usual small patterns
+            let built = match RegexBuilder::new(&pattern).size_limit(limit).build() {
+                Err(regex::Error::CompiledTooBig(_)) => {
+                    warnings.push(ValidationWarning::PatternTooLarge((*name).clone(), limit));
+                    Regex::new(&pattern)
+                }
+                built => built
+            };
+            match built {
+                Ok(re) => anchored.push((name, re)),
+                Err(e) => warnings.push(ValidationWarning::InvalidPattern((*name).clone(), e.to_string()))
+            }
+        }
+
+        for (name, _) in &anchored {
+            let sample = match fixed_string(&literals[*name].pattern()) {
+                Some(s) => s,
+                None => continue
+            };
+            for (other, re) in &anchored {
+                if other == name || !re.is_match(&sample) || self.resolved(name, other) {
+                    continue;
+                }
+                // two fixed strings which are equal would be reported from both sides
+                if fixed_string(&literals[*other].pattern()).as_ref() == Some(&sample) && other < name {
+                    continue;
+                }
+                warnings.push(ValidationWarning::AmbiguousRules((*name).clone(), (*other).clone(), sample.clone()));
+            }
+        }
+
+        let whitespace = whitespace.and_then(|p| Regex::new(&format!("^(?:{})", p)).ok());
+        if let (Some(whitespace), false) = (whitespace, self.prefer_rules_over_whitespace) {
+            let starts = |re: &Regex, sample: &str| re.find(sample).is_some_and(|m| m.end() > 0);
+            let mut samples: Vec<String> = [" ", "\t", "\n", "\r\n"].iter().map(|s| s.to_string()).collect();
+            samples.extend(names.iter().filter_map(|name| fixed_string(&literals[*name].pattern())));
+            for name in &names {
+                let rule = match Regex::new(&format!("^(?:{})", literals[*name].pattern())) {
+                    Ok(re) => re,
+                    Err(_) => continue
+                };
+                if let Some(sample) = samples.iter().find(|s| starts(&whitespace, s) && starts(&rule, s)) {
+                    warnings.push(ValidationWarning::WhitespaceOverlap((*name).clone(), sample.clone()));
+                }
+            }
+        }
+    }
+
+    /// Compiled size of each rule's pattern in bytes, largest first, for finding the rules which make a lexer slow to
+    /// build
+    ///
+    /// Sizes are probed by compiling with doubling size limits, so each is an upper bound at most twice the real size.
+    /// Native rules and invalid patterns aren't listed.
+    ///
+    /// ```
+    /// let rules = lexer_generator::RuleSet::from_string(String::from(r#"{ "literals": { "word": "\\w{100}", "digit": "[0-9]" } }"#));
+    /// let sizes = rules.pattern_sizes();
+    /// assert_eq!(sizes[0].0, "word");
+    /// assert!(sizes[0].1 > sizes[1].1);
+    /// ```
+    pub fn pattern_sizes(&self) -> Vec<(String, usize)> {
+        let size = |pattern: &str| {
+            let mut limit = 1024;
+            loop {
+                match RegexBuilder::new(pattern).size_limit(limit).build() {
+                    Ok(_) => return Some(limit),
+                    Err(regex::Error::CompiledTooBig(_)) if limit < REGEX_SIZE_LIMIT => limit *= 2,
+                    Err(_) => return None
+                }
+            }
+        };
+        let mut sizes: Vec<(String, usize)> = self.all_rules()
+            .filter(|(_, rule)| rule.native().is_none())
+            .filter_map(|(name, rule)| Some((name.clone(), size(&rule.pattern())?)))
+            .collect();
+        sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        sizes
+    }
+
+    /// Lexes every sample and reports which rules were used, helping to find dead or shadowed rules
+    ///
+    /// A rule is dead if it never matched where a token started, and shadowed if it matched but always lost to another rule
+    pub fn coverage(&self, samples: &[&str]) -> CoverageReport {
+        let mut counts: HashMap<String, usize> = self.all_rules().map(|(k, _)| (k.clone(), 0)).collect();
+        let mut matched: HashMap<String, bool> = HashMap::new();
+        let mut errors = vec![];
+        let mut lexer = Lexer::from_rules(self.clone(), String::new());
+        for (i, sample) in samples.iter().enumerate() {
+            lexer.reset(sample.to_string());
+            loop {
+                lexer.skip_whitespace();
+                for (name, _) in lexer.candidates(false) {
+                    matched.insert(name, true);
+                }
+                match lexer.next_token() {
+                    Ok(token) => *counts.entry(token.token_type).or_insert(0) += 1,
+                    Err(ParsingError::EndOfFileError) => break,
+                    Err(e) => errors.push((i, e))
+                }
+            }
+        }
+        let mut dead = vec![];
+        let mut shadowed = vec![];
+        for (name, count) in &counts {
+            if !matched.contains_key(name) {
+                dead.push(name.clone());
+            } else if *count == 0 {
+                shadowed.push(name.clone());
+            }
+        }
+        dead.sort();
+        shadowed.sort();
+        CoverageReport { counts, dead, shadowed, errors }
+    }
+
+    fn resolved(&self, a: &str, b: &str) -> bool {
+        self.conflicts.iter().any(|c| (c.prefer == a && c.over == b) || (c.prefer == b && c.over == a))
+    }
+}
+
+/// Parses a rule set from JSON like `RuleSet::load`
+///
+/// ```
+/// use lexer_generator::RuleSet;
+///
+/// let rules: RuleSet = r#"{ "literals": { "number": "[0-9]+" } }"#.parse().unwrap();
+/// assert!(r#"{ "literals": 1 }"#.parse::<RuleSet>().is_err());
+/// ```
+impl std::str::FromStr for RuleSet {
+    type Err = LoadError;
+
+    fn from_str(json: &str) -> Result<Self, LoadError> {
+        Self::load(json)
+    }
+}
+
+/// Parses a rule set from JSON like `RuleSet::load`
+impl TryFrom<&str> for RuleSet {
+    type Error = LoadError;
+
+    fn try_from(json: &str) -> Result<Self, LoadError> {
+        Self::load(json)
+    }
+}
+
+/// Reads a rule set file and parses it like `RuleSet::load`, a file which can't be read is a `LoadError::Io`
+///
+/// ```no_run
+/// use lexer_generator::RuleSet;
+/// use std::path::Path;
+///
+/// let rules = RuleSet::try_from(Path::new("grammar.json")).unwrap();
+/// ```
+impl TryFrom<&Path> for RuleSet {
+    type Error = LoadError;
+
+    fn try_from(path: &Path) -> Result<Self, LoadError> {
+        let json = std::fs::read_to_string(path).map_err(|e| LoadError::Io(path.to_path_buf(), e.to_string()))?;
+        Self::load(&json)
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Result of `RuleSet::coverage`
+pub struct CoverageReport {
+    /// Number of tokens produced by each rule
+    pub counts: HashMap<String, usize>,
+    /// Rules which never matched at the start of a token
+    pub dead: Vec<String>,
+    /// Rules which matched but always lost to a longer or preferred match
+    pub shadowed: Vec<String>,
+    /// Errors hit while lexing, with the index of the sample they came from
+    pub errors: Vec<(usize, ParsingError)>
+}
+
+/// Compiled size limit `Regex::new` builds patterns with, the default of `regex`
+const REGEX_SIZE_LIMIT: usize = 10 * (1 << 20);
+
+/// Compiles an already validated pattern so it only matches at the start of the text, a failed match then costs no
+/// search through the rest of the source
+fn anchored(pattern: &str) -> Regex {
+    Regex::new(&format!("^(?:{})", pattern)).unwrap()
+}
+
+/// Returns the string a pattern matches if it can only ever match that one string
+pub(crate) fn fixed_string(pattern: &str) -> Option<String> {
+    use regex_syntax::hir::{Hir, HirKind, Literal};
+    fn walk(hir: &Hir, out: &mut String) -> bool {
+        match hir.kind() {
+            HirKind::Literal(Literal::Unicode(c)) => { out.push(*c); true }
+            HirKind::Concat(hirs) => hirs.iter().all(|h| walk(h, out)),
+            HirKind::Group(group) => walk(&group.hir, out),
+            _ => false
+        }
+    }
+    let hir = regex_syntax::Parser::new().parse(pattern).ok()?;
+    let mut out = String::new();
+    if walk(&hir, &mut out) && !out.is_empty() { Some(out) } else { None }
+}
+
+#[derive(Clone)]
+/// A rule set with its patterns compiled, ready for lexing, lexers share one through an `Arc`
+///
+/// ```
+/// use lexer_generator::{CompiledRules, Lexer};
+/// use std::sync::Arc;
+///
+/// let rules = Arc::new(CompiledRules::from_json(r#"{ "literals": { "word": "[a-z]+" } }"#).unwrap());
+/// let mut first = Lexer::from_compiled(rules.clone(), String::from("one"));
+/// let mut second = Lexer::from_compiled(rules, String::from("two"));
+/// assert_eq!(first.next_token().unwrap().value, "one");
+/// assert_eq!(second.next_token().unwrap().value, "two");
+/// ```
+pub struct CompiledRules {
+    /// Compiled rules of every mode, indexed like `mode_names`, the main mode is 0
+    modes: Vec<ModeRules>,
+    mode_names: Vec<String>,
+    ids: HashMap<String, TokenTypeId>,
+    /// Rule names indexed by their `TokenTypeId`, shared by every `OwnedToken`
+    names: Vec<Arc<str>>,
+    /// Category of every token type whose rule declares one, the first declaration wins if a type is in several modes
+    categories: HashMap<String, Arc<str>>,
+    whitespace_token: Option<String>,
+    prefer_rules_over_whitespace: bool,
+    conflicts: Vec<Conflict>,
+    pairs: Vec<(String, String)>,
+    asi: Option<Asi>,
+    merges: Vec<Merge>,
+    coalesce: HashSet<String>,
+    interpolations: Vec<Interpolation>,
+    line_directive: Option<Regex>,
+    skip_bom: bool,
+    skip_shebang: bool,
+    max_token_bytes: Option<usize>,
+    max_errors: Option<usize>,
+    recover: Recover
+}
+
+#[derive(Clone)]
+/// Compiled rules of one mode
+struct ModeRules {
+    /// Rules matched by regex, in order of their names
+    literals: Vec<(String, Regex)>,
+    /// Rules matching a fixed string are searched for all at once, `fixed_names` holds the rule of each pattern
+    fixed: AhoCorasick,
+    fixed_names: Vec<String>,
+    /// Options of the rules whose values are cleaned
+    cleaning: HashMap<String, RuleOptions>,
+    /// Mode changes after the rules with `push` or `pop`
+    transitions: HashMap<String, Transition>,
+    /// (`when_prev_is`, `when_prev_is_not`) of the rules which depend on the previous token
+    predicates: HashMap<String, (Option<Vec<String>>, Vec<String>)>,
+    /// Rules which only match at the beginning of a line, at the end of a line and at the end of the source
+    bol: HashSet<String>,
+    eol: HashSet<String>,
+    eof_only: HashSet<String>,
+    /// (`max_len`, `truncate`) of the rules with a length limit
+    max_lens: HashMap<String, (usize, bool)>,
+    /// Anchored patterns of the `number` rules, for telling the sub-kind of their tokens
+    numbers: HashMap<String, Regex>,
+    /// `until` templates of the rules whose token runs to a closing text built from the opening match
+    terminators: HashMap<String, String>,
+    /// Index in `CompiledRules::interpolations` of the interpolation of each rule with `interpolate`
+    interpolations: HashMap<String, usize>,
+    /// Doc comments of the rules with `doc`
+    docs: HashMap<String, DocComment>,
+    /// Hand-written rules registered with `Lexer::with_matcher`, they replace the JSON rules of the same name
+    matchers: Vec<(String, Arc<dyn RuleMatcher>)>,
+    whitespace: Option<Regex>
+}
+
+#[derive(Clone)]
+/// Compiled `Interpolate`
+struct Interpolation {
+    open: Regex,
+    close: Regex,
+    mode: usize,
+    open_token: String,
+    close_token: String
+}
+
+#[derive(Clone, Copy, Debug)]
+/// A string with interpolation the lexer is in, and whether it is in one of its expressions
+struct InterpolationFrame {
+    interpolation: usize,
+    /// Length of the mode stack with the string's mode on top
+    depth: usize,
+    /// Number of open delimiters when the current expression started, None between expressions
+    expression: Option<usize>
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Transition {
+    pop: bool,
+    /// Index of the mode to enter, None for rules which only pop or push a mode which isn't defined
+    push: Option<usize>
+}
+
+impl ModeRules {
+    fn from(literals: &BTreeMap<String, Rule>, whitespace: Option<String>, mode_ids: &HashMap<String, usize>) -> Self {
+        let mut regexes: Vec<(String, Regex)> = vec![];
+        let mut fixed_names = vec![];
+        let mut fixed_strings = vec![];
+        for (k, v) in literals.iter().filter(|(_, v)| v.native().is_none()) {
+            let pattern = v.pattern();
+            let terminated = matches!(v, Rule::Options(options) if options.until.is_some());
+            match fixed_string(&pattern).filter(|_| !terminated) {
+                Some(s) => {
+                    fixed_names.push(k.clone());
+                    fixed_strings.push(s);
+                }
+                None => regexes.push((k.clone(), anchored(&pattern)))
+            }
+        }
+        let flagged = |flag: fn(&RuleOptions) -> bool| -> HashSet<String> {
+            literals.iter().filter(|(_, v)| matches!(v, Rule::Options(options) if flag(options))).map(|(k, _)| k.clone()).collect()
+        };
+        Self {
+            // list of literal values, operators, keywords, etc., "name" : "regex pattern"
+            literals: regexes,
+            fixed: AhoCorasickBuilder::new().anchored(true).build(&fixed_strings),
+            fixed_names,
+            cleaning: literals.iter().filter_map(|(k, v)| Some((k.clone(), v.cleaning()?.clone()))).collect(),
+            transitions: literals.iter().filter_map(|(k, v)| {
+                let options = v.transition()?;
+                Some((k.clone(), Transition { pop: options.pop, push: options.push.as_ref().and_then(|m| mode_ids.get(m).copied()) }))
+            }).collect(),
+            predicates: literals.iter().filter_map(|(k, v)| {
+                let options = v.predicate()?;
+                Some((k.clone(), (options.when_prev_is.clone(), options.when_prev_is_not.clone())))
+            }).collect(),
+            bol: flagged(|options| options.bol),
+            eol: flagged(|options| options.eol),
+            eof_only: flagged(|options| options.eof_only),
+            max_lens: literals.iter().filter_map(|(k, v)| match v {
+                Rule::Options(options) => Some((k.clone(), (options.max_len?, options.truncate))),
+                _ => None
+            }).collect(),
+            numbers: literals.iter().filter_map(|(k, v)| match v {
+                Rule::Options(RuleOptions { number: Some(number), .. }) => Some((k.clone(), Regex::new(&format!("^(?:{})$", number.pattern())).unwrap())),
+                _ => None
+            }).collect(),
+            terminators: literals.iter().filter_map(|(k, v)| match v {
+                Rule::Options(options) => Some((k.clone(), options.until.clone()?)),
+                _ => None
+            }).collect(),
+            interpolations: HashMap::new(),
+            docs: literals.iter().filter_map(|(k, v)| Some((k.clone(), v.doc()?.clone()))).collect(),
+            matchers: vec![],
+            whitespace: whitespace.map(|p| anchored(&p))
+        }
+    }
+
+    /// Whether rule `name` may match after a token of type `prev`, None at the start of the source, and at the start
+    /// of a line or not
+    fn enabled(&self, name: &str, prev: Option<&str>, line_start: bool) -> bool {
+        if !line_start && self.bol.contains(name) {
+            return false;
+        }
+        match self.predicates.get(name) {
+            Some((is, is_not)) => {
+                let after = |types: &Vec<String>| prev.is_some_and(|prev| types.iter().any(|t| t == prev));
+                is.as_ref().is_none_or(after) && !after(is_not)
+            }
+            None => true
+        }
+    }
+
+    /// Whether a match of rule `name` may end after `len` bytes of `rest`, the source up to its end
+    fn fits_end(&self, name: &str, rest: &str, len: usize) -> bool {
+        let after = &rest[len..];
+        (!self.eol.contains(name) || after.is_empty() || after.starts_with('\n') || after.starts_with("\r\n"))
+            && (!self.eof_only.contains(name) || after.is_empty())
+    }
+}
+
+#[allow(dead_code)]
+impl CompiledRules {
+    /// Compiles the rules, failing on a pattern which isn't a valid regex or on more token types than there are ids
+    /// rather than panicking, the other problems `validate` reports don't stop it
+    pub fn compile(rules: RuleSet) -> Result<Self, CompileError> {
+        let types = Self::token_types(&rules.clone().with_features(&[])).len();
+        if types > TokenTypeId::MAX_TYPES {
+            return Err(CompileError::TooManyTypes(types));
+        }
+        let invalid = rules.validate().into_iter().find_map(|warning| match warning {
+            ValidationWarning::InvalidPattern(rule, message) => Some(CompileError::InvalidPattern(rule, message)),
+            _ => None
+        });
+        match invalid {
+            Some(e) => Err(e),
+            None => Ok(Self::from(rules))
+        }
+    }
+
+    /// Loads a rule set like `RuleSet::load` and compiles it
+    pub fn from_json(json: &str) -> Result<Self, CompileError> {
+        Self::compile(RuleSet::load(json).map_err(CompileError::Load)?)
+    }
+
+    /// Every token type of the rules, sorted by name
+    fn token_types(ruleset: &RuleSet) -> std::collections::BTreeSet<&String> {
+        let asi_emit = ruleset.asi.as_ref().map(|asi| &asi.emit);
+        let merged = ruleset.merge.iter().map(|merge| &merge.into);
+        let interpolated = ruleset.all_rules().filter_map(|(_, rule)| rule.interpolate()).flat_map(|i| [&i.open_token, &i.close_token]);
+        let docs = ruleset.all_rules().filter_map(|(_, rule)| rule.doc()).map(|doc| &doc.token);
+        ruleset.all_rules().map(|(k, _)| k).chain(&ruleset.whitespace_token).chain(asi_emit).chain(merged).chain(interpolated).chain(docs).collect()
+    }
+
+    fn from(ruleset: RuleSet) -> Self {
+        let ruleset = ruleset.with_features(&[]);
+        let mode_names: Vec<String> = std::iter::once(String::from(MAIN_MODE)).chain(ruleset.modes.keys().cloned()).collect();
+        let mode_ids: HashMap<String, usize> = mode_names.iter().enumerate().map(|(i, name)| (name.clone(), i)).collect();
+        let mut modes = vec![ModeRules::from(&ruleset.literals, ruleset.whitespace.clone(), &mode_ids)];
+        modes.extend(ruleset.modes.values().map(|mode| ModeRules::from(&mode.literals, mode.whitespace.clone(), &mode_ids)));
+        let mut interpolations = vec![];
+        let mode_literals = std::iter::once(&ruleset.literals).chain(ruleset.modes.values().map(|mode| &mode.literals));
+        for (mode, literals) in modes.iter_mut().zip(mode_literals) {
+            // without a push there is no string mode to interpolate in, so the option is ignored
+            let pushing = |rule: &Rule| rule.transition().is_some_and(|t| t.push.is_some());
+            for (name, interpolate) in literals.iter().filter(|(_, rule)| pushing(rule)).filter_map(|(name, rule)| Some((name, rule.interpolate()?))) {
+                mode.interpolations.insert(name.clone(), interpolations.len());
+                interpolations.push(Interpolation {
+                    open: anchored(&interpolate.open),
+                    close: anchored(&interpolate.close),
+                    mode: mode_ids.get(&interpolate.mode).copied().unwrap_or(0),
+                    open_token: interpolate.open_token.clone(),
+                    close_token: interpolate.close_token.clone()
+                });
+            }
+        }
+        // sorted by name, so ids only depend on the set of token types
+        let types = Self::token_types(&ruleset);
+        let mut categories: HashMap<String, Arc<str>> = HashMap::new();
+        for (name, rule) in ruleset.all_rules() {
+            if let Some(category) = rule.category() {
+                categories.entry(name.clone()).or_insert_with(|| Arc::from(category.as_str()));
+            }
+        }
+        Self {
+            ids: types.iter().enumerate().map(|(i, k)| ((*k).clone(), TokenTypeId::from_index(i).expect("more token types than ids"))).collect(),
+            names: types.iter().map(|k| Arc::from(k.as_str())).collect(),
+            categories,
+            modes,
+            mode_names,
+            whitespace_token: ruleset.whitespace_token,
+            prefer_rules_over_whitespace: ruleset.prefer_rules_over_whitespace,
+            conflicts: ruleset.conflicts,
+            pairs: ruleset.pairs,
+            asi: ruleset.asi,
+            merges: ruleset.merge,
+            coalesce: ruleset.coalesce.into_iter().collect(),
+            interpolations,
+            line_directive: ruleset.line_directive.map(|p| anchored(&p)),
+            skip_bom: ruleset.skip_bom,
+            skip_shebang: ruleset.skip_shebang,
+            max_token_bytes: ruleset.max_token_bytes,
+            max_errors: ruleset.max_errors,
+            recover: ruleset.recover
+        }
+    }
+    fn from_string(json: String) -> Self {
+        Self::from(RuleSet::from_string(json))
+    }
+
+    /// Origin of a token of type `name` spanning `span`, rules never match empty text so an empty token of the
+    /// `asi.emit` type was inserted
+    fn origin(&self, name: &str, span: &Range<usize>) -> TokenOrigin {
+        if span.is_empty() && self.asi.as_ref().is_some_and(|asi| asi.emit == name) {
+            TokenOrigin::Inserted
+        } else {
+            TokenOrigin::Source
+        }
+    }
+
+    /// True if a conflict makes candidate `a` (rule name, match length) win over candidate `b`, whatever their lengths
+    fn beats(&self, a: (&str, usize), b: (&str, usize)) -> bool {
+        self.conflicts.iter().any(|c| (c.ignore_length || a.1 == b.1) && c.prefer == a.0 && c.over == b.0)
+    }
+}
+
+/// Small integer id of a token type, for indexing parser tables instead of hashing type names
+///
+/// Ids are assigned in order of the rule names, so a rule set with the same rule names always gives the same ids
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TokenTypeId(pub u16);
+
+impl TokenTypeId {
+    /// Id of the `ERROR_TOKEN` tokens of `Lexer::tokenize_all_errors`, no rule has it
+    pub const ERROR: TokenTypeId = TokenTypeId(u16::MAX);
+
+    /// Most token types a rule set can have, every id below `ERROR`
+    pub const MAX_TYPES: usize = u16::MAX as usize;
+
+    /// The id of index `i`, None if it would be `ERROR` or doesn't fit
+    fn from_index(i: usize) -> Option<TokenTypeId> {
+        u16::try_from(i).ok().filter(|id| *id != u16::MAX).map(TokenTypeId)
+    }
+
+    /// The id as a table index
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Tokens are parsed from source code, their types are defined by the Lexer's ruleset
+pub struct Token {
+    pub token_type: String,
+    pub type_id: TokenTypeId,
+    /// The token's text, cleaned by the rule's `trim`, `strip` and `unescape` options
+    pub value: String,
+    /// The text exactly as it was matched
+    pub raw: String,
+    pub line: usize,
+    /// Column of the first character from the start of the line, in characters unless the lexer was given another
+    /// `ColumnUnit`
+    pub column: usize,
+    /// Byte range of the token in the source
+    pub span: Range<usize>,
+    /// Name of the source the token was lexed from, see `Lexer::with_source_name`
+    pub source_name: Option<Arc<str>>,
+    category: Option<Arc<str>>,
+    sub_kind: Option<&'static str>,
+    origin: TokenOrigin
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// Where a token comes from, see `Token::origin`
+pub enum TokenOrigin {
+    /// Text of the source matched by a rule
+    #[default]
+    Source,
+    /// Inserted by the lexer without any text of its own, like the terminators of `"asi"`, its span is empty and at
+    /// the place it was inserted
+    Inserted,
+    /// Text skipped because of an error, the `ERROR_TOKEN` tokens of `Lexer::tokenize_all_errors`
+    Recovered
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// What columns are counted in, see `Lexer::with_column_unit`
+pub enum ColumnUnit {
+    /// UTF-8 bytes
+    Bytes,
+    /// Unicode scalar values
+    #[default]
+    Chars,
+    /// UTF-16 code units, the columns of the Language Server Protocol
+    Utf16
+}
+
+impl ColumnUnit {
+    /// Length of `text` in this unit
+    pub fn measure(self, text: &str) -> usize {
+        match self {
+            ColumnUnit::Bytes => text.len(),
+            ColumnUnit::Chars => text.chars().count(),
+            ColumnUnit::Utf16 => text.encode_utf16().count()
+        }
+    }
+
+    /// Length of one character in this unit
+    fn width(self, c: char) -> usize {
+        match self {
+            ColumnUnit::Bytes => c.len_utf8(),
+            ColumnUnit::Chars => 1,
+            ColumnUnit::Utf16 => c.len_utf16()
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Position in a named source, displayed as `name:line:column`
+pub struct Location {
+    pub source_name: Option<Arc<str>>,
+    pub line: usize,
+    pub column: usize,
+    /// Byte offset in the source
+    pub offset: usize
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.source_name {
+            Some(name) => write!(f, "{}:{}:{}", name, self.line, self.column),
+            None => write!(f, "{}:{}", self.line, self.column)
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Token {
+    /// Category the token's rule declares with `"category"`, like `"operator"`, for branching on coarse classes of
+    /// tokens instead of every token type
+    pub fn category(&self) -> Option<&str> {
+        self.category.as_deref()
+    }
+
+    /// Kind of numeric literal of a token from a `"number"` rule, `int`, `int_hex`, `int_oct`, `int_bin` or `float`
+    ///
+    /// ```
+    /// use lexer_generator::{Lexer, RuleSet};
+    ///
+    /// let rules = RuleSet::load(r#"{ "version": 2, "literals": { "number": { "number": { "radixes": ["hex"], "separator": "_", "float": true } } } }"#).unwrap();
+    /// let mut lexer = Lexer::from_rules(rules, String::from("1_000 0xff 2.5"));
+    /// let kinds: Vec<&str> = std::iter::from_fn(|| lexer.next_token().ok()).map(|t| t.sub_kind().unwrap()).collect();
+    /// assert_eq!(kinds, ["int", "int_hex", "float"]);
+    /// ```
+    pub fn sub_kind(&self) -> Option<&'static str> {
+        self.sub_kind
+    }
+
+    /// Whether the token is text of the source, was inserted by the lexer or stands for text skipped by an error
+    ///
+    /// ```
+    /// use lexer_generator::{Lexer, RuleSet, TokenOrigin};
+    ///
+    /// let rules = RuleSet::load(r#"{ "version": 2, "literals": { "name": "[a-z]+" }, "asi": { "after": ["name"], "emit": "semicolon" } }"#).unwrap();
+    /// let mut lexer = Lexer::from_rules(rules, String::from("go\n"));
+    /// assert_eq!(lexer.next_token().unwrap().origin(), TokenOrigin::Source);
+    /// let semicolon = lexer.next_token().unwrap();
+    /// assert_eq!((semicolon.origin(), semicolon.span), (TokenOrigin::Inserted, 2..2));
+    /// ```
+    pub fn origin(&self) -> TokenOrigin {
+        self.origin
+    }
+
+    /// True for tokens which the lexer inserted rather than matched, see `origin`
+    pub fn is_synthetic(&self) -> bool {
+        self.origin == TokenOrigin::Inserted
+    }
+
+    /// Where the token starts
+    pub fn location(&self) -> Location {
+        Location { source_name: self.source_name.clone(), line: self.line, column: self.column, offset: self.span.start }
+    }
+
+    /// Returns true if token.token_type matches any of the types
+    pub fn is<T: ToString>(&self, types: Vec<T>) -> bool {
+        {
+            let mut v = vec![];
+            for t in types {
+                v.push(t.to_string());
+            }
+            v
+        }.contains(&self.token_type)
+    }
+
+    /// Length of the token's value in bytes
+    pub fn len(&self) -> usize {
+        self.value.len()
+    }
+
+    /// Returns true if the token's value is empty
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    /// Display width of the token's value in terminal columns
+    #[cfg(feature = "unicode-width")]
+    pub fn width(&self) -> usize {
+        unicode_width::UnicodeWidthStr::width(self.value.as_str())
+    }
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({})", self.token_type, self.value)
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Token from `Lexer::tokenize_into_arena`, its type name and value are shared with the other tokens instead of
+/// being allocated for each of them, and it can be sent to another thread
+pub struct OwnedToken {
+    pub token_type: Arc<str>,
+    pub type_id: TokenTypeId,
+    pub line: usize,
+    pub column: usize,
+    pub span: Range<usize>,
+    pub source_name: Option<Arc<str>>,
+    category: Option<Arc<str>>,
+    sub_kind: Option<&'static str>,
+    origin: TokenOrigin,
+    /// The whole source, the value is `source[span]`
+    source: Arc<str>
+}
+
+impl OwnedToken {
+    /// The text exactly as it was matched, the cleaning options of rules aren't applied
+    pub fn value(&self) -> &str {
+        &self.source[self.span.clone()]
+    }
+
+    pub fn category(&self) -> Option<&str> {
+        self.category.as_deref()
+    }
+
+    /// See `Token::sub_kind`
+    pub fn sub_kind(&self) -> Option<&'static str> {
+        self.sub_kind
+    }
+
+    /// See `Token::origin`
+    pub fn origin(&self) -> TokenOrigin {
+        self.origin
+    }
+
+    pub fn to_token(&self) -> Token {
+        Token {
+            token_type: self.token_type.to_string(),
+            type_id: self.type_id,
+            value: self.value().to_string(),
+            raw: self.value().to_string(),
+            line: self.line,
+            column: self.column,
+            span: self.span.clone(),
+            source_name: self.source_name.clone(),
+            category: self.category.clone(),
+            sub_kind: self.sub_kind,
+            origin: self.origin
+        }
+    }
+}
+
+impl std::fmt::Display for OwnedToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({})", self.token_type, self.value())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Token from `Lexer::tokenize_compact` in 20 bytes, without any text, its value is read from the source on demand
+/// and `Lexer::expand` turns it back into a `Token`
+///
+/// Offsets are bytes, so sources are limited to 4 GiB.
+pub struct CompactToken {
+    /// Id of the token type, `TokenTypeId(kind_id)`
+    pub kind_id: u16,
+    /// Byte offset of the token in the source
+    pub start: u32,
+    /// Length in bytes
+    pub len: u32,
+    pub line: u32,
+    pub column: u32
+}
+
+impl CompactToken {
+    pub fn type_id(&self) -> TokenTypeId {
+        TokenTypeId(self.kind_id)
+    }
+
+    /// Byte range of the token in the source
+    pub fn span(&self) -> Range<usize> {
+        self.start as usize..self.start as usize + self.len as usize
+    }
+
+    /// The text exactly as it was matched, from the source the token was lexed from
+    pub fn value<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.span()]
+    }
+}
+
+impl TryFrom<&Token> for CompactToken {
+    type Error = std::num::TryFromIntError;
+
+    /// Fails if the token lies beyond 4 GiB of source
+    fn try_from(token: &Token) -> Result<Self, Self::Error> {
+        Ok(Self {
+            kind_id: token.type_id.0,
+            start: u32::try_from(token.span.start)?,
+            len: u32::try_from(token.span.len())?,
+            line: u32::try_from(token.line)?,
+            column: u32::try_from(token.column)?
+        })
+    }
+}
+
+/// Runs of tokens starting on the same line with that line, for line-oriented tools like linters, lines without tokens
+/// are left out and a token spanning several lines belongs to the line it starts on
+///
+/// ```
+/// use lexer_generator::{group_by_line, Lexer};
+///
+/// let lexer = Lexer::from(String::from(r#"{ "literals": { "name": "[a-z]+" } }"#), String::from("a b\n\nc"));
+/// let tokens: Vec<_> = lexer.map(Result::unwrap).collect();
+/// let lines: Vec<(usize, usize)> = group_by_line(&tokens).map(|(line, tokens)| (line, tokens.len())).collect();
+/// assert_eq!(lines, [(0, 2), (2, 1)]);
+/// ```
+pub fn group_by_line(tokens: &[Token]) -> impl Iterator<Item = (usize, &[Token])> {
+    tokens.chunk_by(|a, b| a.line == b.line).map(|tokens| (tokens[0].line, tokens))
+}
+
+/// Formats tokens as an aligned table of index, kind, value, line:col and span, for debugging grammars
+pub fn format_tokens(tokens: &[Token]) -> String {
+    let mut rows = vec![[String::from("index"), String::from("kind"), String::from("value"), String::from("line:col"), String::from("span")]];
+    for (i, token) in tokens.iter().enumerate() {
+        rows.push([
+            i.to_string(),
+            token.token_type.clone(),
+            token.value.escape_debug().to_string(),
+            format!("{}:{}", token.line, token.column),
+            format!("{}..{}", token.span.start, token.span.end)
+        ]);
+    }
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = String::new();
+    for row in &rows {
+        let mut line = String::new();
+        for (width, cell) in widths.iter().zip(row) {
+            line.push_str(&format!("{:<width$}  ", cell, width = width));
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Reasons `CompiledRules::compile` and `CompiledRules::from_json` reject a rule set
+pub enum CompileError {
+    Load(LoadError),
+    /// (rule, regex error)
+    InvalidPattern(String, String),
+    /// The rules have this many token types, more than `TokenTypeId::MAX_TYPES`
+    TooManyTypes(usize)
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::Load(e) => write!(f, "{}", e),
+            CompileError::InvalidPattern(rule, e) => write!(f, "invalid pattern of rule {}: {}", rule, e),
+            CompileError::TooManyTypes(n) => write!(f, "{} token types, at most {} are supported", n, TokenTypeId::MAX_TYPES)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Reasons `LexerBuilder::build` can't build a lexer
+pub enum BuildError {
+    /// (rule, native) the rule's `native` matcher wasn't registered
+    UnknownMatcher(String, String)
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::UnknownMatcher(rule, native) => write!(f, "rule {} uses native matcher {}, which isn't registered", rule, native)
+        }
+    }
+}
+
+/// Builds lexers from a rule set whose rules may name native matchers, `{ "native": "raw_string" }`, which the
+/// application registers in code
+///
+/// ```
+/// use lexer_generator::{LexerBuilder, RuleMatcher, RuleSet, Scanner};
+///
+/// /// Rust raw strings, `r#"..."#` with any number of `#`
+/// struct RawString;
+///
+/// impl RuleMatcher for RawString {
+///     fn scan(&self, scanner: &mut Scanner) -> bool {
+///         if !scanner.eat("r") {
+///             return false;
+///         }
+///         let hashes = scanner.eat_while(|c| c == '#');
+///         if !scanner.eat("\"") {
+///             return false;
+///         }
+///         let close = format!("\"{}", "#".repeat(hashes));
+///         match scanner.rest().find(&close) {
+///             Some(i) => scanner.eat(&scanner.rest()[..i + close.len()]),
+///             None => false
+///         }
+///     }
+/// }
+///
+/// let rules = RuleSet::load(r#"{ "version": 2, "literals": { "raw": { "native": "raw_string" }, "id": "[a-z]+" } }"#).unwrap();
+/// let mut lexer = LexerBuilder::new(rules).register_matcher("raw_string", Box::new(RawString)).build(String::from("r##\"a\"#b\"## c")).unwrap();
+/// assert_eq!(lexer.next_token().unwrap().value, "r##\"a\"#b\"##");
+/// assert_eq!(lexer.next_token().unwrap().value, "c");
+/// ```
+pub struct LexerBuilder {
+    rules: RuleSet,
+    matchers: HashMap<String, Arc<dyn RuleMatcher>>
+}
+
+impl LexerBuilder {
+    pub fn new(rules: RuleSet) -> Self {
+        Self { rules, matchers: HashMap::new() }
+    }
+
+    /// Makes `matcher` available to rules with `"native": name`
+    pub fn register_matcher(mut self, name: &str, matcher: Box<dyn RuleMatcher>) -> Self {
+        self.matchers.insert(name.to_string(), Arc::from(matcher));
+        self
+    }
+
+    /// A lexer of `source`, failing if a rule names a matcher which wasn't registered
+    pub fn build(self, source: String) -> Result<Lexer, BuildError> {
+        let mut natives = vec![];
+        let modes = std::iter::once(&self.rules.literals).chain(self.rules.modes.values().map(|mode| &mode.literals));
+        for (mode, literals) in modes.enumerate() {
+            for (name, native) in literals.iter().filter_map(|(name, rule)| Some((name, rule.native()?))) {
+                match self.matchers.get(native) {
+                    Some(matcher) => natives.push((mode, name.clone(), matcher.clone())),
+                    None => return Err(BuildError::UnknownMatcher(name.clone(), native.clone()))
+                }
+            }
+        }
+        let mut lexer = Lexer::from_rules(self.rules, source);
+        for (mode, name, matcher) in natives {
+            lexer.add_matcher(mode, &name, matcher);
+        }
+        Ok(lexer)
+    }
+}
+
+#[derive(Clone)]
+/// Lexes tokens from source code based on JSON-parsed ruleset
+/// # Example: 
+/// ```
+/// use lexer_generator::Lexer;
+///
+/// let json = String::from(r#"{ "literals": { "number": "[0-9]+" }, "whitespace": " +" }"#);
+/// let mut lexer = Lexer::from(json, String::from("1 2   "));
+/// let mut values = vec![];
+/// while lexer.has_next_token() {
+///     values.push(lexer.next_token().unwrap().value);
+/// }
+/// // the trailing whitespace is no token
+/// assert_eq!(values, ["1", "2"]);
+/// assert!(!lexer.has_next_token());
+/// ```
+///
+pub struct Lexer {
+    /// Shared with clones of the lexer, `push_str` only copies it while a clone still holds it
+    source: Arc<String>,
+    pos: usize,
+    line_start: usize,
+    /// Column of the cursor in `column_unit`, kept up to date by `advance`
+    column: usize,
+    last_token: Option<Result<Token, ParsingError>>,
+    /// Tokens lexed ahead by peeking, in order
+    lookahead: VecDeque<Result<Token, ParsingError>>,
+    /// Tokens lexed ahead to check a `"merge"` sequence which didn't fit, in order
+    unmerged: VecDeque<Result<Token, ParsingError>>,
+    rules: Arc<CompiledRules>,
+    line: usize,
+    /// (physical line, reported line) set by the last line directive, lines after it are reported relative to it
+    line_shift: (usize, usize),
+    file: Option<String>,
+    /// Name of the source for tokens and errors, see `with_source_name`
+    source_name: Option<Arc<str>>,
+    column_unit: ColumnUnit,
+    /// Where lexing stops, the end of the source if None, see `with_range`
+    end: Option<usize>,
+    /// Modes entered by rules with `push`, innermost last, the main mode is below all of them
+    modes: Vec<usize>,
+    /// Open delimiters of `"pairs"` with where they were opened, innermost last
+    delimiters: Vec<(usize, Location)>,
+    /// Strings with `interpolate` the lexer is in, innermost last
+    interpolations: Vec<InterpolationFrame>,
+    /// (offset, line, column) of the end of the last token if it was of an `asi.after` type
+    asi_after: Option<(usize, usize, usize)>,
+    /// Type of the last token lexed other than whitespace tokens, for `when_prev_is`
+    prev: Option<TokenTypeId>,
+    /// The end of the source was reached, so it isn't scanned again and every later token is `EndOfFileError`
+    exhausted: bool,
+    /// A `ParsingError::TokenTooLong` skips the rest of its line, which `push_str` hasn't all been given yet
+    skip_line: bool,
+    /// Errors lexed so far, for `max_errors`
+    errors: usize,
+    stats: LexStats,
+    /// Ties decided by rule names, only collected after `with_tie_warnings`
+    ties: Option<Vec<TieWarning>>,
+    truncations: Vec<TruncationWarning>,
+    /// Keywords added with `add_keyword` and `add_contextual_keyword` by their text, the first which applies wins
+    keywords: HashMap<String, Vec<Keyword>>,
+    /// Maps of the preprocessors which rewrote the source, the first applied first, see `with_preprocessor`
+    preprocessed: Arc<Vec<preprocess::OffsetMap>>,
+    /// The source before the first preprocessor rewrote it
+    original: Option<Arc<str>>
+}
+
+/// Tokens ahead of a lexer from `Lexer::preview`, lexed by a copy of it
+pub struct LexerSnapshotIter<'a> {
+    /// None after the end of the source or an error
+    lexer: Option<Lexer>,
+    lexed_from: std::marker::PhantomData<&'a Lexer>
+}
+
+impl Iterator for LexerSnapshotIter<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.lexer.as_mut()?.next_token().ok();
+        if token.is_none() {
+            self.lexer = None;
+        }
+        token
+    }
+}
+
+impl std::iter::FusedIterator for LexerSnapshotIter<'_> {}
+
+#[derive(Clone, Debug)]
+/// Snapshot of where a lexer is, taken with `Lexer::checkpoint` and gone back to with `Lexer::restore`
+///
+/// It holds offsets, the line tracking and the small stacks of modes, open delimiters and interpolated strings, plus
+/// any tokens already peeked, never the source, so taking one is cheap
+pub struct LexerState {
+    pos: usize,
+    line: usize,
+    line_start: usize,
+    column: usize,
+    line_shift: (usize, usize),
+    file: Option<String>,
+    modes: Vec<usize>,
+    delimiters: Vec<(usize, Location)>,
+    interpolations: Vec<InterpolationFrame>,
+    asi_after: Option<(usize, usize, usize)>,
+    prev: Option<TokenTypeId>,
+    exhausted: bool,
+    errors: usize,
+    last_token: Option<Result<Token, ParsingError>>,
+    lookahead: VecDeque<Result<Token, ParsingError>>,
+    unmerged: VecDeque<Result<Token, ParsingError>>
+}
+
+impl LexerState {
+    /// Byte offset lexing goes on from, after any peeked tokens
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Counters collected while lexing, for profiling grammars, see `Lexer::stats`
+pub struct LexStats {
+    /// Number of tokens lexed of each type
+    pub tokens: HashMap<String, usize>,
+    /// Bytes skipped as whitespace or line directives
+    pub whitespace_bytes: usize,
+    /// Characters skipped because no rule matched them
+    pub unrecognized: usize,
+    /// Time spent matching each regex rule, only collected with the `timing` feature
+    #[cfg(feature = "timing")]
+    pub rule_time: HashMap<String, std::time::Duration>,
+    /// Time spent matching the fixed string rules, which are all searched at once
+    #[cfg(feature = "timing")]
+    pub fixed_time: std::time::Duration,
+    /// Attempts, matches and hits of each rule, only collected with the `profile` feature, see `Lexer::rule_profile`
+    #[cfg(feature = "profile")]
+    pub rules: HashMap<String, RuleProfile>
+}
+
+#[cfg(feature = "profile")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// How one rule fared while lexing, see `Lexer::rule_profile`
+pub struct RuleProfile {
+    /// Times the rule was tried, rules excluded by `when_prev_is`, `bol` and the like aren't tried
+    pub attempts: usize,
+    /// Times the rule matched, whether or not it was selected
+    pub matches: usize,
+    /// Tokens the rule produced
+    pub hits: usize,
+    /// Bytes of the tokens the rule produced
+    pub bytes: usize
+}
+
+#[derive(Clone, Debug)]
+/// Result of `Lexer::explain_next`
+pub struct MatchExplanation {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    /// Whitespace skipped before matching
+    pub skipped: String,
+    /// Every rule matching at the position with its match length, longest first
+    pub candidates: Vec<(String, usize)>,
+    pub selected: Option<String>,
+    pub reason: SelectionReason
+}
+
+#[derive(Clone, Debug)]
+/// Why a rule was selected over the other candidates
+pub enum SelectionReason {
+    /// Nothing matched, lexing fails here
+    NoMatch,
+    OnlyMatch,
+    LongestMatch,
+    /// A declared conflict decided
+    Conflict(Conflict),
+    /// Another rule matched the same length and nothing decided between them, the rule whose name sorts first won
+    Tie(String),
+}
+
+impl std::fmt::Display for SelectionReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SelectionReason::NoMatch => write!(f, "no rule matches"),
+            SelectionReason::OnlyMatch => write!(f, "only match"),
+            SelectionReason::LongestMatch => write!(f, "longest match"),
+            SelectionReason::Conflict(c) => write!(f, "conflict prefers {} over {}", c.prefer, c.over),
+            SelectionReason::Tie(other) => write!(f, "tie with {}, no conflict declared so the first by name wins", other)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Rules which matched the same text at a position where nothing but the order of their names decided, collected by
+/// lexers made `with_tie_warnings`
+pub struct TieWarning {
+    /// The rule which won, the one whose name sorts first
+    pub selected: String,
+    pub others: Vec<String>,
+    pub span: Range<usize>,
+    pub location: Location
+}
+
+impl std::fmt::Display for TieWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} and {} match the same text, {} was selected by name, declare a conflict to decide", self.location, self.selected, self.others.join(" and "), self.selected)
+    }
+}
+
+#[derive(Clone, Debug)]
+/// A keyword added with `Lexer::add_contextual_keyword`, recognized only where all of its constraints hold
+///
+/// ```
+/// use lexer_generator::{Keyword, Lexer};
+///
+/// let rules = String::from(r#"{ "literals": { "name": "[a-z]+", "dot": "\\." } }"#);
+/// let mut lexer = Lexer::from(rules, String::from("async fn async x.async"));
+/// lexer.add_contextual_keyword("async", Keyword::new("async").with_followed_by("\\s*fn\\b").unwrap());
+/// let types: Vec<String> = lexer.map(|token| token.unwrap().token_type).collect();
+/// assert_eq!(types, ["async", "name", "name", "name", "dot", "name"]);
+/// ```
+pub struct Keyword {
+    token_type: String,
+    modes: Vec<String>,
+    when_prev_is: Vec<String>,
+    /// Anchored at the end of the token
+    followed_by: Option<Regex>
+}
+
+impl Keyword {
+    /// A keyword lexed as `token_type` everywhere, until constraints are added
+    pub fn new(token_type: &str) -> Self {
+        Self { token_type: token_type.to_string(), modes: vec![], when_prev_is: vec![], followed_by: None }
+    }
+
+    /// Only recognizes the keyword in `mode`, and in the other modes given this way
+    pub fn with_mode(mut self, mode: &str) -> Self {
+        self.modes.push(mode.to_string());
+        self
+    }
+
+    /// Only recognizes the keyword right after a token of type `token_type`, or of the other types given this way,
+    /// like a rule's `when_prev_is`
+    pub fn with_prev(mut self, token_type: &str) -> Self {
+        self.when_prev_is.push(token_type.to_string());
+        self
+    }
+
+    /// Only recognizes the keyword where `pattern` matches the text right after it, whitespace included, like
+    /// `\s*fn\b` for an `async` before `fn`
+    pub fn with_followed_by(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.followed_by = Some(Regex::new(&format!("^(?:{})", pattern))?);
+        Ok(self)
+    }
+
+    pub fn token_type(&self) -> &str {
+        &self.token_type
+    }
+
+    /// Whether the constraints hold in `mode`, after a token of type `prev`, with `after` following the keyword
+    fn applies(&self, mode: &str, prev: Option<&str>, after: &str) -> bool {
+        (self.modes.is_empty() || self.modes.iter().any(|m| m == mode))
+            && (self.when_prev_is.is_empty() || prev.is_some_and(|prev| self.when_prev_is.iter().any(|t| t == prev)))
+            && self.followed_by.as_ref().is_none_or(|re| re.is_match(after))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A token whose value was cut to its rule's `max_len` because the rule has `truncate`, see
+/// `Lexer::truncation_warnings`
+pub struct TruncationWarning {
+    pub rule: String,
+    pub max_len: usize,
+    /// The whole match, the token's `raw` text isn't cut
+    pub span: Range<usize>,
+    pub location: Location
+}
+
+impl std::fmt::Display for TruncationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} cut to {} characters", self.location, self.rule, self.max_len)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ParsingError {
+    EndOfFileError,
+    /// (character, where it is) no rule matches at the character
+    UnrecognizedPatternError(String, Location),
+    /// Lexing was stopped through a `CancelToken`
+    Cancelled,
+    /// A closer of `"pairs"` which doesn't close the innermost open delimiter, `expected` is the closer which would
+    /// fit, None if nothing is open
+    UnbalancedDelimiter { found: String, expected: Option<String>, location: Location },
+    /// An opener of `"pairs"` which is still open at the end of the source, reported once for every such opener,
+    /// innermost first, before `EndOfFileError`
+    UnclosedDelimiter { opener: String, location: Location },
+    /// An escape which `unescape` can't decode, with its byte range in the source and where it starts, the token it
+    /// is in is skipped
+    InvalidEscape { escape: String, span: Range<usize>, location: Location },
+    /// A token with more characters than its rule's `max_len`, the token is skipped
+    MaxLenExceeded { rule: String, max_len: usize, span: Range<usize>, location: Location },
+    /// Text pushed with `Lexer::push_str` which is still no complete token after `limit` bytes, see `max_token_bytes`,
+    /// the text is skipped
+    TokenTooLong { limit: usize, location: Location },
+    /// More errors than `max_errors`, `count` of them, the last at `location`, after which lexing ends
+    TooManyErrors { count: usize, location: Location }
+}
+
+impl std::fmt::Display for ParsingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParsingError::EndOfFileError => write!(f, "unexpected end of input"),
+            ParsingError::UnrecognizedPatternError(s, location) => write!(f, "{}: unrecognized input {:?}", location, s),
+            ParsingError::Cancelled => write!(f, "cancelled"),
+            ParsingError::UnbalancedDelimiter { found, expected: Some(expected), location } => write!(f, "{}: unbalanced {}, expected {}", location, found, expected),
+            ParsingError::UnbalancedDelimiter { found, expected: None, location } => write!(f, "{}: unbalanced {}, nothing is open", location, found),
+            ParsingError::UnclosedDelimiter { opener, location } => write!(f, "{}: {} is never closed", location, opener),
+            ParsingError::InvalidEscape { escape, location, .. } => write!(f, "{}: invalid escape {}", location, escape),
+            ParsingError::MaxLenExceeded { rule, max_len, location, .. } => write!(f, "{}: {} longer than {} characters", location, rule, max_len),
+            ParsingError::TokenTooLong { limit, location } => write!(f, "{}: token longer than {} bytes", location, limit),
+            ParsingError::TooManyErrors { count, location } => write!(f, "{}: too many errors, stopped after {}", location, count)
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+/// Handle for stopping `Lexer::tokenize_with_cancel` from another thread, clones share the same flag
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[allow(dead_code)]
+impl Lexer {
+    /// Generates a lexer from JSON
+    pub fn from(json: String, source: String) -> Self {
+        Self::with_rules(Arc::new(CompiledRules::from_string(json)), source)
+    }
+
+    /// Generates a lexer from JSON with the rules tagged with any of the `features`, see `RuleSet::with_features`
+    pub fn from_json_with_features(json: String, features: &[&str], source: String) -> Self {
+        Self::from_rules(RuleSet::from_string(json).with_features(features), source)
+    }
+
+    /// Generates a lexer from an already parsed rule set
+    pub fn from_rules(rules: RuleSet, source: String) -> Self {
+        Self::with_rules(Arc::new(CompiledRules::from(rules)), source)
+    }
+
+    /// Initializes lexer without JSON parsing
+    pub fn from_args(literals: HashMap<String, String>, whitespace: String, source: String) -> Self {
+        Self::with_rules(Arc::new(CompiledRules::from(RuleSet { literals: literals.into_iter().map(|(k, v)| (k, Rule::from(v))).collect(), whitespace: Some(whitespace), ..Default::default() })), source)
+    }
+
+    /// Like `from_args`, but invalid patterns are returned as errors instead of panicking
+    pub fn try_from_args(literals: HashMap<String, String>, whitespace: String, source: String) -> Result<Self, ArgsError> {
+        Self::try_from_pairs(literals.into_iter().collect(), whitespace, source)
+    }
+
+    /// Initializes a lexer from (name, pattern) pairs without JSON parsing, a name given twice is an error rather than
+    /// one pattern silently replacing the other
+    ///
+    /// ```
+    /// use lexer_generator::{ArgsError, Lexer};
+    ///
+    /// let rules = vec![(String::from("number"), String::from("[0-9]+")), (String::from("number"), String::from("[0-9.]+"))];
+    /// let error = Lexer::try_from_pairs(rules, String::from("\\s+"), String::from("1.5")).err().unwrap();
+    /// assert_eq!(error, ArgsError::DuplicateRule(String::from("number")));
+    /// ```
+    pub fn try_from_pairs(literals: Vec<(String, String)>, whitespace: String, source: String) -> Result<Self, ArgsError> {
+        Regex::new(&whitespace).map_err(|e| ArgsError::InvalidWhitespace(e.to_string()))?;
+        let mut rules = BTreeMap::new();
+        for (name, pattern) in literals {
+            let rule = Rule::from(pattern);
+            if let Err(e) = Regex::new(&rule.pattern()) {
+                return Err(ArgsError::InvalidPattern(name, e.to_string()));
+            }
+            if rules.contains_key(&name) {
+                return Err(ArgsError::DuplicateRule(name));
+            }
+            rules.insert(name, rule);
+        }
+        Ok(Self::with_rules(Arc::new(CompiledRules::from(RuleSet { literals: rules, whitespace: Some(whitespace), ..Default::default() })), source))
+    }
+
+    /// A lexer of `source` with rules compiled once for many lexers
+    pub fn from_compiled(rules: Arc<CompiledRules>, source: String) -> Self {
+        Self::with_rules(rules, source)
+    }
+
+    fn with_rules(rules: Arc<CompiledRules>, source: String) -> Self {
+        let mut lexer = Self {
+            source: Arc::new(source),
+            pos: 0,
+            line_start: 0,
+            column: 0,
+            last_token: None,
+            lookahead: VecDeque::new(),
+            unmerged: VecDeque::new(),
+            rules,
+            line: 0,
+            line_shift: (0, 0),
+            file: None,
+            source_name: None,
+            column_unit: ColumnUnit::default(),
+            end: None,
+            modes: vec![],
+            delimiters: vec![],
+            interpolations: vec![],
+            asi_after: None,
+            prev: None,
+            exhausted: false,
+            skip_line: false,
+            errors: 0,
+            stats: LexStats::default(),
+            ties: None,
+            truncations: vec![],
+            keywords: HashMap::new(),
+            preprocessed: Arc::new(vec![]),
+            original: None
+        };
+        lexer.skip_preamble();
+        lexer
+    }
+
+    /// Rewrites the source before lexing with a preprocessor, which returns the replacements to make in it, like the
+    /// ones of the `preprocess` module, for files whose bytes differ from what the grammar expects
+    ///
+    /// Tokens are lexed from the rewritten text, so their values, lines and columns are those of it, while their spans
+    /// and the offsets of errors refer to the original source. A token starting or ending inside a replacement, like
+    /// inside an expanded tab, covers all of the text it replaced. Preprocessors apply in the order they are added, and
+    /// before `with_range`.
+    ///
+    /// ```
+    /// use lexer_generator::{preprocess, Lexer};
+    ///
+    /// let rules = String::from(r#"{ "version": 2, "literals": { "word": "[a-z]+", "newline": "\n" }, "whitespace": " +" }"#);
+    /// let mut lexer = Lexer::from(rules, String::from("\tab  \r\ncd")).with_preprocessor(preprocess::strip_cr)
+    ///     .with_preprocessor(preprocess::trim_trailing_whitespace).with_preprocessor(preprocess::expand_tabs(4));
+    /// let ab = lexer.next_token().unwrap();
+    /// assert_eq!((ab.column, ab.span), (4, 1..3));
+    /// assert_eq!(lexer.next_token().unwrap().span, 6..7);
+    /// assert_eq!(lexer.next_token().unwrap().span, 7..9);
+    /// ```
+    pub fn with_preprocessor(mut self, preprocessor: impl Fn(&str) -> Vec<(Range<usize>, String)>) -> Self {
+        let (source, map) = preprocess::OffsetMap::apply(&self.source, preprocessor(&self.source));
+        let original = std::mem::replace(&mut self.source, Arc::new(source));
+        self.original.get_or_insert_with(|| Arc::from(original.as_str()));
+        Arc::make_mut(&mut self.preprocessed).push(map);
+        (self.pos, self.line, self.line_start, self.column) = (0, 0, 0, 0);
+        self.skip_preamble();
+        self
+    }
+
+    /// The source before any preprocessor rewrote it
+    fn original_source(&self) -> &str {
+        self.original.as_deref().unwrap_or(&self.source)
+    }
+
+    /// Span in the original source of a span of the preprocessed one
+    fn original_span(&self, span: Range<usize>) -> Range<usize> {
+        self.preprocessed.iter().rev().fold(span, |span, map| map.span(span))
+    }
+
+    /// Offset in the original source of a start in the preprocessed one
+    fn original_start(&self, offset: usize) -> usize {
+        self.preprocessed.iter().rev().fold(offset, |offset, map| map.start(offset))
+    }
+
+    /// Offset in the original source of an end in the preprocessed one
+    fn original_end(&self, offset: usize) -> usize {
+        self.preprocessed.iter().rev().fold(offset, |offset, map| map.end(offset))
+    }
+
+    /// Token or error with the positions of the original source, if the source was preprocessed
+    fn original(&self, token: Result<Token, ParsingError>) -> Result<Token, ParsingError> {
+        if self.preprocessed.is_empty() {
+            return token;
+        }
+        let location = |location: Location| Location { offset: self.original_start(location.offset), ..location };
+        match token {
+            Ok(token) => Ok(Token { span: self.original_span(token.span.clone()), ..token }),
+            Err(ParsingError::UnrecognizedPatternError(text, at)) => Err(ParsingError::UnrecognizedPatternError(text, location(at))),
+            Err(ParsingError::UnbalancedDelimiter { found, expected, location: at }) => Err(ParsingError::UnbalancedDelimiter { found, expected, location: location(at) }),
+            Err(ParsingError::UnclosedDelimiter { opener, location: at }) => Err(ParsingError::UnclosedDelimiter { opener, location: location(at) }),
+            Err(ParsingError::InvalidEscape { escape, span, location: at }) => Err(ParsingError::InvalidEscape { escape, span: self.original_span(span), location: location(at) }),
+            Err(ParsingError::MaxLenExceeded { rule, max_len, span, location: at }) => Err(ParsingError::MaxLenExceeded { rule, max_len, span: self.original_span(span), location: location(at) }),
+            Err(ParsingError::TokenTooLong { limit, location: at }) => Err(ParsingError::TokenTooLong { limit, location: location(at) }),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Skips a byte order mark and shebang line at the start of the source, if the rules ask for it
+    fn skip_preamble(&mut self) {
+        if self.rules.skip_bom && self.rest().starts_with('\u{feff}') {
+            self.advance('\u{feff}'.len_utf8());
+        }
+        if self.rules.skip_shebang && self.rest().starts_with("#!") {
+            let len = self.rest().find('\n').map_or(self.rest().len(), |end| end + 1);
+            self.advance(len);
+        }
+    }
+
+    /// Appends `chunk` to the source and returns every token which is complete, the last token lexed from the text
+    /// pushed so far is held back since the next chunk may continue it, like a `/` which turns out to start a `/*`
+    /// comment
+    ///
+    /// A token is returned once the token after it was lexed, so a token starting in the last token's text and
+    /// reaching into the next chunk can still be missed: with `x /*` pushed before ` c */`, `/` is returned before
+    /// `*`. Pushing whole lines avoids it for grammars whose tokens span several of their tokens' text, like block
+    /// comments.
+    ///
+    /// An unrecognized character is only reported once the rest of its line has arrived, it might start a token which
+    /// isn't complete yet, like a string literal. Lexers for pushed source start from an empty source,
+    /// `Lexer::from_rules(rules, String::new())`.
+    pub fn push_str(&mut self, chunk: &str) -> Vec<Result<Token, ParsingError>> {
+        self.feed(chunk, false)
+    }
+
+    /// Returns the tokens held back by `push_str`, once there is no more source to push
+    pub fn finish(&mut self) -> Vec<Result<Token, ParsingError>> {
+        self.feed("", true)
+    }
+
+    pub(crate) fn feed(&mut self, chunk: &str, finished: bool) -> Vec<Result<Token, ParsingError>> {
+        if !chunk.is_empty() {
+            Arc::make_mut(&mut self.source).push_str(chunk);
+        }
+        let mut out = vec![];
+        if self.skip_line {
+            self.skip_too_long(0);
+        }
+        if self.pos == 0 {
+            // a shebang line has to be complete before it can be skipped
+            if !finished && !self.source.contains('\n') && (self.rules.skip_bom || self.rules.skip_shebang) {
+                return out;
+            }
+            self.skip_preamble();
+        }
+        // the last token lexed with the state before it and the empty tokens after it, like inserted semicolons, only
+        // returned once a token with text after them was lexed
+        let mut last: Option<(LexerState, LexStats, Vec<Token>)> = None;
+        loop {
+            let saved = (self.checkpoint(), self.stats.clone());
+            match self.parse_next() {
+                Err(ParsingError::EndOfFileError) if finished => break,
+                Ok(token) if finished => out.push(Ok(token)),
+                Ok(token) => match &mut last {
+                    Some((_, _, tokens)) if token.span.is_empty() => tokens.push(token),
+                    _ => if let Some((_, _, previous)) = last.replace((saved.0, saved.1, vec![token])) {
+                        out.extend(previous.into_iter().map(Ok));
+                    }
+                },
+                Err(e) if finished || self.rest().contains('\n') => {
+                    out.extend(last.take().into_iter().flat_map(|(_, _, previous)| previous).map(Ok));
+                    out.push(Err(e));
+                }
+                held => {
+                    // where text which isn't complete yet starts, None if only whitespace follows the last token
+                    let start = match (&held, &last) {
+                        (Err(ParsingError::UnrecognizedPatternError(_, location)), _) => Some(location.offset),
+                        (_, Some((_, _, tokens))) if tokens[0].span.end < self.end() => None,
+                        (_, Some((_, _, tokens))) => Some(tokens[0].span.start),
+                        _ => None
+                    };
+                    let held_from = last.as_ref().map_or(start.unwrap_or(self.end()), |(_, _, tokens)| tokens[0].span.start);
+                    let limit = self.rules.max_token_bytes.filter(|limit| self.end() - held_from > *limit);
+                    let (state, stats) = match last.take() {
+                        // too much is held back, so the last token counts as complete unless it is what is too long
+                        Some((_, _, tokens)) if limit.is_some() && start != Some(tokens[0].span.start) => {
+                            out.extend(tokens.into_iter().map(Ok));
+                            saved
+                        }
+                        Some((state, stats, _)) => (state, stats),
+                        None => saved
+                    };
+                    self.restore(state);
+                    self.stats = stats;
+                    match limit.zip(start).filter(|(limit, start)| self.end() - start > *limit) {
+                        Some((limit, start)) => {
+                            self.advance(start - self.pos);
+                            out.push(Err(ParsingError::TokenTooLong { limit, location: self.location() }));
+                            self.skip_too_long(limit);
+                        }
+                        None => break
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Skips the start of a token longer than `limit` bytes as `recover` asks, the rest of the source is lexed as usual
+    fn skip_too_long(&mut self, limit: usize) {
+        let rest = self.rest();
+        let line_end = rest.find(['\r', '\n']);
+        let len = match self.rules.recover {
+            Recover::NextChar => (limit.max(1)..=rest.len()).find(|len| rest.is_char_boundary(*len)).unwrap_or(rest.len()),
+            Recover::NextLine => line_end.unwrap_or(rest.len()),
+            Recover::None => rest.len()
+        };
+        // the rest of the line may only arrive with a later chunk
+        self.skip_line = self.rules.recover == Recover::NextLine && line_end.is_none();
+        self.exhausted |= self.rules.recover == Recover::None;
+        self.advance(len);
+    }
+
+    /// Snapshot of the position, line tracking, mode, delimiter and interpolation stacks and peeked tokens, to
+    /// backtrack to with `restore`
+    ///
+    /// ```
+    /// use lexer_generator::Lexer;
+    ///
+    /// let rules = String::from(r#"{ "literals": { "word": "[a-z]+", "quote": { "literal": "'", "push": "quoted" } },
+    ///     "modes": { "quoted": { "literals": { "text": "[^']+", "end": { "literal": "'", "pop": true } }, "whitespace": null } } }"#);
+    /// let mut lexer = Lexer::from(rules, String::from("a 'b c' d"));
+    /// lexer.next_token().unwrap();
+    /// lexer.next_token().unwrap();
+    /// let state = lexer.checkpoint();
+    /// assert_eq!(lexer.next_token().unwrap().token_type, "text");
+    /// assert_eq!(lexer.next_token().unwrap().token_type, "end");
+    /// lexer.restore(state);
+    /// assert_eq!(lexer.current_mode(), "quoted");
+    /// assert_eq!(lexer.next_token().unwrap().value, "b c");
+    /// ```
+    pub fn checkpoint(&self) -> LexerState {
+        LexerState {
+            pos: self.pos,
+            line: self.line,
+            line_start: self.line_start,
+            column: self.column,
+            line_shift: self.line_shift,
+            file: self.file.clone(),
+            modes: self.modes.clone(),
+            delimiters: self.delimiters.clone(),
+            interpolations: self.interpolations.clone(),
+            asi_after: self.asi_after,
+            prev: self.prev,
+            exhausted: self.exhausted,
+            errors: self.errors,
+            last_token: self.last_token.clone(),
+            lookahead: self.lookahead.clone(),
+            unmerged: self.unmerged.clone()
+        }
+    }
+
+    /// Goes back to a snapshot taken with `checkpoint` since the source was last replaced, `stats` keep counting the
+    /// tokens lexed after it
+    pub fn restore(&mut self, state: LexerState) {
+        self.pos = state.pos;
+        self.line = state.line;
+        self.line_start = state.line_start;
+        self.column = state.column;
+        self.line_shift = state.line_shift;
+        self.file = state.file;
+        self.modes = state.modes;
+        self.delimiters = state.delimiters;
+        self.interpolations = state.interpolations;
+        self.asi_after = state.asi_after;
+        self.prev = state.prev;
+        self.exhausted = state.exhausted;
+        self.errors = state.errors;
+        self.last_token = state.last_token;
+        self.lookahead = state.lookahead;
+        self.unmerged = state.unmerged;
+    }
+
+    /// Restarts the lexer on a new source, keeping its rules
+    fn reset(&mut self, source: String) {
+        *self = Self::with_rules(self.rules.clone(), source);
+    }
+
+    /// The source which hasn't been lexed yet
+    fn rest(&self) -> &str {
+        &self.source[self.pos..self.end()]
+    }
+
+    fn ch(&self) -> char {
+        self.rest().chars().next().unwrap()
+    }
 
-use std::collections::HashMap;
-use regex::*;
+    /// Moves the cursor forward by `len` bytes, keeping track of lines
+    fn advance(&mut self, len: usize) {
+        for (i, c) in self.source[self.pos..self.pos + len].char_indices() {
+            if c == '\n' {
+                self.line += 1;
+                self.line_start = self.pos + i + 1;
+                self.column = 0;
+            } else {
+                self.column += self.column_unit.width(c);
+            }
+        }
+        self.pos += len;
+    }
 
-#[derive(Serialize, Deserialize)]
-struct RuleSet { // Parsed rule set from JSON file
-    literals: HashMap<String, String>,
-    whitespace: String
-}
+    /// Line reported for the cursor, which differs from the physical line after a line directive
+    fn reported_line(&self) -> usize {
+        (self.line + self.line_shift.1).saturating_sub(self.line_shift.0)
+    }
 
-#[derive(Clone)]
-struct RegexRuleSet { // Converting above into regex
-    literals: HashMap<String, Regex>,
-    whitespace: Regex
-}
+    /// Token types whose rules declare `category`, sorted by name
+    pub fn kinds_in_category(&self, category: &str) -> Vec<&str> {
+        let mut kinds: Vec<&str> = self.rules.categories.iter().filter(|(_, c)| c.as_ref() == category).map(|(name, _)| name.as_str()).collect();
+        kinds.sort();
+        kinds
+    }
 
-#[allow(dead_code)]
-impl RegexRuleSet {
-    fn from(ruleset: RuleSet) -> Self {
-        Self {
-            // list of literal values, operators, keywords, etc., "name" : "regex pattern"
-            literals: {
-                let mut hm: HashMap<String, Regex> = HashMap::new();
-                for (k, v) in ruleset.literals {
-                    hm.insert(k, Regex::new(&v).unwrap());
-                }
-                hm
-            },
-            whitespace: Regex::new(&ruleset.whitespace).unwrap()
-        }
+    /// Id of the token type `name`, or None if the rule set has no such rule
+    pub fn type_id(&self, name: &str) -> Option<TokenTypeId> {
+        self.rules.ids.get(name).copied()
     }
-    fn from_string(json: String) -> Self {
-        Self::from(serde_json::from_str::<RuleSet>(&json).unwrap())
+
+    /// Counters collected since the lexer was created or `reset_stats` was called
+    ///
+    /// Copies of the lexer made by `explain_next` and `dump` don't add to them
+    pub fn stats(&self) -> &LexStats {
+        &self.stats
     }
-}
 
-#[derive(Clone)]
-/// Tokens are parsed from source code, their types are defined by the Lexer's ruleset
-pub struct Token {
-    pub token_type: String,
-    pub value: String,
-    pub line: usize
-}
+    pub fn reset_stats(&mut self) {
+        self.stats = LexStats::default();
+    }
 
-#[allow(dead_code)]
-impl Token {
-    /// Returns true if token.token_type matches any of the types
-    pub fn is<T: ToString>(&self, types: Vec<T>) -> bool {
-        {
-            let mut v = vec![];
-            for t in types {
-                v.push(t.to_string());
-            }
-            v
-        }.contains(&self.token_type)
+    /// How often each rule was tried, matched and produced a token, with the bytes of its tokens, only collected with
+    /// the `profile` feature
+    ///
+    /// Rules which are tried everywhere but rarely match are worth restricting with `when_prev_is` or `bol`, rules
+    /// which match but never produce a token lose every time to another rule, and rules missing from the profile were
+    /// never even tried on the input.
+    ///
+    /// ```
+    /// use lexer_generator::Lexer;
+    ///
+    /// let mut lexer = Lexer::from(String::from(r#"{ "literals": { "number": "[0-9]+", "name": "[a-z]+", "if": "if" } }"#), String::from("if x 1"));
+    /// while lexer.next_token().is_ok() {}
+    /// let profile = lexer.rule_profile();
+    /// assert_eq!((profile["name"].attempts, profile["name"].matches, profile["name"].hits), (3, 2, 1));
+    /// assert_eq!((profile["number"].hits, profile["number"].bytes), (1, 1));
+    /// ```
+    #[cfg(feature = "profile")]
+    pub fn rule_profile(&self) -> &HashMap<String, RuleProfile> {
+        &self.stats.rules
     }
-}
 
-impl std::fmt::Display for Token {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}({})", self.token_type, self.value)
+    /// Collects a `TieWarning` wherever rules match the same text and only the order of their names decides, so
+    /// grammars relying on it by accident show up during development
+    ///
+    /// ```
+    /// use lexer_generator::Lexer;
+    ///
+    /// let rules = String::from(r#"{ "literals": { "word": "[a-z]+", "keyword": "if|else" } }"#);
+    /// let mut lexer = Lexer::from(rules, String::from("if x")).with_tie_warnings();
+    /// while lexer.next_token().is_ok() {}
+    /// let ties = lexer.tie_warnings();
+    /// assert_eq!((ties[0].selected.as_str(), ties[0].others.as_slice(), ties[0].span.clone()), ("keyword", &[String::from("word")][..], 0..2));
+    /// ```
+    pub fn with_tie_warnings(mut self) -> Self {
+        self.ties = Some(vec![]);
+        self
     }
-}
 
-#[derive(Clone)]
-/// Lexes tokens from source code based on JSON-parsed ruleset
-/// # Example: 
-/// ```
-/// let mut lexer = Lexer::from(json, source);
-/// while !lexer.done() {
-///    println!("{}", lexer.next_token().unwrap());
-///}
-///```
-///
-pub struct Lexer {
-    source: String,
-    last_token: Option<Result<Token, ParsingError>>,
-    cache: Option<Result<Token, ParsingError>>,
-    rules: RegexRuleSet,
-    line: usize
-}
+    /// Ties collected since `with_tie_warnings` or the last `take_tie_warnings`, in the order the tokens were lexed
+    pub fn tie_warnings(&self) -> &[TieWarning] {
+        self.ties.as_deref().unwrap_or_default()
+    }
 
-#[derive(Clone, Debug)]
-pub enum ParsingError {
-    EndOfFileError,
-    UnrecognizedPatternError(String),
-}
+    pub fn take_tie_warnings(&mut self) -> Vec<TieWarning> {
+        self.ties.as_mut().map(std::mem::take).unwrap_or_default()
+    }
 
-#[allow(dead_code)]
-impl Lexer {
-    /// Generates a lexer from JSON
-    pub fn from(json: String, source: String) -> Self {
-        Self {
-            source: source,
-            last_token: None,
-            cache: None,
-            rules: RegexRuleSet::from_string(json),
-            line: 0
+    /// Tokens cut to the `max_len` of their rule since the lexer was created or the last `take_truncation_warnings`
+    ///
+    /// ```
+    /// use lexer_generator::Lexer;
+    ///
+    /// let rules = String::from(r#"{ "literals": { "identifier": { "pattern": "[a-z]+", "max_len": 4, "truncate": true } } }"#);
+    /// let mut lexer = Lexer::from(rules, String::from("abcdef"));
+    /// let token = lexer.next_token().unwrap();
+    /// assert_eq!((token.value.as_str(), token.raw.as_str()), ("abcd", "abcdef"));
+    /// assert_eq!(lexer.truncation_warnings()[0].span, 0..6);
+    /// ```
+    pub fn truncation_warnings(&self) -> &[TruncationWarning] {
+        &self.truncations
+    }
+
+    pub fn take_truncation_warnings(&mut self) -> Vec<TruncationWarning> {
+        std::mem::take(&mut self.truncations)
+    }
+
+    /// Names the source, like a path or `"repl"`, so tokens and errors tell which input they came from in applications
+    /// lexing several sources
+    ///
+    /// ```ignore
+    /// let lexer = Lexer::from(json, source).with_source_name("config.dsl");
+    /// ```
+    pub fn with_source_name(mut self, name: &str) -> Self {
+        self.source_name = Some(Arc::from(name));
+        self
+    }
+
+    pub fn source_name(&self) -> Option<&str> {
+        self.source_name.as_deref()
+    }
+
+    /// Lexes tokens of type `name` with a hand-written matcher in the main mode, replacing the JSON rule of that name
+    /// if there is one, while its options like `category` or `when_prev_is` still apply
+    ///
+    /// The matcher competes with the other rules like a regex would, by length and conflicts. Like `add_keyword`, it
+    /// panics if `name` is a new token type and there is no id left for it.
+    pub fn with_matcher(mut self, name: &str, matcher: Box<dyn RuleMatcher>) -> Self {
+        self.add_matcher(0, name, Arc::from(matcher));
+        self
+    }
+
+    /// Matches rule `name` of mode `mode` with `matcher`, instead of its regex if it has one
+    fn add_matcher(&mut self, mode: usize, name: &str, matcher: Arc<dyn RuleMatcher>) {
+        self.add_type(name);
+        let rules = Arc::make_mut(&mut self.rules);
+        let mode = &mut rules.modes[mode];
+        mode.literals.retain(|(n, _)| n != name);
+        mode.matchers.retain(|(n, _)| n != name);
+        mode.matchers.push((name.to_string(), matcher));
+    }
+
+    /// Gives token type `name` an id if the rules don't have one for it yet, panicking if every id is taken
+    fn add_type(&mut self, name: &str) {
+        if !self.rules.ids.contains_key(name) {
+            let rules = Arc::make_mut(&mut self.rules);
+            let id = TokenTypeId::from_index(rules.names.len()).unwrap_or_else(|| panic!("more than {} token types", TokenTypeId::MAX_TYPES));
+            rules.ids.insert(name.to_string(), id);
+            rules.names.push(Arc::from(name));
         }
     }
 
-    /// Initializes lexer without JSON parsing
-    pub fn from_args(literals: HashMap<String, String>, whitespace: String, source: String) -> Self {
-        Self {
-            source: source,
-            last_token: None,
-            cache: None,
-            rules: RegexRuleSet::from(RuleSet { literals: literals, whitespace: whitespace } ),
-            line: 0
+    /// Lexes tokens whose whole text is `text` as `token_type` from the next token on, like contextual keywords a
+    /// plugin declares, without recompiling any rule
+    ///
+    /// Some rule still has to match the text, typically the identifier rule, the keyword only changes the type of
+    /// its token. A keyword added again replaces the type it had, and any contextual keywords of the same text.
+    /// Panics if `token_type` is a new type and the rules already have `TokenTypeId::MAX_TYPES` of them.
+    ///
+    /// ```
+    /// use lexer_generator::Lexer;
+    ///
+    /// let mut lexer = Lexer::from(String::from(r#"{ "literals": { "name": "[a-z]+" } }"#), String::from("await x await"));
+    /// assert_eq!(lexer.next_token().unwrap().token_type, "name");
+    /// lexer.add_keyword("await", "await");
+    /// assert_eq!(lexer.next_token().unwrap().token_type, "name");
+    /// assert_eq!(lexer.next_token().unwrap().token_type, "await");
+    /// assert!(lexer.remove_keyword("await"));
+    /// ```
+    pub fn add_keyword(&mut self, text: &str, token_type: &str) {
+        self.add_type(token_type);
+        self.keywords.insert(text.to_string(), vec![Keyword::new(token_type)]);
+    }
+
+    /// Like `add_keyword`, but `text` is only lexed as the keyword where its constraints hold, like `async` before
+    /// `fn` or `get` in a class body mode
+    ///
+    /// Several keywords can be added for one text, the first added whose constraints hold decides the type.
+    pub fn add_contextual_keyword(&mut self, text: &str, keyword: Keyword) {
+        self.add_type(&keyword.token_type);
+        self.keywords.entry(text.to_string()).or_default().push(keyword);
+    }
+
+    /// Stops lexing `text` as a keyword added with `add_keyword` or `add_contextual_keyword`, false if it wasn't one
+    pub fn remove_keyword(&mut self, text: &str) -> bool {
+        self.keywords.remove(text).is_some()
+    }
+
+    /// Lexes with `rules` from the next token on, for tools which reload a grammar while it is being edited
+    ///
+    /// Modes, open delimiters of `"pairs"` and the previous token's type are carried over by name: the mode stack is
+    /// cut at the first mode the new rules don't have and delimiters of pairs they don't have are dropped. Strings
+    /// with `interpolate` are left, matchers added with `with_matcher` or a `LexerBuilder` are kept in modes of the
+    /// same name, so are keywords added with `add_keyword`, and tokens already peeked stay as the old rules lexed them.
+    ///
+    /// ```
+    /// use lexer_generator::{CompiledRules, Lexer};
+    /// use std::sync::Arc;
+    ///
+    /// let mut lexer = Lexer::from(String::from(r#"{ "literals": { "word": "[a-z]+" } }"#), String::from("let x"));
+    /// assert_eq!(lexer.next_token().unwrap().token_type, "word");
+    /// let edited = CompiledRules::from_json(r#"{ "literals": { "word": "[a-z]+", "let": "let" } }"#).unwrap();
+    /// lexer.swap_rules(Arc::new(edited));
+    /// assert_eq!(lexer.next_token().unwrap().token_type, "word");
+    /// ```
+    pub fn swap_rules(&mut self, rules: Arc<CompiledRules>) {
+        let old = std::mem::replace(&mut self.rules, rules);
+        let mode_id = |rules: &CompiledRules, name: &str| rules.mode_names.iter().position(|n| n == name);
+        self.modes = self.modes.iter().map_while(|m| mode_id(&self.rules, &old.mode_names[*m])).collect();
+        let pairs = &self.rules.pairs;
+        self.delimiters = self.delimiters.drain(..).filter_map(|(pair, location)| Some((pairs.iter().position(|p| *p == old.pairs[pair])?, location))).collect();
+        self.interpolations.clear();
+        self.prev = self.prev.and_then(|id| self.rules.ids.get(old.names[id.index()].as_ref()).copied());
+        for (mode, rules) in old.modes.iter().enumerate() {
+            if let Some(mode) = mode_id(&self.rules, &old.mode_names[mode]) {
+                for (name, matcher) in &rules.matchers {
+                    self.add_matcher(mode, name, matcher.clone());
+                }
+            }
+        }
+        for token_type in self.keywords.values().flatten().map(|k| k.token_type.clone()).collect::<Vec<_>>() {
+            self.add_type(&token_type);
         }
     }
 
-    fn ch(&self) -> char {
-        (&self.source).as_bytes()[0] as char
+    /// Counts the columns of tokens and errors in `unit` rather than characters, e.g. `ColumnUnit::Utf16` for
+    /// language servers
+    pub fn with_column_unit(mut self, unit: ColumnUnit) -> Self {
+        self.column_unit = unit;
+        self.column = unit.measure(&self.source[self.line_start..self.pos]);
+        self
+    }
+
+    /// Lexes only `range` of the source, like a code block inside a Markdown document, while positions stay those
+    /// of the whole source
+    ///
+    /// ```ignore
+    /// let lexer = Lexer::from(json, markdown).with_range(block.start..block.end);
+    /// ```
+    ///
+    /// Patterns only see the text inside the range. A byte order mark or shebang is only skipped if the range starts
+    /// at 0.
+    pub fn with_range(mut self, range: Range<usize>) -> Self {
+        assert!(range.start <= range.end && range.end <= self.source.len(), "range {:?} is outside the source", range);
+        assert!(self.source.is_char_boundary(range.start) && self.source.is_char_boundary(range.end), "range {:?} splits a character", range);
+        (self.pos, self.line, self.line_start, self.column, self.end) = (0, 0, 0, 0, Some(range.end));
+        self.advance(range.start);
+        if range.start == 0 {
+            self.skip_preamble();
+        }
+        self
+    }
+
+    /// Where lexing stops
+    fn end(&self) -> usize {
+        self.end.unwrap_or(self.source.len())
+    }
+
+    /// Position of the cursor
+    fn location(&self) -> Location {
+        Location { source_name: self.source_name.clone(), line: self.reported_line(), column: self.column(), offset: self.pos }
+    }
+
+    /// File named by the last line directive, if any
+    pub fn current_file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// Name of the mode the next token is lexed in, `MAIN_MODE` unless a rule pushed another one
+    pub fn current_mode(&self) -> &str {
+        &self.rules.mode_names[self.mode()]
+    }
+
+    fn mode(&self) -> usize {
+        self.modes.last().copied().unwrap_or(0)
+    }
+
+    fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Applies a line directive at the cursor, it only counts as the first thing on a line
+    fn line_directive(&mut self) -> bool {
+        if !self.source[self.line_start..self.pos].trim().is_empty() {
+            return false;
+        }
+        let captures = match self.rules.line_directive.as_ref().and_then(|re| re.captures(self.rest())) {
+            Some(captures) => captures,
+            None => return false
+        };
+        let mat = captures.get(0).unwrap();
+        let line = match captures.name("line").and_then(|l| l.as_str().parse::<usize>().ok()) {
+            Some(line) if mat.end() > 0 => line,
+            _ => return false
+        };
+        let (len, file) = (mat.end(), captures.name("file").map(|f| f.as_str().to_string()));
+        trace!("{}:0 line directive to line {} of {:?}", self.line, line, file);
+        let physical = self.line;
+        self.advance(len);
+        // directives count lines from 1, tokens from 0
+        self.line_shift = (physical + 1, line.saturating_sub(1));
+        if file.is_some() {
+            self.file = file;
+        }
+        true
     }
 
+    /// Skips whitespace matches (and line directives) until the whitespace pattern no longer matches at the cursor
     fn skip_whitespace(&mut self) {
-        let mat = match self.rules.whitespace.find(&self.source) { Some(a) => (a.start() as i32, a.end() as i32), None => (-1, -1)};
-        if mat.0 == 0 {
-            for _i in mat.0..mat.1 {
-                match self.source.remove(0) {
-                    '\n' => self.line += 1,
-                    _ => {}
+        let start = self.pos;
+        loop {
+            if self.line_directive() {
+                continue;
+            }
+            if self.rules.whitespace_token.is_some() || (self.rules.prefer_rules_over_whitespace && !self.candidates(false).is_empty()) {
+                break;
+            }
+            match self.rules.modes[self.mode()].whitespace.as_ref().and_then(|w| w.find(self.rest())) {
+                Some(mat) if mat.end() > 0 => {
+                    trace!("{}:{} skipped {} bytes of whitespace", self.line, self.column(), mat.end());
+                    self.advance(mat.end());
                 }
+                _ => break
             }
         }
+        self.stats.whitespace_bytes += self.pos - start;
     }
 
+    /// Returns true once every byte of the source was lexed and no peeked token is left
+    ///
+    /// Whitespace at the end of the source isn't skipped until the next token is lexed, so this can be false with only
+    /// whitespace left, `has_next_token` tells for sure
     pub fn done(&self) -> bool {
-        0 >= self.source.len()
+        match self.lookahead.front().or(self.unmerged.front()) {
+            Some(token) => matches!(token, Err(ParsingError::EndOfFileError)),
+            None => self.at_end()
+        }
+    }
+
+    /// Returns true if `next_token` would return a token or an error rather than the end of the source, lexing the
+    /// next token ahead to find out
+    pub fn has_next_token(&mut self) -> bool {
+        !matches!(self.peek_nth_token(0), Err(ParsingError::EndOfFileError))
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.end()
+    }
+
+    fn get(&mut self) -> char {
+        let c = self.ch();
+        self.advance(c.len_utf8());
+        c
     }
 
-    fn get(& mut self) -> char {
-        match self.source.remove(0) {
-            c => {
-                if c == '\n' { self.line += 1; }
-                c
+    /// Every rule matching at the start of the remaining source, with the length of its match, in order of the rule
+    /// names so ties are always broken the same way
+    ///
+    /// Empty matches are left out, they would never move the lexer forward. Tries and matches are only counted for
+    /// `profile` and `timing` if `counted`, so checking whether any rule matches before lexing the token doesn't count
+    /// them again.
+    #[cfg_attr(not(any(feature = "profile", feature = "timing")), allow(unused_variables))]
+    fn candidates(&mut self, counted: bool) -> Vec<(String, usize)> {
+        let end = self.end();
+        let rest = &self.source[self.pos..end];
+        let mode = &self.rules.modes[self.modes.last().copied().unwrap_or(0)];
+        let prev = self.prev.map(|id| &*self.rules.names[id.index()]);
+        let line_start = self.pos == self.line_start;
+        let mut found = vec![];
+        for (lit_type, pat) in &mode.literals {
+            if !mode.enabled(lit_type, prev, line_start) {
+                continue;
+            }
+            #[cfg(feature = "profile")]
+            if counted { self.stats.rules.entry(lit_type.clone()).or_default().attempts += 1; }
+            #[cfg(feature = "timing")]
+            let started = std::time::Instant::now();
+            let len = match mode.terminators.get(lit_type) {
+                Some(until) => pat.captures(rest).and_then(|caps| {
+                    let open = caps.get(0).unwrap().end();
+                    let mut close = String::new();
+                    caps.expand(until, &mut close);
+                    rest[open..].find(&close).map(|i| open + i + close.len())
+                }),
+                None => pat.find(rest).map(|mat| mat.end())
+            };
+            if let Some(len) = len.filter(|len| *len > 0 && mode.fits_end(lit_type, rest, *len)) {
+                found.push((lit_type.clone(), len));
+                #[cfg(feature = "profile")]
+                if counted { self.stats.rules.entry(lit_type.clone()).or_default().matches += 1; }
+            }
+            #[cfg(feature = "timing")]
+            if counted { *self.stats.rule_time.entry(lit_type.clone()).or_default() += started.elapsed(); }
+        }
+        // the fixed strings are all searched at once, so each one is tried wherever any is
+        #[cfg(feature = "profile")]
+        for name in &mode.fixed_names {
+            if counted && !mode.matchers.iter().any(|(n, _)| n == name) && mode.enabled(name, prev, line_start) {
+                self.stats.rules.entry(name.clone()).or_default().attempts += 1;
+            }
+        }
+        #[cfg(feature = "timing")]
+        let started = std::time::Instant::now();
+        for mat in mode.fixed.find_overlapping_iter(rest) {
+            let name = &mode.fixed_names[mat.pattern()];
+            let replaced = mode.matchers.iter().any(|(n, _)| n == name);
+            if !replaced && mode.enabled(name, prev, line_start) && mode.fits_end(name, rest, mat.end()) {
+                found.push((name.clone(), mat.end()));
+                #[cfg(feature = "profile")]
+                if counted { self.stats.rules.entry(name.clone()).or_default().matches += 1; }
+            }
+        }
+        #[cfg(feature = "timing")]
+        if counted { self.stats.fixed_time += started.elapsed(); }
+        for (name, matcher) in &mode.matchers {
+            if !mode.enabled(name, prev, line_start) {
+                continue;
+            }
+            #[cfg(feature = "profile")]
+            if counted { self.stats.rules.entry(name.clone()).or_default().attempts += 1; }
+            #[cfg(feature = "timing")]
+            let started = std::time::Instant::now();
+            let mut scanner = Scanner::new(&self.source, self.pos, end);
+            let len = if matcher.scan(&mut scanner) { scanner.pos() - self.pos } else { 0 };
+            if len > 0 && mode.fits_end(name, rest, len) {
+                found.push((name.clone(), len));
+                #[cfg(feature = "profile")]
+                if counted { self.stats.rules.entry(name.clone()).or_default().matches += 1; }
             }
+            #[cfg(feature = "timing")]
+            if counted { *self.stats.rule_time.entry(name.clone()).or_default() += started.elapsed(); }
         }
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        found
     }
 
+    /// Index of the candidate which wins, longest match unless a conflict says otherwise
+    ///
+    /// Candidates losing a conflict to another candidate are left out first, so the choice doesn't depend on their
+    /// order, then the longest of the rest wins and the first by name of those as long
+    fn select(&self, candidates: &[(String, usize)]) -> Option<usize> {
+        let beaten = |(name, len): &(String, usize)| candidates.iter().any(|(other, other_len)| self.rules.beats((other, *other_len), (name, *len)));
+        let remaining: Vec<usize> = (0..candidates.len()).filter(|i| !beaten(&candidates[*i])).collect();
+        // conflicts going both ways leave nothing, then length alone decides
+        let remaining = if remaining.is_empty() { (0..candidates.len()).collect() } else { remaining };
+        remaining.into_iter().min_by(|a, b| candidates[*b].1.cmp(&candidates[*a].1).then_with(|| candidates[*a].0.cmp(&candidates[*b].0)))
+    }
+
+    /// Lexes the next token with the positions of the original source
     fn parse_next(&mut self) -> Result<Token, ParsingError> {
-        self.skip_whitespace();
-        if !self.done() {
-            let mut name = String::new();
-            let mut mat: (i32, i32) = (-1, -1);
-            for (lit_type, pat) in &self.rules.literals {
-                let new_mat = match pat.find(&self.source) {
-                    Some(thing) => thing,
-                    None => continue
+        let token = self.merge_next();
+        let token = self.original(token);
+        let location = match &token {
+            Err(ParsingError::UnrecognizedPatternError(_, location))
+            | Err(ParsingError::UnbalancedDelimiter { location, .. })
+            | Err(ParsingError::UnclosedDelimiter { location, .. })
+            | Err(ParsingError::InvalidEscape { location, .. })
+            | Err(ParsingError::MaxLenExceeded { location, .. })
+            | Err(ParsingError::TokenTooLong { location, .. }) => location.clone(),
+            _ => return token
+        };
+        self.errors += 1;
+        match self.rules.max_errors {
+            Some(max) if self.errors > max => {
+                // nothing after the error is lexed, not even tokens already lexed ahead
+                self.exhausted = true;
+                self.unmerged.clear();
+                Err(ParsingError::TooManyErrors { count: self.errors, location })
+            }
+            _ => token
+        }
+    }
+
+    /// Lexes the next token and joins it with the tokens after it if they fit a `"merge"` sequence
+    fn merge_next(&mut self) -> Result<Token, ParsingError> {
+        if self.unmerged.is_empty() {
+            self.lex_ahead();
+        }
+        let first = self.unmerged.pop_front().unwrap()?;
+        for i in 0..self.rules.merges.len() {
+            if self.rules.merges[i].seq.first() != Some(&first.token_type) {
+                continue;
+            }
+            let len = self.rules.merges[i].seq.len() - 1;
+            while self.unmerged.len() < len && !matches!(self.unmerged.back(), Some(Err(_))) {
+                self.lex_ahead();
+            }
+            let merge = &self.rules.merges[i];
+            let mut end = first.span.end;
+            let fits = (0..len).all(|j| match self.unmerged.get(j) {
+                Some(Ok(token)) if token.token_type == merge.seq[j + 1] && (!merge.adjacent_only || token.span.start == end) => {
+                    end = token.span.end;
+                    true
+                }
+                _ => false
+            });
+            if !fits {
+                continue;
+            }
+            let mut value = first.value.clone();
+            for token in self.unmerged.drain(..len).flatten() {
+                value.push_str(&token.value);
+            }
+            let name = merge.into.clone();
+            trace!("{}:{} merged {} tokens into {}", first.line, first.column, len + 1, name);
+            let span = first.span.start..end;
+            return Ok(Token {
+                type_id: self.rules.ids[&name],
+                category: self.rules.categories.get(&name).cloned(),
+                sub_kind: None,
+                token_type: name,
+                value,
+                raw: self.source[span.clone()].to_string(),
+                span,
+                ..first
+            });
+        }
+        Ok(first)
+    }
+
+    /// Lexes the next token into `unmerged`, a run of a `"coalesce"` type joined into one token followed by the token
+    /// ending the run
+    fn lex_ahead(&mut self) {
+        let mut token = self.lex_token();
+        loop {
+            let run = match token {
+                Ok(run) if self.rules.coalesce.contains(&run.token_type) => run,
+                other => {
+                    self.unmerged.push_back(other);
+                    return;
+                }
+            };
+            let next = self.lex_token();
+            token = match next {
+                Ok(next) if next.token_type == run.token_type => {
+                    trace!("{}:{} coalesced {}", next.line, next.column, run.token_type);
+                    let span = run.span.start..next.span.end;
+                    Ok(Token { value: run.value + &next.value, raw: self.source[span.clone()].to_string(), span, ..run })
+                }
+                other => {
+                    self.unmerged.push_back(Ok(run));
+                    other
+                }
+            };
+        }
+    }
+
+    fn lex_token(&mut self) -> Result<Token, ParsingError> {
+        // the token's rule may change the mode, its options are those of the mode it was lexed in
+        let mode = self.mode();
+        let (name, span, line, column) = self.match_next()?;
+        let type_id = self.rules.ids[&name];
+        let raw = self.source[span.clone()].to_string();
+        let doc = self.rules.modes[mode].docs.values().filter(|doc| doc.token == name).find_map(|doc| doc.strip(&raw));
+        let cleaned = match doc {
+            Some(text) => Some(Ok(text.to_string())),
+            None => self.rules.modes[mode].cleaning.get(&name).map(|options| options.clean(&raw))
+        };
+        let value = match cleaned {
+            Some(Ok(value)) => value,
+            Some(Err(escape)) => {
+                let before = &raw[..escape.start];
+                let location = Location {
+                    source_name: self.source_name.clone(),
+                    line: line + before.matches('\n').count(),
+                    column: match before.rfind('\n') {
+                        Some(newline) => self.column_unit.measure(&before[newline + 1..]),
+                        None => column + self.column_unit.measure(before)
+                    },
+                    offset: span.start + escape.start
                 };
-                if new_mat.start() == 0 && new_mat.end() as i32 > mat.1 {
-                    mat = (new_mat.start() as i32, new_mat.end() as i32);
-                    name = lit_type.clone();
+                let span = span.start + escape.start..span.start + escape.end;
+                return Err(ParsingError::InvalidEscape { escape: raw[escape].to_string(), span, location });
+            }
+            None => raw.clone()
+        };
+        let value = match self.rules.modes[mode].max_lens.get(&name) {
+            Some(&(max_len, true)) if raw.chars().count() > max_len => value.chars().take(max_len).collect(),
+            _ => value
+        };
+        let category = self.rules.categories.get(&name).cloned();
+        let sub_kind = self.sub_kind(mode, &name, &raw);
+        let origin = self.rules.origin(&name, &span);
+        Ok(Token { token_type: name, type_id, value, raw, line, column, span, source_name: self.source_name.clone(), category, sub_kind, origin })
+    }
+
+    /// Sub-kind of a token of rule `name` lexed in `mode`, for `number` rules
+    fn sub_kind(&self, mode: usize, name: &str, raw: &str) -> Option<&'static str> {
+        let captures = self.rules.modes[mode].numbers.get(name)?.captures(raw)?;
+        NumberFormat::SUB_KINDS.into_iter().find(|kind| captures.name(kind).is_some())
+    }
+
+    /// Pops and pushes modes as the rule of the token just lexed says
+    fn change_mode(&mut self, name: &str) {
+        let transition = match self.rules.modes[self.mode()].transitions.get(name) {
+            Some(transition) => *transition,
+            None => return
+        };
+        if transition.pop {
+            self.modes.pop();
+        }
+        // a string with interpolation ends with its mode
+        let depth = self.modes.len();
+        self.interpolations.retain(|frame| frame.depth <= depth);
+        if let Some(mode) = transition.push {
+            self.modes.push(mode);
+        }
+        trace!("{}:{} {} switched to mode {}", self.line, self.column(), name, self.current_mode());
+    }
+
+    /// Lexes the next token without copying its text, as (type, span, line, column)
+    fn match_next(&mut self) -> Result<(String, Range<usize>, usize, usize), ParsingError> {
+        if self.exhausted {
+            return Err(ParsingError::EndOfFileError);
+        }
+        self.skip_whitespace();
+        if let (Some((end, line, column)), Some(asi)) = (self.asi_after.take(), &self.rules.asi) {
+            if self.at_end() || self.source[end..self.pos].contains('\n') {
+                let name = asi.emit.clone();
+                trace!("{}:{} inserted {}", line, column, name);
+                *self.stats.tokens.entry(name.clone()).or_insert(0) += 1;
+                self.prev = Some(self.rules.ids[&name]);
+                return Ok((name, end..end, line, column));
+            }
+        }
+        let rule_first = self.rules.prefer_rules_over_whitespace && !self.candidates(false).is_empty();
+        if let (Some(name), Some(whitespace), false) = (&self.rules.whitespace_token, &self.rules.modes[self.mode()].whitespace, rule_first) {
+            if let Some(mat) = whitespace.find(self.rest()).filter(|m| m.end() > 0) {
+                let name = name.clone();
+                let (start, line, column) = (self.pos, self.reported_line(), self.column());
+                self.advance(mat.end());
+                *self.stats.tokens.entry(name.clone()).or_insert(0) += 1;
+                return Ok((name, start..self.pos, line, column));
+            }
+        }
+        if let Some(token) = self.interpolation() {
+            return Ok(token);
+        }
+        if !self.at_end() {
+            let mut candidates = self.candidates(true);
+            trace!("{}:{} candidates {:?}", self.line, self.column(), candidates);
+            let (rule, len) = match self.select(&candidates) {
+                Some(i) => candidates.swap_remove(i),
+                None => {
+                    trace!("{}:{} no rule matches", self.line, self.column());
+                    self.stats.unrecognized += 1;
+                    let location = self.location();
+                    let skipped = match self.rules.recover {
+                        Recover::NextChar => String::from(self.get()),
+                        Recover::NextLine => {
+                            let rest = &self.source[self.pos..self.end()];
+                            // a line break no rule matches is skipped alone
+                            let len = match rest.find(['\r', '\n']) {
+                                Some(0) => 1,
+                                Some(len) => len,
+                                None => rest.len()
+                            };
+                            let skipped = rest[..len].to_string();
+                            self.advance(len);
+                            skipped
+                        }
+                        Recover::None => {
+                            self.exhausted = true;
+                            String::from(self.ch())
+                        }
+                    };
+                    return Err(ParsingError::UnrecognizedPatternError(skipped, location)) // no patterns
+                }
+            };
+            trace!("{}:{} selected {} ({} bytes)", self.line, self.column(), rule, len);
+            #[cfg(feature = "profile")]
+            {
+                let profile = self.stats.rules.entry(rule.clone()).or_default();
+                profile.hits += 1;
+                profile.bytes += len;
+            }
+            let others: Vec<String> = match self.ties {
+                Some(_) => candidates.iter().filter(|(other, other_len)| *other_len == len && !self.rules.beats((&rule, len), (other, *other_len))).map(|(other, _)| other.clone()).collect(),
+                None => vec![]
+            };
+            if !others.is_empty() {
+                let warning = TieWarning { selected: rule.clone(), others, span: self.pos..self.pos + len, location: self.location() };
+                if let Some(ties) = &mut self.ties {
+                    ties.push(warning);
                 }
             }
-            if mat.0 != 0 { // no patterns
-                return Err(ParsingError::UnrecognizedPatternError(String::from(self.get())))
+            let name = match self.rules.modes[self.mode()].docs.get(&rule) {
+                Some(doc) if doc.strip(&self.rest()[..len]).is_some() => doc.token.clone(),
+                _ => {
+                    let (mode, prev) = (&self.rules.mode_names[self.mode()], self.prev.map(|id| &*self.rules.names[id.index()]));
+                    let after = &self.source[self.pos + len..self.end()];
+                    let keyword = self.keywords.get(&self.rest()[..len]).and_then(|keywords| keywords.iter().find(|k| k.applies(mode, prev, after)));
+                    keyword.map_or_else(|| rule.clone(), |keyword| keyword.token_type.clone())
+                }
+            };
+            let (start, line, column) = (self.pos, self.reported_line(), self.column());
+            let too_long = match self.rules.modes[self.mode()].max_lens.get(&rule) {
+                Some(&(max_len, truncate)) if self.rest()[..len].chars().count() > max_len => {
+                    let (span, location) = (start..start + len, self.location());
+                    if truncate {
+                        self.truncations.push(TruncationWarning { rule: rule.clone(), max_len, span, location });
+                        None
+                    } else {
+                        Some(ParsingError::MaxLenExceeded { rule: rule.clone(), max_len, span, location })
+                    }
+                }
+                _ => None
+            };
+            self.advance(len);
+            match self.stats.tokens.get_mut(&name) {
+                Some(count) => *count += 1,
+                None => { self.stats.tokens.insert(name.clone(), 1); }
+            }
+            self.prev = Some(self.rules.ids[&name]);
+            if self.rules.asi.as_ref().is_some_and(|asi| asi.after.contains(&name)) {
+                self.asi_after = Some((self.pos, self.reported_line(), self.column()));
+            }
+            let interpolation = self.rules.modes[self.mode()].interpolations.get(&rule).copied();
+            self.change_mode(&rule);
+            if let Some(interpolation) = interpolation {
+                self.interpolations.push(InterpolationFrame { interpolation, depth: self.modes.len(), expression: None });
+            }
+            self.balance(&name, Location { source_name: self.source_name.clone(), line, column, offset: start })?;
+            return match too_long {
+                Some(e) => Err(e),
+                None => Ok((name, start..self.pos, line, column))
+            };
+        }
+        match self.delimiters.pop() {
+            Some((pair, location)) => Err(ParsingError::UnclosedDelimiter { opener: self.rules.pairs[pair].0.clone(), location }),
+            None => {
+                self.exhausted = true;
+                Err(ParsingError::EndOfFileError)
+            }
+        }
+    }
+
+    /// Lexes the start or the end of an expression embedded in the string the lexer is in, if there is one at the
+    /// cursor
+    fn interpolation(&mut self) -> Option<(String, Range<usize>, usize, usize)> {
+        let frame = *self.interpolations.last()?;
+        let interpolation = &self.rules.interpolations[frame.interpolation];
+        let (pattern, name) = match frame.expression {
+            None if self.modes.len() == frame.depth => (&interpolation.open, &interpolation.open_token),
+            Some(delimiters) if self.modes.len() == frame.depth + 1 && self.delimiters.len() == delimiters => (&interpolation.close, &interpolation.close_token),
+            _ => return None
+        };
+        let len = pattern.find(self.rest()).filter(|m| m.end() > 0)?.end();
+        let name = name.clone();
+        let (start, line, column) = (self.pos, self.reported_line(), self.column());
+        self.advance(len);
+        *self.stats.tokens.entry(name.clone()).or_insert(0) += 1;
+        self.prev = Some(self.rules.ids[&name]);
+        let frame = self.interpolations.last_mut().unwrap();
+        if frame.expression.is_none() {
+            frame.expression = Some(self.delimiters.len());
+            self.modes.push(self.rules.interpolations[frame.interpolation].mode);
+        } else {
+            frame.expression = None;
+            self.modes.pop();
+        }
+        trace!("{}:{} {} switched to mode {}", line, column, name, self.current_mode());
+        Some((name, start..self.pos, line, column))
+    }
+
+    /// Opens or closes a delimiter of `"pairs"` for the token just lexed
+    fn balance(&mut self, name: &str, location: Location) -> Result<(), ParsingError> {
+        if let Some(pair) = self.rules.pairs.iter().position(|(opener, _)| opener == name) {
+            self.delimiters.push((pair, location));
+            return Ok(());
+        }
+        if !self.rules.pairs.iter().any(|(_, closer)| closer == name) {
+            return Ok(());
+        }
+        match self.delimiters.last() {
+            Some((pair, _)) if self.rules.pairs[*pair].1 == name => {
+                self.delimiters.pop();
+                Ok(())
             }
-            let mut lexeme = String::new();
-            for _ in 0..mat.1 {
-                lexeme.push(self.get());
+            open => Err(ParsingError::UnbalancedDelimiter {
+                found: name.to_string(),
+                expected: open.map(|(pair, _)| self.rules.pairs[*pair].1.clone()),
+                location
+            })
+        }
+    }
+
+    /// Number of delimiters of `"pairs"` open after the tokens lexed so far, including peeked ones
+    pub fn depth(&self) -> usize {
+        self.delimiters.len()
+    }
+
+    /// Describes how the next token would be chosen: the skipped whitespace, every matching rule and why one was selected
+    ///
+    /// Doesn't move the lexer, a token which was already peeked isn't included
+    pub fn explain_next(&self) -> MatchExplanation {
+        let mut lexer = self.clone();
+        let start = lexer.pos;
+        lexer.skip_whitespace();
+        let mut candidates = lexer.candidates(false);
+        let selected = lexer.select(&candidates).map(|i| candidates[i].clone());
+        candidates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let reason = match &selected {
+            None => SelectionReason::NoMatch,
+            Some(_) if candidates.len() == 1 => SelectionReason::OnlyMatch,
+            Some((name, len)) => {
+                let others: Vec<&(String, usize)> = candidates.iter().filter(|c| &c.0 != name).collect();
+                let conflict = lexer.rules.conflicts.iter().find(|c| {
+                    &c.prefer == name && others.iter().any(|(other, other_len)| &c.over == other && (c.ignore_length || len == other_len))
+                });
+                match (conflict, others.iter().find(|(_, other_len)| other_len == len)) {
+                    (Some(c), _) => SelectionReason::Conflict(c.clone()),
+                    (None, Some((other, _))) => SelectionReason::Tie(other.clone()),
+                    (None, None) => SelectionReason::LongestMatch
+                }
             }
-            return Ok(Token { token_type: name, value: lexeme, line: self.line });
+        };
+        MatchExplanation {
+            offset: lexer.pos,
+            line: lexer.reported_line(),
+            column: lexer.column(),
+            skipped: self.source[start..lexer.pos].to_string(),
+            selected: selected.map(|(name, _)| name),
+            candidates,
+            reason
         }
-        Err(ParsingError::EndOfFileError)
     }
 
     /// Advances and returns the next token
     pub fn next_token(&mut self) -> Result<Token, ParsingError> {
-        match self.cache.clone() {
+        match self.lookahead.pop_front() {
             Some(token) => {
-                self.cache = None;
                 self.last_token = Some(token);
                 self.last_token.clone().unwrap()
             }
@@ -216,6 +3584,242 @@ impl Lexer {
         }
     }
 
+    /// Consumes the next `n` tokens, including peeked ones, like skipping a known preamble
+    ///
+    /// Stops at the first error, which is returned, so a source with fewer than `n` tokens left gives
+    /// `ParsingError::EndOfFileError`. `current_token` is the last token consumed either way
+    pub fn advance_by(&mut self, n: usize) -> Result<(), ParsingError> {
+        for _ in 0..n {
+            self.next_token()?;
+        }
+        Ok(())
+    }
+
+    /// Consumes `n` tokens and returns the one after them, so `nth_token(0)` is `next_token()`
+    ///
+    /// ```
+    /// use lexer_generator::Lexer;
+    ///
+    /// let mut lexer = Lexer::from(String::from(r#"{ "literals": { "number": "[0-9]+" } }"#), String::from("1 2 3 4"));
+    /// assert_eq!(lexer.nth_token(2).unwrap().value, "3");
+    /// assert_eq!(lexer.next_token().unwrap().value, "4");
+    /// ```
+    pub fn nth_token(&mut self, n: usize) -> Result<Token, ParsingError> {
+        self.advance_by(n)?;
+        self.next_token()
+    }
+
+    /// Lexes the rest of the source, checking `cancel` before every token so a long run stops promptly with
+    /// `ParsingError::Cancelled`
+    pub fn tokenize_with_cancel(&mut self, cancel: &CancelToken) -> Result<Vec<Token>, ParsingError> {
+        let mut tokens = vec![];
+        loop {
+            if cancel.is_cancelled() {
+                return Err(ParsingError::Cancelled);
+            }
+            match self.next_token() {
+                Ok(token) => tokens.push(token),
+                Err(ParsingError::EndOfFileError) => return Ok(tokens),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    /// Lexes the rest of the source without stopping at errors, returning the tokens and every error
+    ///
+    /// The text skipped because of an error, like an unrecognized character or a string with an invalid escape,
+    /// becomes an `ERROR_TOKEN` token with the id `TokenTypeId::ERROR`, so parsers can go on and report every
+    /// problem in a file at once
+    ///
+    /// ```
+    /// use lexer_generator::{Lexer, ERROR_TOKEN};
+    ///
+    /// let mut lexer = Lexer::from(String::from(r#"{ "literals": { "number": "[0-9]+" } }"#), String::from("1 ? 2 ! 3"));
+    /// let (tokens, errors) = lexer.tokenize_all_errors();
+    /// let values: Vec<&str> = tokens.iter().map(|t| t.value.as_str()).collect();
+    /// assert_eq!(values, ["1", "?", "2", "!", "3"]);
+    /// assert_eq!(tokens[1].token_type, ERROR_TOKEN);
+    /// assert_eq!(errors.len(), 2);
+    /// ```
+    ///
+    /// With `"max_errors"` the error after that many ends lexing as a `ParsingError::TooManyErrors`
+    ///
+    /// ```
+    /// use lexer_generator::{Lexer, ParsingError};
+    ///
+    /// let mut lexer = Lexer::from(String::from(r#"{ "literals": { "number": "[0-9]+" }, "max_errors": 1 }"#), String::from("1 ? 2 ! 3"));
+    /// let (tokens, errors) = lexer.tokenize_all_errors();
+    /// assert_eq!(tokens.len(), 4);
+    /// assert!(matches!(errors[1], ParsingError::TooManyErrors { count: 2, ref location } if location.offset == 6));
+    /// ```
+    pub fn tokenize_all_errors(&mut self) -> (Vec<Token>, Vec<ParsingError>) {
+        let mut tokens = vec![];
+        let mut errors = vec![];
+        loop {
+            // where the next token starts, unless it was already lexed ahead
+            let start = if self.lookahead.is_empty() && self.unmerged.is_empty() {
+                self.skip_whitespace();
+                Some((self.original_start(self.pos), self.location()))
+            } else {
+                None
+            };
+            let e = match self.next_token() {
+                Ok(token) => {
+                    tokens.push(token);
+                    continue;
+                }
+                Err(ParsingError::EndOfFileError) => return (tokens, errors),
+                Err(e) => e
+            };
+            let start = start.or(match &e {
+                ParsingError::UnrecognizedPatternError(_, location) | ParsingError::UnbalancedDelimiter { location, .. } => Some((location.offset, location.clone())),
+                ParsingError::InvalidEscape { span, location, .. } | ParsingError::MaxLenExceeded { span, location, .. } => Some((span.start, location.clone())),
+                _ => None
+            });
+            // in the original source, like the positions of errors
+            let end = self.original_end(self.pos);
+            if let Some((start, location)) = start.filter(|(start, _)| *start < end) {
+                let raw = self.original_source()[start..end].to_string();
+                tokens.push(Token {
+                    token_type: String::from(ERROR_TOKEN),
+                    type_id: TokenTypeId::ERROR,
+                    value: raw.clone(),
+                    raw,
+                    line: location.line,
+                    column: location.column,
+                    span: start..end,
+                    source_name: self.source_name.clone(),
+                    category: None,
+                    sub_kind: None,
+                    origin: TokenOrigin::Recovered
+                });
+            }
+            errors.push(e);
+        }
+    }
+
+    /// Whether tokens can be lexed as spans without building a `Token`: merging, coalescing and preprocessing need
+    /// whole tokens, and unescaping and `max_errors` the errors `parse_next` finds, so every tokenizer reports the
+    /// same errors
+    fn spans_only(&self) -> bool {
+        self.rules.merges.is_empty() && self.rules.coalesce.is_empty() && self.preprocessed.is_empty()
+            && self.rules.max_errors.is_none()
+            && !self.rules.modes.iter().any(|mode| mode.cleaning.values().any(|options| options.unescape))
+    }
+
+    /// Lexes the rest of the source into `OwnedToken`s, which share one copy of the source and one name per token type
+    /// rather than allocating a value and a type name for every token
+    pub fn tokenize_into_arena(&mut self) -> Result<Vec<OwnedToken>, ParsingError> {
+        let source: Arc<str> = self.original.clone().unwrap_or_else(|| Arc::from(self.source.as_str()));
+        let mut tokens = vec![];
+        while let Some(peeked) = self.lookahead.pop_front() {
+            match peeked {
+                Ok(token) => tokens.push(OwnedToken {
+                    token_type: self.rules.names[token.type_id.index()].clone(),
+                    type_id: token.type_id,
+                    line: token.line,
+                    column: token.column,
+                    span: token.span,
+                    source_name: token.source_name,
+                    category: token.category,
+                    sub_kind: token.sub_kind,
+                    origin: token.origin,
+                    source: source.clone()
+                }),
+                Err(ParsingError::EndOfFileError) => return Ok(tokens),
+                Err(e) => return Err(e)
+            }
+        }
+        loop {
+            // otherwise the text of tokens isn't copied
+            let mode = self.mode();
+            let next = if self.spans_only() {
+                self.match_next().map(|(name, span, line, column)| {
+                    let sub_kind = self.sub_kind(mode, &name, &source[span.clone()]);
+                    let origin = self.rules.origin(&name, &span);
+                    (name, span, line, column, sub_kind, origin)
+                })
+            } else {
+                self.parse_next().map(|token| (token.token_type, token.span, token.line, token.column, token.sub_kind, token.origin))
+            };
+            match next {
+                Ok((name, span, line, column, sub_kind, origin)) => {
+                    let type_id = self.rules.ids[&name];
+                    tokens.push(OwnedToken { token_type: self.rules.names[type_id.index()].clone(), type_id, line, column, span, source_name: self.source_name.clone(), category: self.rules.categories.get(&name).cloned(), sub_kind, origin, source: source.clone() });
+                }
+                Err(ParsingError::EndOfFileError) => return Ok(tokens),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    /// Lexes the rest of the source into `CompactToken`s, which take 20 bytes each and no allocation, for sources too
+    /// large to hold a `Token` for every token
+    ///
+    /// ```
+    /// use lexer_generator::Lexer;
+    ///
+    /// let source = String::from("one two\nthree");
+    /// let mut lexer = Lexer::from(String::from(r#"{ "literals": { "word": "[a-z]+" } }"#), source.clone());
+    /// let tokens = lexer.tokenize_compact().unwrap();
+    /// assert_eq!(tokens[2].value(&source), "three");
+    /// let token = lexer.expand(&tokens[2]);
+    /// assert_eq!((token.token_type.as_str(), token.line, token.column), ("word", 1, 0));
+    /// ```
+    ///
+    /// Panics if the source is larger than 4 GiB.
+    pub fn tokenize_compact(&mut self) -> Result<Vec<CompactToken>, ParsingError> {
+        assert!(u32::try_from(self.source.len()).is_ok(), "source larger than 4 GiB");
+        let mut tokens: Vec<CompactToken> = vec![];
+        while let Some(peeked) = self.lookahead.pop_front() {
+            match peeked {
+                Ok(token) => tokens.push(CompactToken::try_from(&token).expect("source larger than 4 GiB")),
+                Err(ParsingError::EndOfFileError) => return Ok(tokens),
+                Err(e) => return Err(e)
+            }
+        }
+        loop {
+            let next = if self.spans_only() {
+                self.match_next().map(|(name, span, line, column)| (self.rules.ids[&name], span, line, column))
+            } else {
+                self.parse_next().map(|token| (token.type_id, token.span, token.line, token.column))
+            };
+            match next {
+                Ok((type_id, span, line, column)) => tokens.push(CompactToken { kind_id: type_id.0, start: span.start as u32, len: span.len() as u32, line: line as u32, column: column as u32 }),
+                Err(ParsingError::EndOfFileError) => return Ok(tokens),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    /// The `Token` of a `CompactToken` lexed from this lexer's source, its value is the text as it was matched like
+    /// `raw`, without the cleaning options of rules, and it has no sub-kind
+    ///
+    /// Its text is that of the original source if the source was preprocessed, like its span. A token with the id
+    /// `TokenTypeId::ERROR`, from the tokens of `tokenize_all_errors`, is an `ERROR_TOKEN` token.
+    pub fn expand(&self, token: &CompactToken) -> Token {
+        let span = token.span();
+        let name = match token.type_id() {
+            TokenTypeId::ERROR => String::from(ERROR_TOKEN),
+            type_id => self.rules.names[type_id.index()].to_string()
+        };
+        let source = self.original_source();
+        let raw = source[span.clone()].to_string();
+        Token {
+            type_id: token.type_id(),
+            value: raw.clone(),
+            raw,
+            line: token.line as usize,
+            column: token.column as usize,
+            source_name: self.source_name.clone(),
+            category: self.rules.categories.get(&name).cloned(),
+            sub_kind: None,
+            origin: self.rules.origin(&name, &span),
+            span,
+            token_type: name
+        }
+    }
+
     /// Advances and returns the next token
     pub fn next_token_x(& mut self) -> Token {
         self.next_token().unwrap()
@@ -233,12 +3837,161 @@ impl Lexer {
 
     /// Returns the next token to be lexed
     pub fn peek_next_token(&mut self) -> Option<Result<Token, ParsingError>> {
-        self.cache = Some(self.next_token());
-        self.cache.clone()
+        let token = self.next_token();
+        self.lookahead.push_front(token);
+        self.lookahead.front().cloned()
     }
 
     /// Returns the next token to be lexed. can throw exceptions
     pub fn peek_next_token_x(&mut self) -> Token {
         self.peek_next_token().unwrap().unwrap()
     }
-}
\ No newline at end of file
+
+    /// Returns the token `n` tokens ahead without consuming anything, `peek_nth_token(0)` being the next one
+    ///
+    /// Lexing stops at the end of the source or an error, which is returned for every `n` past it
+    pub fn peek_nth_token(&mut self, n: usize) -> Result<Token, ParsingError> {
+        while self.lookahead.len() <= n {
+            if let Some(Err(e)) = self.lookahead.back() {
+                return Err(e.clone());
+            }
+            let token = self.parse_next();
+            self.lookahead.push_back(token);
+        }
+        self.lookahead[n].clone()
+    }
+
+    /// The tokens after the current one, lexed without changing the lexer, so parsers can look ahead while holding
+    /// other borrows of it
+    ///
+    /// The preview works on a clone of the lexer, which shares its source rather than copying it, and ends at the end
+    /// of the source or the first error.
+    ///
+    /// ```
+    /// use lexer_generator::Lexer;
+    ///
+    /// let mut lexer = Lexer::from(String::from(r#"{ "literals": { "number": "[0-9]+", "comma": "," } }"#), String::from("1, 2, 3"));
+    /// lexer.next_token().unwrap();
+    /// let ahead: Vec<String> = lexer.preview().map(|token| token.value).collect();
+    /// assert_eq!(ahead, [",", "2", ",", "3"]);
+    /// assert_eq!(lexer.next_token().unwrap().value, ",");
+    /// ```
+    pub fn preview(&self) -> LexerSnapshotIter<'_> {
+        LexerSnapshotIter { lexer: Some(self.clone()), lexed_from: std::marker::PhantomData }
+    }
+
+    /// Returns true if the next tokens have the given types, in order, without consuming them
+    ///
+    /// False if the source ends or an error comes before all of them were matched
+    pub fn lookahead_matches(&mut self, types: &[&str]) -> bool {
+        types.iter().enumerate().all(|(i, t)| self.peek_nth_token(i).is_ok_and(|token| token.token_type == *t))
+    }
+
+    /// Lexes a copy of the remaining input and formats it with `format_tokens`, without moving this lexer, followed by
+    /// the error lexing stopped at if it didn't reach the end of the source
+    pub fn dump(&self) -> String {
+        let mut lexer = self.clone();
+        let mut tokens = vec![];
+        let error = loop {
+            match lexer.next_token() {
+                Ok(token) => tokens.push(token),
+                Err(e) => break e
+            }
+        };
+        let mut out = format_tokens(&tokens);
+        if !matches!(error, ParsingError::EndOfFileError) {
+            out.push_str(&format!("stopped at {}\n", error));
+        }
+        out
+    }
+
+    /// The rest of the tokens grouped by the line they start on like `group_by_line`, failing on the first error
+    pub fn tokens_by_line(&mut self) -> Result<Vec<Vec<Token>>, ParsingError> {
+        let tokens = self.by_ref().collect::<Result<Vec<_>, _>>()?;
+        Ok(group_by_line(&tokens).map(|(_, tokens)| tokens.to_vec()).collect())
+    }
+
+    /// Tokens starting on `line`, lexed by a copy of the lexer so this one doesn't move, failing on an error before the
+    /// end of the line
+    ///
+    /// Lexing goes from the lexer's position to the first token after the line, so asking for each line of a large
+    /// source in turn lexes it over and over, `tokens_by_line` lexes it once.
+    ///
+    /// ```
+    /// use lexer_generator::Lexer;
+    ///
+    /// let lexer = Lexer::from(String::from(r#"{ "literals": { "name": "[a-z]+" } }"#), String::from("a b\nc d e\nf"));
+    /// let values: Vec<String> = lexer.tokens_on_line(1).unwrap().into_iter().map(|token| token.value).collect();
+    /// assert_eq!(values, ["c", "d", "e"]);
+    /// ```
+    pub fn tokens_on_line(&self, line: usize) -> Result<Vec<Token>, ParsingError> {
+        let mut lexer = self.clone();
+        let mut tokens = vec![];
+        for token in lexer.by_ref() {
+            let token = token?;
+            if token.line > line {
+                break;
+            }
+            if token.line == line {
+                tokens.push(token);
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// The lexer's iterator with each token paired with its byte span, the `(token, span)` items parser combinator
+    /// libraries like chumsky build their streams from
+    ///
+    /// ```
+    /// use lexer_generator::Lexer;
+    ///
+    /// let mut lexer = Lexer::from(String::from(r#"{ "literals": { "number": "[0-9]+" } }"#), String::from("1 23"));
+    /// let spans: Vec<_> = lexer.spanned().map(|item| item.unwrap().1).collect();
+    /// assert_eq!(spans, [0..1, 2..4]);
+    /// ```
+    pub fn spanned(&mut self) -> impl Iterator<Item = Result<(Token, Range<usize>), ParsingError>> + '_ {
+        self.by_ref().map(|token| token.map(|token| {
+            let span = token.span.clone();
+            (token, span)
+        }))
+    }
+
+    /// The lexer's iterator as `(start, token, end)` triples, the shape lalrpop's external lexers produce
+    ///
+    /// ```
+    /// use lexer_generator::Lexer;
+    ///
+    /// let mut lexer = Lexer::from(String::from(r#"{ "literals": { "number": "[0-9]+" } }"#), String::from("1 23"));
+    /// let (start, token, end) = lexer.triples().nth(1).unwrap().unwrap();
+    /// assert_eq!((start, token.value.as_str(), end), (2, "23", 4));
+    /// ```
+    pub fn triples(&mut self) -> impl Iterator<Item = Result<(usize, Token, usize), ParsingError>> + '_ {
+        self.by_ref().map(|token| token.map(|token| {
+            let Range { start, end } = token.span;
+            (start, token, end)
+        }))
+    }
+}
+/// The tokens and errors of `next_token` until the end of the source, after which it only returns None
+///
+/// ```
+/// use lexer_generator::Lexer;
+///
+/// let mut lexer = Lexer::from(String::from(r#"{ "literals": { "number": "[0-9]+" } }"#), String::from("1 ? 2"));
+/// let values: Vec<String> = lexer.by_ref().map(|token| token.map_or_else(|e| e.to_string(), |t| t.value)).collect();
+/// assert_eq!(values, ["1", "0:2: unrecognized input \"?\"", "2"]);
+/// assert!(lexer.next().is_none());
+/// assert!(lexer.next_token().is_err());
+/// ```
+impl Iterator for Lexer {
+    type Item = Result<Token, ParsingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_token() {
+            Err(ParsingError::EndOfFileError) => None,
+            result => Some(result)
+        }
+    }
+}
+
+impl std::iter::FusedIterator for Lexer {}
@@ -0,0 +1,104 @@
+//! Loading a shipped rule set with changes applied by the application
+
+use crate::{LoadError, Rule, RuleSet};
+
+#[derive(Clone, Debug)]
+enum Change {
+    Override(String, String),
+    Remove(String)
+}
+
+/// Loads a rule set with rules overridden or removed after parsing and before compiling, so an application can adjust
+/// a grammar it ships, e.g. allow `-` in identifiers for one tenant, without editing the JSON
+///
+/// Changes are applied in the order they were given, to the rule of that name in every mode.
+///
+/// ```
+/// use lexer_generator::{assert_tokens, RuleSetLoader};
+///
+/// let json = r#"{ "version": 2, "literals": { "identifier": { "pattern": "[a-z]+", "category": "name" }, "minus": "-", "number": "[0-9]+" } }"#;
+/// let rules = RuleSetLoader::new().override_rule("identifier", "[a-z][a-z-]*").remove_rule("number").load(json).unwrap();
+/// assert_tokens!(rules, "tenant-name", [("identifier", "tenant-name")]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RuleSetLoader {
+    changes: Vec<Change>
+}
+
+impl RuleSetLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the pattern of rule `name`, keeping its options like `category` or `push`, or adds the rule to the
+    /// main mode if there is none of that name
+    pub fn override_rule(mut self, name: &str, pattern: &str) -> Self {
+        self.changes.push(Change::Override(name.to_string(), pattern.to_string()));
+        self
+    }
+
+    /// Removes rule `name` from every mode, loading fails with `LoadError::UnknownRule` if there is none
+    pub fn remove_rule(mut self, name: &str) -> Self {
+        self.changes.push(Change::Remove(name.to_string()));
+        self
+    }
+
+    /// Overrides rules from the environment variables named `prefix` followed by a rule name, like
+    /// `LEXER_RULE_identifier` for the prefix `LEXER_RULE_`, an empty variable removes the rule
+    ///
+    /// Variables are read when this is called, sorted by name, after the changes given before it.
+    pub fn env_overrides(mut self, prefix: &str) -> Self {
+        let mut vars: Vec<(String, String)> = std::env::vars().filter(|(key, _)| key.starts_with(prefix) && key.len() > prefix.len()).collect();
+        vars.sort();
+        for (key, pattern) in vars {
+            let name = key[prefix.len()..].to_string();
+            self.changes.push(if pattern.is_empty() { Change::Remove(name) } else { Change::Override(name, pattern) });
+        }
+        self
+    }
+
+    /// Parses a rule set like `RuleSet::load` and applies the changes
+    pub fn load(&self, json: &str) -> Result<RuleSet, LoadError> {
+        self.apply(RuleSet::load(json)?)
+    }
+
+    /// Applies the changes to a rule set which is already loaded, like one of `grammars`
+    pub fn apply(&self, mut rules: RuleSet) -> Result<RuleSet, LoadError> {
+        for change in &self.changes {
+            let modes = std::iter::once(&mut rules.literals).chain(rules.modes.values_mut().map(|mode| &mut mode.literals));
+            match change {
+                Change::Override(name, pattern) => {
+                    let mut found = false;
+                    for literals in modes {
+                        if let Some(rule) = literals.get_mut(name) {
+                            override_pattern(rule, pattern);
+                            found = true;
+                        }
+                    }
+                    if !found {
+                        rules.literals.insert(name.clone(), Rule::Pattern(pattern.clone()));
+                    }
+                }
+                Change::Remove(name) => {
+                    if modes.filter_map(|literals| literals.remove(name)).count() == 0 {
+                        return Err(LoadError::UnknownRule(name.clone()));
+                    }
+                }
+            }
+        }
+        Ok(rules)
+    }
+}
+
+/// Replaces what a rule matches with `pattern`, keeping its other options
+fn override_pattern(rule: &mut Rule, pattern: &str) {
+    match rule {
+        Rule::Options(options) => {
+            options.pattern = Some(Box::new(Rule::Pattern(pattern.to_string())));
+            options.literal = None;
+            options.number = None;
+            options.native = None;
+        }
+        _ => *rule = Rule::Pattern(pattern.to_string())
+    }
+}
@@ -1,95 +1,511 @@
-mod lib;
+use lexer_generator::*;
 
-use lib::*;
+#[cfg(feature = "timing")]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "timing")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
-impl Token {
-    fn get_precedence(&self) -> usize {
-        match self.token_type.as_str() {
-            "add" => 1,
-            "subtract" => 1,
-            "divide" => 2,
-            "multiply" => 2,
-            _ => panic!()
+/// The system allocator, counting allocations and reallocations for `bench`, only installed with the `timing` feature
+#[cfg(feature = "timing")]
+struct CountingAllocator;
+
+#[cfg(feature = "timing")]
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "timing")]
+static REALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "timing")]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        REALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[cfg(feature = "timing")]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// `debug <rules.json> <source>`: prints every rule matching at each token and which one was selected
+fn debug(args: &[String]) {
+    if args.len() != 2 {
+        eprintln!("usage: lexer-generator debug <rules.json> <source>");
+        std::process::exit(2);
+    }
+    let read = |path: &String| std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        std::process::exit(1);
+    });
+    let rules = CompiledRules::from_json(&read(&args[0])).unwrap_or_else(|e| {
+        eprintln!("{}: {}", args[0], e);
+        std::process::exit(1);
+    });
+    let source = read(&args[1]);
+    let end = source.len();
+    // explaining works on a clone of this one lexer, which shares the source instead of copying it
+    let mut lexer = Lexer::from_compiled(Arc::new(rules), source);
+    loop {
+        let explanation = lexer.explain_next();
+        if !explanation.skipped.is_empty() {
+            println!("skipped {:?}", explanation.skipped);
+        }
+        if explanation.offset >= end {
+            break;
+        }
+        println!("{}:{} (offset {})", explanation.line, explanation.column, explanation.offset);
+        for (name, len) in &explanation.candidates {
+            let marker = if explanation.selected.as_ref() == Some(name) { "*" } else { " " };
+            println!("  {} {} matched {} bytes", marker, name, len);
+        }
+        match &explanation.selected {
+            Some(name) => println!("  selected {}: {}", name, explanation.reason),
+            None => println!("  {}", explanation.reason)
+        }
+        match lexer.next_token() {
+            Ok(token) => println!("  -> {}", token),
+            Err(ParsingError::EndOfFileError) => break,
+            Err(e) => println!("  -> {:?}", e)
         }
     }
 }
 
-enum StackObject {
-    Number(i32),
-    Operator(Token)
+/// Field of a CSV row, quoted if it has commas, quotes or line breaks
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) { format!("\"{}\"", field.replace('"', "\"\"")) } else { field.to_string() }
 }
 
-fn main() {
-    let argv: Vec<String> = std::env::args().collect();
-    let json: String = std::fs::read_to_string(argv[1].clone()).unwrap();
-    let source: String = std::fs::read_to_string(argv[2].clone()).unwrap();
-
-    let mut lexer = lib::Lexer::from(json, source);
-    let mut output_stack: Vec<StackObject> = vec![];
-    let mut operator_stack: Vec<Token> = vec![];
-    while !lexer.done() {
-        if lexer.next_token().unwrap().is(vec!["number"]) {
-            output_stack.push(StackObject::Number(lexer.current_token_x().value.parse::<i32>().unwrap()));
-        } else if lexer.current_token_x().is(vec!["add", "subtract", "divide", "multiply"]) {
-            if operator_stack.len() > 0 && operator_stack.last().unwrap().get_precedence() > lexer.current_token_x().get_precedence() {
-                operator_stack.reverse();
-                for t in &operator_stack {
-                    output_stack.push(StackObject::Operator(t.clone()));
+/// Appends a MessagePack string
+fn msgpack_str(out: &mut Vec<u8>, s: &str) {
+    let len = s.len();
+    if len < 32 {
+        out.push(0xa0 | len as u8);
+    } else if len <= u8::MAX as usize {
+        out.extend([0xd9, len as u8]);
+    } else if len <= u16::MAX as usize {
+        out.push(0xda);
+        out.extend((len as u16).to_be_bytes());
+    } else {
+        out.push(0xdb);
+        out.extend((len as u32).to_be_bytes());
+    }
+    out.extend(s.as_bytes());
+}
+
+/// Appends a MessagePack unsigned integer in its shortest form
+fn msgpack_uint(out: &mut Vec<u8>, n: usize) {
+    if n < 128 {
+        out.push(n as u8);
+    } else if n <= u8::MAX as usize {
+        out.extend([0xcc, n as u8]);
+    } else if n <= u16::MAX as usize {
+        out.push(0xcd);
+        out.extend((n as u16).to_be_bytes());
+    } else if n <= u32::MAX as usize {
+        out.push(0xce);
+        out.extend((n as u32).to_be_bytes());
+    } else {
+        out.push(0xcf);
+        out.extend((n as u64).to_be_bytes());
+    }
+}
+
+/// Writes the tokens of the source in `format`, one record per token with its type, value, line, column and byte span:
+/// `jsonl` writes a JSON object per line, `csv` a header and a row per token, `msgpack` a MessagePack map per token.
+/// Lexing errors are reported on stderr after the tokens before them.
+fn structured(lexer: Lexer, format: &str) {
+    use std::io::Write;
+    let mut out: Vec<u8> = vec![];
+    if format == "csv" {
+        out.extend(b"kind,value,line,column,start,end\n");
+    }
+    let mut failed = false;
+    for token in lexer {
+        let token = match token {
+            Ok(token) => token,
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+                continue;
+            }
+        };
+        match format {
+            "jsonl" => {
+                let record = serde_json::json!({
+                    "kind": token.token_type,
+                    "value": token.value,
+                    "line": token.line,
+                    "column": token.column,
+                    "start": token.span.start,
+                    "end": token.span.end
+                });
+                out.extend(format!("{}\n", record).as_bytes());
+            }
+            "csv" => {
+                let row = [token.token_type.clone(), token.value.clone(), token.line.to_string(), token.column.to_string(), token.span.start.to_string(), token.span.end.to_string()];
+                out.extend(format!("{}\n", row.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(",")).as_bytes());
+            }
+            _ => {
+                // fixmap of 6 entries
+                out.push(0x86);
+                msgpack_str(&mut out, "kind");
+                msgpack_str(&mut out, &token.token_type);
+                msgpack_str(&mut out, "value");
+                msgpack_str(&mut out, &token.value);
+                for (key, n) in [("line", token.line), ("column", token.column), ("start", token.span.start), ("end", token.span.end)] {
+                    msgpack_str(&mut out, key);
+                    msgpack_uint(&mut out, n);
                 }
-                operator_stack = vec![];
-            } else {
-                operator_stack.push(lexer.current_token_x())
             }
-        } else {
-            panic!()
         }
     }
-    operator_stack.reverse();
-    for t in &operator_stack {
-        output_stack.push(StackObject::Operator(t.clone()));
+    std::io::stdout().write_all(&out).unwrap();
+    if failed {
+        std::process::exit(1);
     }
+}
 
-    for obj in &output_stack {
-        print!("{} ", match obj {
-            StackObject::Number(n) => n.to_string(),
-            StackObject::Operator(t) => t.value.clone()
-        });
-    }
+/// One finding of `check`, errors fail the check and warnings don't
+struct Diagnostic {
+    severity: &'static str,
+    kind: &'static str,
+    message: String,
+    /// JSON pointer into the rule set for schema errors, the test's name or index for test failures
+    at: Option<String>
+}
 
-    println!();
+/// `check <rules.json> [--tests <tests.json>] [--format text|json]`: validates a rule set for CI, its shape against the
+/// schema, its patterns, rules and modes it refers to, ambiguities between its rules and, with `--tests`, the cases of
+/// a test file (see `testing::load_test_cases`), exiting with 1 if any error was found
+fn check(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: lexer-generator check <rules.json> [--tests <tests.json>] [--format text|json]");
+        std::process::exit(2);
+    };
+    let (mut rules_path, mut tests_path, mut format) = (None, None, String::from("text"));
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tests" => tests_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--format" => format = args.next().unwrap_or_else(|| usage()).clone(),
+            _ if arg.starts_with("--format=") => format = arg["--format=".len()..].to_string(),
+            _ if rules_path.is_none() && !arg.starts_with("--") => rules_path = Some(arg),
+            _ => usage()
+        }
+    }
+    let rules_path = rules_path.unwrap_or_else(|| usage());
+    if format != "text" && format != "json" {
+        usage();
+    }
 
-    let mut stack: Vec<i32> = vec![];
-    for obj in output_stack {
-        match obj {
-            StackObject::Number(n) => {
-                stack.push(n);
-            }
-            StackObject::Operator(token) => {
-                match token.value.as_str() {
-                    "+" => {
-                        let b = stack.pop().unwrap();
-                        let a = stack.pop().unwrap();
-                        stack.push(a + b);
-                    }
-                    "-" => {
-                        let b = stack.pop().unwrap();
-                        let a = stack.pop().unwrap();
-                        stack.push(a - b);
+    let mut diagnostics = vec![];
+    let error = |kind, message, at| Diagnostic { severity: "error", kind, message, at };
+    match std::fs::read_to_string(rules_path) {
+        Err(e) => diagnostics.push(error("io", e.to_string(), None)),
+        Ok(json) => {
+            let schema = validate_json(&json);
+            diagnostics.extend(schema.iter().map(|e| error("schema", e.message.clone(), Some(e.path.clone()))));
+            match RuleSet::load(&json) {
+                // the schema errors are already listed
+                Err(LoadError::Schema(_)) => {}
+                Err(e) => diagnostics.push(error("load", e.to_string(), None)),
+                Ok(rules) => {
+                    for warning in rules.validate() {
+                        let (severity, kind) = match warning {
+                            ValidationWarning::InvalidPattern(..) => ("error", "pattern"),
+                            ValidationWarning::UnknownRule(_) | ValidationWarning::UnknownMode(..) => ("error", "reference"),
+                            ValidationWarning::AmbiguousRules(..) | ValidationWarning::WhitespaceOverlap(..) => ("warning", "ambiguity"),
+                            ValidationWarning::PatternTooLarge(..) => ("warning", "size"),
+                            ValidationWarning::InvalidOption(..) => ("warning", "option"),
+                            ValidationWarning::MissingFeature { .. } => ("warning", "feature"),
+                            ValidationWarning::FormatIndent(_) => ("warning", "format")
+                        };
+                        diagnostics.push(Diagnostic { severity, kind, message: warning.to_string(), at: None });
                     }
-                    "/" => {
-                        let b = stack.pop().unwrap();
-                        let a = stack.pop().unwrap();
-                        stack.push(a / b);
+                    let compiled = CompiledRules::compile(rules.clone());
+                    if let Err(e) = &compiled {
+                        if !diagnostics.iter().any(|d| d.kind == "pattern") {
+                            diagnostics.push(error("compile", e.to_string(), None));
+                        }
                     }
-                    "*" => {
-                        let b = stack.pop().unwrap();
-                        let a = stack.pop().unwrap();
-                        stack.push(a * b);
+                    match tests_path.map(|path| std::fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|json| testing::load_test_cases(&json))) {
+                        None => {}
+                        Some(Err(e)) => diagnostics.push(error("tests", e, None)),
+                        // the tests can't run without a lexer, the compile error says why
+                        Some(Ok(_)) if compiled.is_err() => {}
+                        Some(Ok(cases)) => for (i, case) in cases.iter().enumerate() {
+                            if let Err(diff) = case.run(&rules) {
+                                diagnostics.push(error("test", diff, Some(case.name.clone().unwrap_or_else(|| i.to_string()))));
+                            }
+                        }
                     }
-                    _ => panic!()
                 }
             }
         }
     }
-    println!("final output: {}", stack.pop().unwrap());
+
+    let errors = diagnostics.iter().filter(|d| d.severity == "error").count();
+    if format == "json" {
+        let diagnostics: Vec<serde_json::Value> = diagnostics.iter().map(|d| serde_json::json!({
+            "severity": d.severity,
+            "kind": d.kind,
+            "message": d.message,
+            "at": d.at
+        })).collect();
+        println!("{}", serde_json::json!({ "file": rules_path, "errors": errors, "warnings": diagnostics.len() - errors, "diagnostics": diagnostics }));
+    } else {
+        for d in &diagnostics {
+            let at = d.at.as_ref().map(|at| format!(" {}", at)).unwrap_or_default();
+            println!("{}: {} ({}{}): {}", rules_path, d.severity, d.kind, at, d.message.trim_end());
+        }
+        println!("{} errors, {} warnings", errors, diagnostics.len() - errors);
+    }
+    if errors > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Files under `path` in a stable order, or `path` itself if it's a file
+fn corpus_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?.map(|entry| entry.map(|e| e.path())).collect::<Result<_, _>>()?;
+    entries.sort();
+    for entry in entries {
+        corpus_files(&entry, files)?;
+    }
+    Ok(())
+}
+
+/// `bench <rules.json> <corpus> [--top N]`: lexes every file of a corpus directory, or a single file, and reports the
+/// throughput, allocations and, with the `timing` feature, the rules which took the longest to match
+fn bench(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: lexer-generator bench <rules.json> <corpus> [--top N]");
+        std::process::exit(2);
+    };
+    let mut paths = vec![];
+    let mut top = 10;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--top" {
+            top = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| usage());
+        } else if arg.starts_with("--") || paths.len() == 2 {
+            usage();
+        } else {
+            paths.push(arg);
+        }
+    }
+    if paths.len() != 2 {
+        usage();
+    }
+    let json = std::fs::read_to_string(paths[0]).unwrap_or_else(|e| {
+        eprintln!("{}: {}", paths[0], e);
+        std::process::exit(1);
+    });
+    let rules = Arc::new(CompiledRules::from_json(&json).unwrap_or_else(|e| {
+        eprintln!("{}: {}", paths[0], e);
+        std::process::exit(1);
+    }));
+    let mut files = vec![];
+    if let Err(e) = corpus_files(Path::new(paths[1]), &mut files) {
+        eprintln!("{}: {}", paths[1], e);
+        std::process::exit(1);
+    }
+
+    let (mut bytes, mut tokens, mut errors) = (0, 0, 0);
+    let mut elapsed = std::time::Duration::ZERO;
+    #[cfg(feature = "timing")]
+    let (mut allocations, mut reallocations) = (0, 0);
+    #[cfg(feature = "timing")]
+    let mut rule_time: std::collections::HashMap<String, std::time::Duration> = std::collections::HashMap::new();
+    for file in &files {
+        let source = match std::fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("skipping {}: {}", file.display(), e);
+                continue;
+            }
+        };
+        bytes += source.len();
+        let mut lexer = Lexer::from_compiled(rules.clone(), source);
+        #[cfg(feature = "timing")]
+        let before = (ALLOCATIONS.load(Ordering::Relaxed), REALLOCATIONS.load(Ordering::Relaxed));
+        let start = std::time::Instant::now();
+        loop {
+            match lexer.next_token() {
+                Ok(_) => tokens += 1,
+                Err(ParsingError::EndOfFileError) => break,
+                Err(_) => errors += 1
+            }
+        }
+        elapsed += start.elapsed();
+        #[cfg(feature = "timing")]
+        {
+            allocations += ALLOCATIONS.load(Ordering::Relaxed) - before.0;
+            reallocations += REALLOCATIONS.load(Ordering::Relaxed) - before.1;
+            for (rule, time) in &lexer.stats().rule_time {
+                *rule_time.entry(rule.clone()).or_default() += *time;
+            }
+            *rule_time.entry(String::from("(fixed strings)")).or_default() += lexer.stats().fixed_time;
+        }
+    }
+
+    let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+    println!("corpus: {} files, {:.2} MB", files.len(), bytes as f64 / 1e6);
+    println!("time: {:.3} s, {:.2} MB/s, {:.0} tokens/s", elapsed.as_secs_f64(), bytes as f64 / 1e6 / seconds, tokens as f64 / seconds);
+    println!("tokens: {}, errors: {}", tokens, errors);
+    #[cfg(feature = "timing")]
+    {
+        let per_token = |n: usize| n as f64 / tokens.max(1) as f64;
+        println!("allocations: {} ({:.2} per token), reallocations: {} ({:.2} per token)", allocations, per_token(allocations), reallocations, per_token(reallocations));
+        let total: std::time::Duration = rule_time.values().sum();
+        let mut slowest: Vec<(String, std::time::Duration)> = rule_time.into_iter().collect();
+        slowest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        println!("slowest rules:");
+        let width = slowest.iter().take(top).map(|(rule, _)| rule.len()).max().unwrap_or(0);
+        for (rule, time) in slowest.into_iter().take(top) {
+            let share = 100.0 * time.as_secs_f64() / total.as_secs_f64().max(f64::EPSILON);
+            println!("  {:width$}  {:>10.3} ms  {:>5.1}%", rule, time.as_secs_f64() * 1e3, share, width = width);
+        }
+    }
+    #[cfg(not(feature = "timing"))]
+    {
+        let _ = top;
+        println!("allocation counts and per-rule timing need the `timing` feature");
+    }
+}
+
+/// `fmt <rules.json> <source>`: prints the source formatted by the rule set's `"format"` rules
+fn fmt(args: &[String]) {
+    if args.len() != 2 {
+        eprintln!("usage: lexer-generator fmt <rules.json> <source>");
+        std::process::exit(2);
+    }
+    let read = |path: &String| std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        std::process::exit(1);
+    });
+    let rules = RuleSet::load(&read(&args[0])).unwrap_or_else(|e| {
+        eprintln!("{}: {}", args[0], e);
+        std::process::exit(1);
+    });
+    match Formatter::new(&rules).format(&read(&args[1])) {
+        Ok(formatted) => print!("{}", formatted),
+        Err(e) => {
+            eprintln!("{}: {}", args[1], e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `diff <rules.json> <old> <new>`: prints the token-level changes between two versions of a source, ignoring
+/// whitespace and comments, and exits with 1 if there are any, like `diff`
+fn token_diff(args: &[String]) {
+    if args.len() != 3 {
+        eprintln!("usage: lexer-generator diff <rules.json> <old> <new>");
+        std::process::exit(2);
+    }
+    let read = |path: &String| std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        std::process::exit(2);
+    });
+    let rules = RuleSet::load(&read(&args[0])).unwrap_or_else(|e| {
+        eprintln!("{}: {}", args[0], e);
+        std::process::exit(2);
+    });
+    match TokenDiff::new(&rules).diff(&read(&args[1]), &read(&args[2])) {
+        Ok(changes) => {
+            for change in &changes {
+                print!("{}", change);
+            }
+            std::process::exit(if changes.is_empty() { 0 } else { 1 });
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// `<rules.json> <source>`: prints the tokens of the source as a table, or as `--format jsonl|csv|msgpack` records,
+/// `debug` explains how each one was chosen, `check` validates a rule set, `bench` measures its speed, `fmt`
+/// formats the source and `diff` compares two versions of it
+fn main() {
+    let mut argv: Vec<String> = std::env::args().collect();
+    if argv.len() > 1 && argv[1] == "check" {
+        return check(&argv[2..]);
+    }
+    if argv.len() > 1 && argv[1] == "bench" {
+        return bench(&argv[2..]);
+    }
+    if argv.len() > 1 && argv[1] == "fmt" {
+        return fmt(&argv[2..]);
+    }
+    if argv.len() > 1 && argv[1] == "diff" {
+        return token_diff(&argv[2..]);
+    }
+    let mut format = None;
+    if let Some(i) = argv.iter().position(|arg| arg == "--format") {
+        if i + 1 >= argv.len() {
+            eprintln!("--format needs one of table, jsonl, csv or msgpack");
+            std::process::exit(2);
+        }
+        format = Some(argv.remove(i + 1));
+        argv.remove(i);
+    } else if let Some(i) = argv.iter().position(|arg| arg.starts_with("--format=")) {
+        format = Some(argv.remove(i)["--format=".len()..].to_string());
+    }
+    if argv.len() > 1 && argv[1] == "debug" {
+        if format.is_some() {
+            eprintln!("debug doesn't take --format, it always prints its explanations as text");
+            std::process::exit(2);
+        }
+        return debug(&argv[2..]);
+    }
+    let format = format.unwrap_or_else(|| String::from("table"));
+    if !["table", "jsonl", "csv", "msgpack"].contains(&format.as_str()) {
+        eprintln!("unknown format {:?}, expected table, jsonl, csv or msgpack", format);
+        std::process::exit(2);
+    }
+    if argv.len() < 3 {
+        eprintln!("usage: lexer-generator [--format table|jsonl|csv|msgpack] <rules.json> <source>");
+        eprintln!("       lexer-generator debug <rules.json> <source>");
+        eprintln!("       lexer-generator check <rules.json> [--tests <tests.json>] [--format text|json]");
+        eprintln!("       lexer-generator bench <rules.json> <corpus> [--top N]");
+        eprintln!("       lexer-generator fmt <rules.json> <source>");
+        eprintln!("       lexer-generator diff <rules.json> <old> <new>");
+        std::process::exit(2);
+    }
+    let read = |path: &String| std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        std::process::exit(1);
+    });
+    let rules = CompiledRules::from_json(&read(&argv[1])).unwrap_or_else(|e| {
+        eprintln!("{}: {}", argv[1], e);
+        std::process::exit(1);
+    });
+    let lexer = Lexer::from_compiled(Arc::new(rules), read(&argv[2]));
+    if format == "table" {
+        print!("{}", lexer.dump());
+    } else {
+        structured(lexer, &format);
+    }
 }
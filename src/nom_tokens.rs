@@ -0,0 +1,122 @@
+//! Lexed tokens as input for nom parsers, only built with the `nom` feature
+
+use nom::error::{ErrorKind, ParseError};
+use nom::{IResult, InputIter, InputLength, InputTake, Needed, Slice, UnspecializedInput};
+
+use std::iter::Enumerate;
+use std::ops::{Range, RangeFrom, RangeFull, RangeTo};
+
+use crate::Token;
+
+/// A slice of tokens as nom input, parsers take one token of a type with `token_kind`
+///
+/// ```
+/// use lexer_generator::{token_kind, Lexer, TokenSlice};
+/// use nom::multi::many0;
+///
+/// let rules = String::from(r#"{ "literals": { "number": "[0-9]+", "comma": "," } }"#);
+/// let tokens: Vec<_> = Lexer::from(rules, String::from("1 2 ,")).map(Result::unwrap).collect();
+/// let (rest, numbers) = many0(token_kind::<nom::error::Error<_>>("number"))(TokenSlice::new(&tokens)).unwrap();
+/// assert_eq!(numbers.len(), 2);
+/// assert_eq!(rest.tokens()[0].value, ",");
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct TokenSlice<'a> {
+    tokens: &'a [Token]
+}
+
+impl<'a> TokenSlice<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Self { tokens }
+    }
+
+    /// The tokens not consumed yet
+    pub fn tokens(&self) -> &'a [Token] {
+        self.tokens
+    }
+}
+
+impl<'a> From<&'a [Token]> for TokenSlice<'a> {
+    fn from(tokens: &'a [Token]) -> Self {
+        Self::new(tokens)
+    }
+}
+
+impl InputLength for TokenSlice<'_> {
+    fn input_len(&self) -> usize {
+        self.tokens.len()
+    }
+}
+
+impl InputTake for TokenSlice<'_> {
+    fn take(&self, count: usize) -> Self {
+        Self::new(&self.tokens[..count])
+    }
+
+    fn take_split(&self, count: usize) -> (Self, Self) {
+        let (prefix, suffix) = self.tokens.split_at(count);
+        (Self::new(suffix), Self::new(prefix))
+    }
+}
+
+impl<'a> InputIter for TokenSlice<'a> {
+    type Item = &'a Token;
+    type Iter = Enumerate<std::slice::Iter<'a, Token>>;
+    type IterElem = std::slice::Iter<'a, Token>;
+
+    fn iter_indices(&self) -> Self::Iter {
+        self.tokens.iter().enumerate()
+    }
+
+    fn iter_elements(&self) -> Self::IterElem {
+        self.tokens.iter()
+    }
+
+    fn position<P>(&self, predicate: P) -> Option<usize> where P: Fn(Self::Item) -> bool {
+        self.tokens.iter().position(predicate)
+    }
+
+    fn slice_index(&self, count: usize) -> Result<usize, Needed> {
+        if count <= self.tokens.len() {
+            Ok(count)
+        } else {
+            Err(Needed::new(count - self.tokens.len()))
+        }
+    }
+}
+
+impl UnspecializedInput for TokenSlice<'_> {}
+
+impl Slice<Range<usize>> for TokenSlice<'_> {
+    fn slice(&self, range: Range<usize>) -> Self {
+        Self::new(&self.tokens[range])
+    }
+}
+
+impl Slice<RangeTo<usize>> for TokenSlice<'_> {
+    fn slice(&self, range: RangeTo<usize>) -> Self {
+        Self::new(&self.tokens[range])
+    }
+}
+
+impl Slice<RangeFrom<usize>> for TokenSlice<'_> {
+    fn slice(&self, range: RangeFrom<usize>) -> Self {
+        Self::new(&self.tokens[range])
+    }
+}
+
+impl Slice<RangeFull> for TokenSlice<'_> {
+    fn slice(&self, _: RangeFull) -> Self {
+        *self
+    }
+}
+
+/// Parser taking the next token if it is of type `kind`, failing with `ErrorKind::Tag` otherwise or `ErrorKind::Eof`
+/// at the end
+pub fn token_kind<'a, E: ParseError<TokenSlice<'a>>>(kind: &str) -> impl Fn(TokenSlice<'a>) -> IResult<TokenSlice<'a>, &'a Token, E> + '_ {
+    move |input: TokenSlice<'a>| match input.tokens.split_first() {
+        Some((token, rest)) if token.token_type == kind => Ok((TokenSlice::new(rest), token)),
+        Some(_) => Err(nom::Err::Error(E::from_error_kind(input, ErrorKind::Tag))),
+        None => Err(nom::Err::Error(E::from_error_kind(input, ErrorKind::Eof)))
+    }
+}
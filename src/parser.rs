@@ -0,0 +1,175 @@
+//! Generic parse trees built from a lexer's tokens by the grammar section
+
+use serde_json::{json, Value};
+
+use std::ops::Range;
+
+use crate::{CursorPos, Grammar, Lexer, Ll1Table, ParsingError, Token, TokenCursor, END_OF_INPUT};
+
+#[derive(Clone, Debug)]
+/// Concrete syntax tree, every nonterminal expanded becomes a node and every token a leaf
+pub enum Cst {
+    Node {
+        kind: String,
+        /// Index of the alternative the node was expanded with
+        alternative: usize,
+        children: Vec<Cst>,
+        /// Byte range of the node's tokens, empty at the position of the next token if it has none
+        span: Range<usize>
+    },
+    Token(Token)
+}
+
+impl Cst {
+    /// Nonterminal name of a node or token type of a leaf
+    pub fn kind(&self) -> &str {
+        match self {
+            Cst::Node { kind, .. } => kind,
+            Cst::Token(token) => &token.token_type
+        }
+    }
+
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Cst::Node { span, .. } => span.clone(),
+            Cst::Token(token) => token.span.clone()
+        }
+    }
+
+    /// Children of a node, leaves have none
+    pub fn children(&self) -> &[Cst] {
+        match self {
+            Cst::Node { children, .. } => children,
+            Cst::Token(_) => &[]
+        }
+    }
+
+    /// Formats the tree as an S-expression, `(kind children...)` for nodes and `(type "value")` for tokens
+    pub fn to_sexpr(&self) -> String {
+        match self {
+            Cst::Node { kind, children, .. } => {
+                let mut out = format!("({}", kind);
+                for child in children {
+                    out.push(' ');
+                    out.push_str(&child.to_sexpr());
+                }
+                out.push(')');
+                out
+            }
+            Cst::Token(token) => format!("({} {:?})", token.token_type, token.value)
+        }
+    }
+
+    /// Serializes the tree to JSON, nodes as `{ "kind", "alternative", "span", "children" }` and tokens as
+    /// `{ "kind", "value", "span", "line", "column" }`, spans being `[start, end]` byte offsets
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_value()).unwrap()
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            Cst::Node { kind, alternative, children, span } => json!({
+                "kind": kind,
+                "alternative": alternative,
+                "span": [span.start, span.end],
+                "children": children.iter().map(Cst::to_value).collect::<Vec<Value>>()
+            }),
+            Cst::Token(token) => json!({
+                "kind": token.token_type,
+                "value": token.value,
+                "span": [token.span.start, token.span.end],
+                "line": token.line,
+                "column": token.column
+            })
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ParseError {
+    /// The next token doesn't fit the grammar, `found` is None at the end of the input
+    Unexpected { expected: Vec<String>, found: Option<Box<Token>> },
+    Lexing(ParsingError),
+    /// The nonterminal derives itself without consuming a token, so expanding it would never end
+    LeftRecursion(String)
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Unexpected { expected, found: Some(token) } => write!(f, "{}: expected {}, found {}", token.location(), expected.join(" or "), token),
+            ParseError::Unexpected { expected, found: None } => write!(f, "expected {}, found end of input", expected.join(" or ")),
+            ParseError::Lexing(e) => write!(f, "{}", e),
+            ParseError::LeftRecursion(nonterminal) => write!(f, "{} is left recursive", nonterminal)
+        }
+    }
+}
+
+/// Predictive recursive descent parser for a rule set's grammar section
+///
+/// Where the grammar isn't LL(1) the first of the conflicting alternatives is taken, left recursive grammars are
+/// refused
+pub struct Parser {
+    grammar: Grammar,
+    table: Ll1Table
+}
+
+impl Parser {
+    /// Builds the parser, `ParseError::LeftRecursion` with the first left recursive nonterminal if there is one
+    pub fn new(grammar: Grammar) -> Result<Self, ParseError> {
+        let table = grammar.ll1_table();
+        match table.left_recursive.iter().next() {
+            Some(nonterminal) => Err(ParseError::LeftRecursion(nonterminal.clone())),
+            None => Ok(Self { grammar, table })
+        }
+    }
+
+    /// The parse table the parser is driven by, including any conflicts
+    pub fn table(&self) -> &Ll1Table {
+        &self.table
+    }
+
+    /// Lexes the rest of the lexer's input and parses it from the grammar's start symbol
+    pub fn parse(&self, lexer: &mut Lexer) -> Result<Cst, ParseError> {
+        let mut input = TokenCursor::lex(lexer).map_err(ParseError::Lexing)?;
+        let tree = self.expand(&self.grammar.start, &mut input, &mut vec![])?;
+        match input.peek() {
+            Some(token) => Err(ParseError::Unexpected { expected: vec![String::from(END_OF_INPUT)], found: Some(Box::new(token.clone())) }),
+            None => Ok(tree)
+        }
+    }
+
+    /// Expands `nonterminal` at the cursor, `active` holding the nonterminals being expanded and where they started
+    fn expand<'g>(&'g self, nonterminal: &'g str, input: &mut TokenCursor, active: &mut Vec<(&'g str, CursorPos)>) -> Result<Cst, ParseError> {
+        let first = input.save();
+        if active.contains(&(nonterminal, first)) {
+            return Err(ParseError::LeftRecursion(nonterminal.to_string()));
+        }
+        let lookahead = input.peek().map_or(END_OF_INPUT, |t| t.token_type.as_str());
+        let alternative = match self.table.get(nonterminal, lookahead) {
+            Some(alternative) => alternative,
+            None => return Err(ParseError::Unexpected {
+                expected: self.table.table.get(nonterminal).map_or(vec![], |row| row.keys().cloned().collect()),
+                found: input.peek().cloned().map(Box::new)
+            })
+        };
+
+        let mut children = vec![];
+        for symbol in &self.grammar.productions[nonterminal][alternative] {
+            if self.grammar.is_nonterminal(symbol) {
+                active.push((nonterminal, first));
+                let child = self.expand(symbol, input, active);
+                active.pop();
+                children.push(child?);
+            } else {
+                children.push(Cst::Token(input.expect(symbol)?.clone()));
+            }
+        }
+        let span = match input.since(first) {
+            [] => input.offset()..input.offset(),
+            [start, .., end] => start.span.start..end.span.end,
+            [only] => only.span.clone()
+        };
+        Ok(Cst::Node { kind: nonterminal.to_string(), alternative, children, span })
+    }
+}
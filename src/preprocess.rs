@@ -0,0 +1,103 @@
+//! Rewriting a source before lexing, see `Lexer::with_preprocessor`
+//!
+//! A preprocessor returns the replacements to make in the text, `(range of the text, new text)` in order and not
+//! overlapping, so the lexer knows where every byte of the rewritten text came from and spans can refer to the
+//! original text.
+
+use std::ops::Range;
+
+/// Removes the `\r` of `\r\n` line breaks
+pub fn strip_cr(text: &str) -> Vec<(Range<usize>, String)> {
+    text.match_indices("\r\n").map(|(i, _)| (i..i + 1, String::new())).collect()
+}
+
+/// Removes spaces and tabs at the end of every line
+pub fn trim_trailing_whitespace(text: &str) -> Vec<(Range<usize>, String)> {
+    let mut replacements = vec![];
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches('\n').trim_end_matches('\r');
+        let trimmed = content.trim_end_matches([' ', '\t']);
+        if trimmed.len() < content.len() {
+            replacements.push((start + trimmed.len()..start + content.len(), String::new()));
+        }
+        start += line.len();
+    }
+    replacements
+}
+
+/// Replaces tabs with spaces up to the next multiple of `width` columns, counted in characters
+pub fn expand_tabs(width: usize) -> impl Fn(&str) -> Vec<(Range<usize>, String)> + Send + Sync {
+    assert!(width > 0, "tabs can't expand to 0 columns");
+    move |text: &str| {
+        let mut replacements = vec![];
+        let mut column = 0;
+        for (i, c) in text.char_indices() {
+            match c {
+                '\t' => {
+                    let spaces = width - column % width;
+                    replacements.push((i..i + 1, " ".repeat(spaces)));
+                    column += spaces;
+                }
+                '\n' => column = 0,
+                _ => column += 1
+            }
+        }
+        replacements
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Where the offsets of a rewritten text come from in the text before, built by `OffsetMap::apply`
+pub struct OffsetMap {
+    /// (range in the rewritten text, range in the text before) of every replacement, in order
+    edits: Vec<(Range<usize>, Range<usize>)>
+}
+
+impl OffsetMap {
+    /// Makes the `replacements` in `text`, returning the rewritten text and its map
+    ///
+    /// Panics if the replacements aren't in order, overlap or split a character.
+    pub fn apply(text: &str, replacements: Vec<(Range<usize>, String)>) -> (String, Self) {
+        let mut rewritten = String::with_capacity(text.len());
+        let mut edits = vec![];
+        let mut copied = 0;
+        for (range, replacement) in replacements {
+            assert!(copied <= range.start && range.start <= range.end, "replacement {:?} overlaps the one before or is reversed", range);
+            rewritten.push_str(&text[copied..range.start]);
+            let start = rewritten.len();
+            rewritten.push_str(&replacement);
+            edits.push((start..rewritten.len(), range.clone()));
+            copied = range.end;
+        }
+        rewritten.push_str(&text[copied..]);
+        (rewritten, Self { edits })
+    }
+
+    /// Offset in the text before of where something starting at `offset` of the rewritten text starts, the start of
+    /// the replaced text for an offset inside a replacement
+    pub fn start(&self, offset: usize) -> usize {
+        match self.edits[..self.edits.partition_point(|(new, _)| new.start <= offset)].last() {
+            Some((new, old)) if offset >= new.end => old.end + (offset - new.end),
+            Some((_, old)) => old.start,
+            None => offset
+        }
+    }
+
+    /// Offset in the text before of where something ending at `offset` of the rewritten text ends, the end of the
+    /// replaced text for an offset inside a replacement
+    pub fn end(&self, offset: usize) -> usize {
+        match self.edits.get(self.edits.partition_point(|(new, _)| new.end < offset)) {
+            Some((new, old)) if offset <= new.start => old.start - (new.start - offset),
+            Some((_, old)) => old.end,
+            None => self.edits.last().map_or(offset, |(new, old)| old.end + (offset - new.end))
+        }
+    }
+
+    /// Range in the text before of a range of the rewritten text
+    pub fn span(&self, span: Range<usize>) -> Range<usize> {
+        // an empty range stays empty
+        let start = if span.is_empty() { self.end(span.start) } else { self.start(span.start) };
+        start..self.end(span.end).max(start)
+    }
+}
@@ -0,0 +1,94 @@
+//! Recompiling a rule set file whenever it changes, for tools whose users edit their grammar live, only built with the
+//! `notify` feature
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::{CompileError, CompiledRules};
+
+#[derive(Debug)]
+pub enum ReloadError {
+    /// The file couldn't be read
+    Io(std::io::Error),
+    Compile(CompileError),
+    /// Watching the file failed
+    Watch(notify::Error)
+}
+
+impl std::fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReloadError::Io(e) => write!(f, "{}", e),
+            ReloadError::Compile(e) => write!(f, "{}", e),
+            ReloadError::Watch(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+/// Watches a rule set file and recompiles it whenever it changes, until it is dropped
+///
+/// A version which doesn't compile is reported and the last rules which did stay current, so a typo in the grammar
+/// doesn't take the host down. Lexers pick up the new rules with `Lexer::swap_rules`.
+///
+/// ```no_run
+/// use lexer_generator::{Lexer, RulesWatcher};
+///
+/// let watcher = RulesWatcher::new("grammar.json", |reloaded| if let Err(e) = reloaded {
+///     eprintln!("grammar.json: {}", e);
+/// }).unwrap();
+/// let mut lexer = Lexer::from_compiled(watcher.rules(), String::from("x = 1"));
+/// // before lexing a document again
+/// lexer.swap_rules(watcher.rules());
+/// ```
+pub struct RulesWatcher {
+    current: Arc<Mutex<Arc<CompiledRules>>>,
+    _watcher: RecommendedWatcher
+}
+
+impl RulesWatcher {
+    /// Compiles the rule set at `path`, failing if it can't be read, compiled or watched, then recompiles it on every
+    /// change and calls `on_reload` with the new rules or why they were rejected
+    pub fn new(path: impl AsRef<Path>, mut on_reload: impl FnMut(Result<Arc<CompiledRules>, ReloadError>) + Send + 'static) -> Result<Self, ReloadError> {
+        let path = path.as_ref().to_path_buf();
+        let current = Arc::new(Mutex::new(Arc::new(compile(&path)?)));
+        let shared = current.clone();
+        let file = path.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => return on_reload(Err(ReloadError::Watch(e)))
+            };
+            let changed = event.kind.is_create() || event.kind.is_modify();
+            if !changed || !event.paths.iter().any(|p| p.file_name() == file.file_name()) {
+                return;
+            }
+            match compile(&file) {
+                Ok(rules) => {
+                    let rules = Arc::new(rules);
+                    *shared.lock().unwrap() = rules.clone();
+                    on_reload(Ok(rules));
+                }
+                Err(e) => on_reload(Err(e))
+            }
+        }).map_err(ReloadError::Watch)?;
+        // editors often save by replacing the file, a watch on the file itself wouldn't survive that
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new(".")
+        };
+        watcher.watch(dir, RecursiveMode::NonRecursive).map_err(ReloadError::Watch)?;
+        Ok(Self { current, _watcher: watcher })
+    }
+
+    /// The rules compiled from the last version of the file which compiled
+    pub fn rules(&self) -> Arc<CompiledRules> {
+        self.current.lock().unwrap().clone()
+    }
+}
+
+fn compile(path: &Path) -> Result<CompiledRules, ReloadError> {
+    let json = std::fs::read_to_string(path).map_err(ReloadError::Io)?;
+    CompiledRules::from_json(&json).map_err(ReloadError::Compile)
+}
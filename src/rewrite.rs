@@ -0,0 +1,113 @@
+//! Editing a source by the spans of its tokens, for refactoring and codemod tools
+
+use std::ops::Range;
+
+use crate::preprocess::OffsetMap;
+use crate::Token;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RewriteError {
+    /// Two edits change the same text, in the order they were added
+    Overlap(Range<usize>, Range<usize>),
+    /// An edit reaches past the end of the source or splits a character
+    OutOfBounds(Range<usize>)
+}
+
+impl std::fmt::Display for RewriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RewriteError::Overlap(a, b) => write!(f, "edits of {:?} and {:?} overlap", a, b),
+            RewriteError::OutOfBounds(span) => write!(f, "edit of {:?} is outside the source", span)
+        }
+    }
+}
+
+/// Collects edits of a source, replacements of spans usually taken from its tokens, and makes them all at once
+///
+/// Edits can be added in any order, they refer to the original source. Insertions at the same place are made in the
+/// order they were added, and an insertion at the start or end of a replaced span is no overlap.
+///
+/// ```
+/// use lexer_generator::{Lexer, Rewriter};
+///
+/// let source = "let x = x + 1";
+/// let rules = String::from(r#"{ "literals": { "let": "let", "name": "[a-z]+", "number": "[0-9]+", "op": "[=+]" } }"#);
+/// let mut rewriter = Rewriter::new(source);
+/// for token in Lexer::from(rules, source.to_string()).map(Result::unwrap) {
+///     match token.token_type.as_str() {
+///         "let" => rewriter.replace_token(&token, "const"),
+///         "name" if token.value == "x" => rewriter.replace_token(&token, "count"),
+///         _ => {}
+///     }
+/// }
+/// assert_eq!(rewriter.finish().unwrap(), "const count = count + 1");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Rewriter<'a> {
+    source: &'a str,
+    edits: Vec<(Range<usize>, String)>
+}
+
+impl<'a> Rewriter<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self { source, edits: vec![] }
+    }
+
+    /// Replaces the text of `span` with `text`
+    pub fn replace(&mut self, span: Range<usize>, text: &str) {
+        self.edits.push((span, text.to_string()));
+    }
+
+    pub fn replace_token(&mut self, token: &Token, text: &str) {
+        self.replace(token.span.clone(), text);
+    }
+
+    pub fn insert_before(&mut self, token: &Token, text: &str) {
+        self.replace(token.span.start..token.span.start, text);
+    }
+
+    pub fn insert_after(&mut self, token: &Token, text: &str) {
+        self.replace(token.span.end..token.span.end, text);
+    }
+
+    pub fn remove(&mut self, token: &Token) {
+        self.replace(token.span.clone(), "");
+    }
+
+    /// Number of edits added so far
+    pub fn len(&self) -> usize {
+        self.edits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// The edited source, or the first edit which is outside the source or the first two which overlap
+    pub fn finish(self) -> Result<String, RewriteError> {
+        self.finish_with_map().map(|(text, _)| text)
+    }
+
+    /// The edited source with where its offsets come from in the original source, e.g. to report positions of the
+    /// edited text in the original
+    pub fn finish_with_map(mut self) -> Result<(String, OffsetMap), RewriteError> {
+        let source = self.source;
+        if let Some((span, _)) = self.edits.iter().find(|(span, _)| {
+            span.start > span.end || span.end > source.len() || !source.is_char_boundary(span.start) || !source.is_char_boundary(span.end)
+        }) {
+            return Err(RewriteError::OutOfBounds(span.clone()));
+        }
+        // stable, so insertions at one place keep their order and come before a replacement starting there
+        let mut order: Vec<usize> = (0..self.edits.len()).collect();
+        order.sort_by_key(|&i| (self.edits[i].0.start, self.edits[i].0.end));
+        for pair in order.windows(2) {
+            let (a, b) = (&self.edits[pair[0]].0, &self.edits[pair[1]].0);
+            if b.start < a.end {
+                let (first, second) = if pair[0] < pair[1] { (a, b) } else { (b, a) };
+                return Err(RewriteError::Overlap(first.clone(), second.clone()));
+            }
+        }
+        let edits = order.into_iter().map(|i| std::mem::take(&mut self.edits[i])).collect();
+        Ok(OffsetMap::apply(source, edits))
+    }
+}
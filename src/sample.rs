@@ -0,0 +1,184 @@
+//! Random text matching the rules, for fuzzing parsers built on the lexer and checking patterns match what their
+//! authors expect, only built with the `sample` feature
+
+use regex::Regex;
+use regex_syntax::hir::{Class, Hir, HirKind, Literal, RepetitionKind, RepetitionRange};
+
+use crate::{Lexer, ParsingError, RuleSet};
+
+/// Attempts at a string or a token before giving up on it
+const ATTEMPTS: usize = 20;
+
+/// Generator of random strings matching patterns and of random token sequences, the same seed always gives the
+/// same samples so failures found with them can be reproduced
+///
+/// ```
+/// use lexer_generator::Sampler;
+///
+/// let mut sampler = Sampler::new(7);
+/// let number = sampler.sample_pattern("[0-9]+(\\.[0-9]+)?").unwrap();
+/// assert!(number.parse::<f64>().is_ok());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Sampler {
+    state: u64,
+    max_repeat: u32
+}
+
+impl Sampler {
+    /// A sampler seeded with `seed`, repeating unbounded parts of patterns at most 8 times beyond their minimum
+    pub fn new(seed: u64) -> Self {
+        // xorshift never leaves a zero state
+        Self { state: if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed }, max_repeat: 8 }
+    }
+
+    /// Repeat `*`, `+` and `{n,}` at most `max_repeat` times beyond their minimum
+    pub fn with_max_repeat(mut self, max_repeat: u32) -> Self {
+        self.max_repeat = max_repeat;
+        self
+    }
+
+    /// xorshift64*
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number in `0..n`, n must not be 0
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// A random string the whole pattern matches, None for invalid patterns and patterns which no attempt satisfied,
+    /// like ones relying on anchors or word boundaries
+    pub fn sample_pattern(&mut self, pattern: &str) -> Option<String> {
+        let hir = regex_syntax::Parser::new().parse(pattern).ok()?;
+        let whole = Regex::new(&format!("^(?:{})$", pattern)).ok()?;
+        (0..ATTEMPTS).find_map(|_| {
+            let mut out = String::new();
+            if self.walk(&hir, &mut out) && whole.is_match(&out) { Some(out) } else { None }
+        })
+    }
+
+    /// A random non-empty string matching rule `name` of any mode, None if there is no such rule or no sample was found
+    pub fn sample_rule(&mut self, rules: &RuleSet, name: &str) -> Option<String> {
+        let rule = rules.all_rules().find(|(n, _)| *n == name)?.1;
+        (0..ATTEMPTS).find_map(|_| self.sample_pattern(&rule.pattern()).filter(|s| !s.is_empty()))
+    }
+
+    /// A source of up to `count` random tokens with the (type, raw text) pairs it lexes to, separated by a space
+    /// where the main mode's whitespace allows it
+    ///
+    /// Each token is kept only if lexing the source so far gives back exactly the tokens generated, so tokens which
+    /// would join with the one before, need another mode or would be merged are skipped and the list may be shorter
+    pub fn tokens(&mut self, rules: &RuleSet, count: usize) -> (String, Vec<(String, String)>) {
+        let names: Vec<String> = {
+            let mut names: Vec<String> = rules.all_rules().map(|(n, _)| n.clone()).collect();
+            names.sort();
+            names.dedup();
+            names
+        };
+        let separator = match rules.whitespace.as_ref().and_then(|p| Regex::new(&format!("^(?:{})$", p)).ok()) {
+            Some(whitespace) if whitespace.is_match(" ") => " ",
+            _ => ""
+        };
+        let mut lexer = Lexer::from_rules(rules.clone(), String::new());
+        let mut source = String::new();
+        let mut tokens: Vec<(String, String)> = vec![];
+        if names.is_empty() {
+            return (source, tokens);
+        }
+        for _ in 0..count {
+            for _ in 0..ATTEMPTS {
+                let name = &names[self.below(names.len() as u64) as usize];
+                let raw = match self.sample_rule(rules, name) {
+                    Some(raw) => raw,
+                    None => continue
+                };
+                let text = if source.is_empty() { raw.clone() } else { format!("{}{}{}", source, separator, raw) };
+                tokens.push((name.clone(), raw));
+                if lexes_to(&mut lexer, &text, &tokens) {
+                    source = text;
+                    break;
+                }
+                tokens.pop();
+            }
+        }
+        (source, tokens)
+    }
+
+    fn walk(&mut self, hir: &Hir, out: &mut String) -> bool {
+        match hir.kind() {
+            HirKind::Empty | HirKind::Anchor(_) | HirKind::WordBoundary(_) => true,
+            HirKind::Literal(Literal::Unicode(c)) => { out.push(*c); true }
+            HirKind::Literal(Literal::Byte(b)) => { out.push(char::from(*b)); true }
+            HirKind::Class(Class::Unicode(class)) => {
+                let ranges: Vec<(u32, u32)> = class.iter().map(|r| (r.start() as u32, r.end() as u32)).collect();
+                self.push_from(&ranges, out)
+            }
+            HirKind::Class(Class::Bytes(class)) => {
+                let ranges: Vec<(u32, u32)> = class.iter().filter(|r| r.start().is_ascii()).map(|r| (r.start() as u32, r.end().min(0x7f) as u32)).collect();
+                self.push_from(&ranges, out)
+            }
+            HirKind::Repetition(repetition) => {
+                let (min, max) = match &repetition.kind {
+                    RepetitionKind::ZeroOrOne => (0, 1),
+                    RepetitionKind::ZeroOrMore => (0, self.max_repeat),
+                    RepetitionKind::OneOrMore => (1, 1 + self.max_repeat),
+                    RepetitionKind::Range(RepetitionRange::Exactly(n)) => (*n, *n),
+                    RepetitionKind::Range(RepetitionRange::AtLeast(n)) => (*n, n + self.max_repeat),
+                    RepetitionKind::Range(RepetitionRange::Bounded(m, n)) => (*m, *n)
+                };
+                let times = min + self.below(u64::from(max - min) + 1) as u32;
+                (0..times).all(|_| self.walk(&repetition.hir, out))
+            }
+            HirKind::Group(group) => self.walk(&group.hir, out),
+            HirKind::Concat(hirs) => hirs.iter().all(|h| self.walk(h, out)),
+            HirKind::Alternation(hirs) => {
+                let i = self.below(hirs.len() as u64) as usize;
+                self.walk(&hirs[i], out)
+            }
+        }
+    }
+
+    /// Pushes a random character of the ranges, mostly printable ASCII when the ranges have some so samples stay readable
+    fn push_from(&mut self, ranges: &[(u32, u32)], out: &mut String) -> bool {
+        let printable: Vec<(u32, u32)> = ranges.iter().filter(|(s, e)| *s <= 0x7e && *e >= 0x20).map(|(s, e)| ((*s).max(0x20), (*e).min(0x7e))).collect();
+        let ranges = if !printable.is_empty() && self.below(4) != 0 { &printable[..] } else { ranges };
+        let total: u64 = ranges.iter().map(|(s, e)| u64::from(e - s) + 1).sum();
+        if total == 0 {
+            return false;
+        }
+        let mut n = self.below(total);
+        for (s, e) in ranges {
+            let size = u64::from(e - s) + 1;
+            if n < size {
+                // surrogates are in no class, but stay safe
+                return match char::from_u32(s + n as u32) {
+                    Some(c) => { out.push(c); true }
+                    None => false
+                };
+            }
+            n -= size;
+        }
+        false
+    }
+}
+
+/// Whether lexing `text` gives exactly the (type, raw text) pairs of `expected`
+fn lexes_to(lexer: &mut Lexer, text: &str, expected: &[(String, String)]) -> bool {
+    lexer.reset(text.to_string());
+    let mut expected = expected.iter();
+    loop {
+        match lexer.next_token() {
+            Ok(token) => match expected.next() {
+                Some((token_type, raw)) if *token_type == token.token_type && *raw == token.raw => {}
+                _ => return false
+            },
+            Err(ParsingError::EndOfFileError) => return expected.next().is_none(),
+            Err(_) => return false
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Cursor over the source for hand-written rules, registered next to the JSON rules with `Lexer::with_matcher`
+
+use regex::Regex;
+
+/// Cursor over the source from the position where a token may start, `RuleMatcher`s move it over the text of their
+/// token
+#[derive(Clone, Debug)]
+pub struct Scanner<'a> {
+    source: &'a str,
+    pos: usize,
+    end: usize
+}
+
+impl<'a> Scanner<'a> {
+    /// A cursor at byte `pos` of `source` which never moves past byte `end`
+    pub fn new(source: &'a str, pos: usize, end: usize) -> Self {
+        Self { source, pos, end }
+    }
+
+    /// Byte offset of the cursor in the source
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// The source from the cursor to the end
+    pub fn rest(&self) -> &'a str {
+        &self.source[self.pos..self.end]
+    }
+
+    /// The character at the cursor, None at the end
+    pub fn peek_char(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    /// Moves past the character at the cursor and returns it, None at the end
+    pub fn bump(&mut self) -> Option<char> {
+        let c = self.peek_char()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Moves past the characters for which `f` is true, returning how many bytes were skipped
+    pub fn eat_while(&mut self, mut f: impl FnMut(char) -> bool) -> usize {
+        let start = self.pos;
+        while self.peek_char().is_some_and(&mut f) {
+            self.bump();
+        }
+        self.pos - start
+    }
+
+    /// Moves past `text` if the source continues with it
+    pub fn eat(&mut self, text: &str) -> bool {
+        if self.rest().starts_with(text) {
+            self.pos += text.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Moves past a match of `regex` starting at the cursor and returns it, None if it doesn't match right there
+    ///
+    /// `^` in the regex matches at the cursor
+    pub fn match_regex_at(&mut self, regex: &Regex) -> Option<&'a str> {
+        let rest = self.rest();
+        let mat = regex.find(rest).filter(|mat| mat.start() == 0)?;
+        self.pos += mat.end();
+        Some(&rest[..mat.end()])
+    }
+}
+
+/// A hand-written rule, for token types which are simpler or faster to match in code than with a regex
+///
+/// ```
+/// use lexer_generator::{Lexer, RuleMatcher, Scanner};
+///
+/// struct Identifier;
+///
+/// impl RuleMatcher for Identifier {
+///     fn scan(&self, scanner: &mut Scanner) -> bool {
+///         scanner.peek_char().is_some_and(|c| c.is_alphabetic()) && scanner.eat_while(|c| c.is_alphanumeric()) > 0
+///     }
+/// }
+///
+/// let rules = String::from(r#"{ "literals": { "equals": "=" } }"#);
+/// let mut lexer = Lexer::from(rules, String::from("x = y2")).with_matcher("identifier", Box::new(Identifier));
+/// let types: Vec<String> = std::iter::from_fn(|| lexer.next_token().ok()).map(|t| t.token_type).collect();
+/// assert_eq!(types, ["identifier", "equals", "identifier"]);
+/// ```
+pub trait RuleMatcher: Send + Sync {
+    /// Moves the scanner over the token's text and returns true if the token starts at the scanner, the token is the
+    /// text moved over. Returning false or not moving means the rule doesn't match
+    fn scan(&self, scanner: &mut Scanner) -> bool;
+}
@@ -0,0 +1,361 @@
+//! JSON Schema of rule set files, and a validator for the parts of JSON Schema it uses
+
+use serde_json::Value;
+
+/// JSON Schema (draft-07) of rule set files
+pub const JSON_SCHEMA: &str = r##"{
+    "$schema": "http://json-schema.org/draft-07/schema#",
+    "title": "lexer-generator rule set",
+    "type": "object",
+    "required": ["literals"],
+    "additionalProperties": false,
+    "properties": {
+        "version": { "type": "integer", "minimum": 1, "maximum": 2, "description": "Schema version, 1 if missing" },
+        "literals": {
+            "type": "object",
+            "description": "Token types and the patterns they match",
+            "additionalProperties": { "$ref": "#/definitions/rule" }
+        },
+        "whitespace": { "type": ["string", "null"], "description": "Pattern skipped between tokens, \\s+ if missing, null to skip nothing" },
+        "whitespace_token": { "type": "string", "description": "Emit whitespace as tokens of this type instead of skipping it" },
+        "prefer_rules_over_whitespace": { "type": "boolean", "description": "Where whitespace and a rule both match, lex the rule" },
+        "modes": {
+            "type": "object",
+            "description": "Modes with their own rules and whitespace, entered and left by rules with push and pop",
+            "additionalProperties": {
+                "type": "object",
+                "required": ["literals"],
+                "additionalProperties": false,
+                "properties": {
+                    "literals": { "type": "object", "additionalProperties": { "$ref": "#/definitions/rule" } },
+                    "whitespace": { "type": ["string", "null"], "description": "Pattern skipped between tokens in the mode, \\s+ if missing, null to skip nothing" }
+                }
+            }
+        },
+        "conflicts": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "required": ["prefer", "over"],
+                "additionalProperties": false,
+                "properties": {
+                    "prefer": { "type": "string" },
+                    "over": { "type": "string" },
+                    "ignore_length": { "type": "boolean" }
+                }
+            }
+        },
+        "pairs": {
+            "type": "array",
+            "description": "Opener and closer token types which have to be balanced",
+            "items": { "type": "array", "minItems": 2, "maxItems": 2, "items": { "type": "string" } }
+        },
+        "asi": {
+            "type": "object",
+            "description": "Insert an emit token where a line ends after a token of one of the after types",
+            "required": ["after", "emit"],
+            "additionalProperties": false,
+            "properties": {
+                "after": { "type": "array", "items": { "type": "string" } },
+                "emit": { "type": "string" }
+            }
+        },
+        "merge": {
+            "type": "array",
+            "description": "Sequences of tokens joined into one token after lexing",
+            "items": {
+                "type": "object",
+                "required": ["seq", "into"],
+                "additionalProperties": false,
+                "properties": {
+                    "seq": { "type": "array", "minItems": 2, "items": { "type": "string" } },
+                    "into": { "type": "string" },
+                    "adjacent_only": { "type": "boolean" }
+                }
+            }
+        },
+        "format": {
+            "type": "object",
+            "description": "Spacing rules of the formatter, by token type or category",
+            "additionalProperties": false,
+            "properties": {
+                "space_around": { "type": "array", "items": { "type": "string" } },
+                "newline_after": { "type": "array", "items": { "type": "string" } },
+                "indent": { "type": "array", "items": { "type": "string" } },
+                "indent_text": { "type": "string" }
+            }
+        },
+        "coalesce": { "type": "array", "items": { "type": "string" }, "description": "Token types whose runs are joined into one token" },
+        "line_directive": { "type": "string", "description": "Pattern of #line directives, with a line group and an optional file group" },
+        "skip_bom": { "type": "boolean" },
+        "skip_shebang": { "type": "boolean" },
+        "max_token_bytes": { "type": "integer", "minimum": 1, "description": "Most bytes held back for a token continuing in the next pushed chunk" },
+        "max_errors": { "type": "integer", "minimum": 0, "description": "Most errors reported, the next one stops lexing" },
+        "recover": { "enum": ["next_char", "next_line", "none"], "description": "How much input is discarded after text no rule matches" },
+        "max_pattern_size": { "type": "integer", "minimum": 1, "description": "Compiled size in bytes above which a rule's pattern is reported as too large" },
+        "grammar": {
+            "type": "object",
+            "required": ["start", "productions"],
+            "additionalProperties": false,
+            "properties": {
+                "start": { "type": "string" },
+                "productions": {
+                    "type": "object",
+                    "additionalProperties": { "type": "array", "items": { "type": "array", "items": { "type": "string" } } }
+                }
+            }
+        }
+    },
+    "definitions": {
+        "alternatives": { "type": "array", "minItems": 1, "items": { "type": "string" } },
+        "rule": {
+            "oneOf": [
+                { "type": "string" },
+                { "$ref": "#/definitions/alternatives" },
+                {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "pattern": { "oneOf": [{ "type": "string" }, { "$ref": "#/definitions/alternatives" }] },
+                        "literal": { "type": "string" },
+                        "number": {
+                            "type": "object",
+                            "description": "Numeric literals in this format instead of a pattern",
+                            "additionalProperties": false,
+                            "properties": {
+                                "radixes": { "type": "array", "items": { "enum": ["hex", "oct", "bin"] } },
+                                "separator": { "type": "string", "description": "Separator allowed between digits, like _" },
+                                "float": { "type": "boolean" },
+                                "exponent": { "type": "boolean" },
+                                "suffixes": { "type": "array", "items": { "type": "string" } }
+                            }
+                        },
+                        "native": { "type": "string", "description": "Name of a matcher registered by the application" },
+                        "until": { "type": "string", "description": "Closing text of the token, with ${group} replaced by the groups of the opening match" },
+                        "interpolate": {
+                            "type": "object",
+                            "description": "Expressions embedded in the string whose mode the rule pushes",
+                            "required": ["open", "close"],
+                            "additionalProperties": false,
+                            "properties": {
+                                "open": { "type": "string" },
+                                "close": { "type": "string" },
+                                "mode": { "type": "string", "description": "Mode of the expressions, main if missing" },
+                                "open_token": { "type": "string", "description": "Type of the token starting an expression, interpolation_open if missing" },
+                                "close_token": { "type": "string", "description": "Type of the token ending an expression, interpolation_close if missing" }
+                            }
+                        },
+                        "doc": {
+                            "type": "object",
+                            "description": "Tokens of the rule between these markers are doc comments, with the markers stripped from their value",
+                            "required": ["prefix"],
+                            "additionalProperties": false,
+                            "properties": {
+                                "prefix": { "type": "string" },
+                                "suffix": { "type": "string" },
+                                "token": { "type": "string", "description": "Type of the doc comments, doc_comment if missing" }
+                            }
+                        },
+                        "trim": { "type": "boolean" },
+                        "strip": { "type": "array", "minItems": 2, "maxItems": 2, "items": { "type": "string" } },
+                        "unescape": { "type": "boolean" },
+                        "normalize": { "enum": ["lower", "upper", "nfc"] },
+                        "escapes": { "type": "object", "description": "More escapes for unescape, from the character after the backslash to its text", "additionalProperties": { "type": "string" } },
+                        "push": { "type": "string", "description": "Mode entered after the token" },
+                        "pop": { "type": "boolean", "description": "Leave the current mode after the token" },
+                        "category": { "type": "string", "description": "Coarse class of the token type, like operator or keyword" },
+                        "tags": { "type": "array", "items": { "type": "string" }, "description": "Language variants the rule belongs to, it is only lexed when one is enabled" },
+                        "when_prev_is": { "type": "array", "items": { "type": "string" }, "description": "Only try the rule after these token types" },
+                        "when_prev_is_not": { "type": "array", "items": { "type": "string" }, "description": "Never try the rule after these token types" },
+                        "bol": { "type": "boolean", "description": "Only try the rule at the beginning of a line" },
+                        "eol": { "type": "boolean", "description": "Only match if the match ends at the end of a line" },
+                        "eof_only": { "type": "boolean", "description": "Only match if the match ends at the end of the source" },
+                        "max_len": { "type": "integer", "minimum": 1, "description": "Most characters a token of the rule may have" },
+                        "truncate": { "type": "boolean", "description": "Cut the value of a token longer than max_len instead of failing" }
+                    }
+                }
+            ]
+        }
+    }
+}"##;
+
+#[derive(Clone, Debug, PartialEq)]
+/// A place where a rule set file doesn't follow `JSON_SCHEMA`, `path` is a JSON pointer like `/literals/number`
+pub struct SchemaError {
+    pub path: String,
+    pub message: String
+}
+
+impl std::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() { "/" } else { &self.path };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+/// Checks a rule set file against `RuleSet::JSON_SCHEMA` without building a lexer, an empty result means it is valid
+///
+/// Only the shape is checked, `RuleSet::validate` finds invalid patterns and rules which are never matched
+pub fn validate_json(json: &str) -> Vec<SchemaError> {
+    let value: Value = match serde_json::from_str(json) {
+        Ok(value) => value,
+        Err(e) => return vec![SchemaError { path: String::new(), message: e.to_string() }]
+    };
+    let schema: Value = serde_json::from_str(JSON_SCHEMA).unwrap();
+    let mut errors = vec![];
+    check(&schema, &schema, &value, "", &mut errors);
+    errors
+}
+
+/// The known name closest to a misspelled one, if any is close enough to be a typo
+fn did_you_mean<'a>(name: &str, known: impl Iterator<Item = &'a String>) -> Option<&'a String> {
+    known
+        .map(|k| (edit_distance(name, k), k))
+        .filter(|(d, k)| *d <= 2.min(k.len() / 2))
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, k)| k)
+}
+
+/// Levenshtein distance in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+fn type_matches(name: &str, value: &Value) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "null" => value.is_null(),
+        _ => true
+    }
+}
+
+/// Whether the value has the schema's `"type"`, which may be a list of types, None if the schema has none
+fn schema_type_matches(schema: &Value, value: &Value) -> Option<bool> {
+    match schema.get("type")? {
+        Value::String(t) => Some(type_matches(t, value)),
+        Value::Array(types) => Some(types.iter().filter_map(Value::as_str).any(|t| type_matches(t, value))),
+        _ => None
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Object(_) => "an object",
+        Value::Array(_) => "an array",
+        Value::String(_) => "a string",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::Null => "null"
+    }
+}
+
+/// Follows `$ref`s, which may only point into the schema's definitions
+fn resolve<'a>(root: &'a Value, schema: &'a Value) -> &'a Value {
+    match schema.get("$ref").and_then(Value::as_str).and_then(|r| r.strip_prefix("#/definitions/")) {
+        Some(name) => resolve(root, &root["definitions"][name]),
+        None => schema
+    }
+}
+
+fn check(root: &Value, schema: &Value, value: &Value, path: &str, errors: &mut Vec<SchemaError>) {
+    let schema = resolve(root, schema);
+    let mut error = |message: String| errors.push(SchemaError { path: path.to_string(), message });
+
+    if let Some(false) = schema_type_matches(schema, value) {
+        let expected = match &schema["type"] {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(" or "),
+            other => other.as_str().unwrap_or_default().to_string()
+        };
+        error(format!("expected {}, found {}", expected, type_name(value)));
+        return;
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array).filter(|allowed| !allowed.contains(value)) {
+        let names: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+        error(format!("must be one of {}", names.join(", ")));
+    }
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64).filter(|min| n < *min) {
+            error(format!("must be at least {}", min));
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64).filter(|max| n > *max) {
+            error(format!("must be at most {}", max));
+        }
+    }
+
+    if let Some(branches) = schema.get("oneOf").and_then(Value::as_array) {
+        let results: Vec<Vec<SchemaError>> = branches.iter().map(|branch| {
+            let mut branch_errors = vec![];
+            check(root, branch, value, path, &mut branch_errors);
+            branch_errors
+        }).collect();
+        match results.iter().filter(|r| r.is_empty()).count() {
+            1 => {}
+            0 => {
+                // report the problems of the only form of the right type, if there is one
+                let typed: Vec<usize> = (0..branches.len()).filter(|i| {
+                    schema_type_matches(resolve(root, &branches[*i]), value) != Some(false)
+                }).collect();
+                match typed.as_slice() {
+                    [i] => errors.extend(results[*i].iter().cloned()),
+                    _ => errors.push(SchemaError { path: path.to_string(), message: format!("{} isn't one of the allowed forms", type_name(value)) })
+                }
+            }
+            _ => errors.push(SchemaError { path: path.to_string(), message: String::from("matches more than one form") })
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        for name in schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+            if !object.contains_key(name) {
+                errors.push(SchemaError { path: path.to_string(), message: format!("missing required property {}", name) });
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (name, item) in object {
+            let item_path = format!("{}/{}", path, name.replace('~', "~0").replace('/', "~1"));
+            match (properties.and_then(|p| p.get(name)), schema.get("additionalProperties")) {
+                (Some(property), _) => check(root, property, item, &item_path, errors),
+                (None, Some(Value::Bool(false))) => {
+                    let known = properties.into_iter().flat_map(|p| p.keys());
+                    let message = match did_you_mean(name, known) {
+                        Some(suggestion) => format!("unknown property {}, did you mean {}?", name, suggestion),
+                        None => format!("unknown property {}", name)
+                    };
+                    errors.push(SchemaError { path: item_path, message });
+                }
+                (None, Some(additional)) if additional.is_object() => check(root, additional, item, &item_path, errors),
+                _ => {}
+            }
+        }
+    }
+
+    if let Some(array) = value.as_array() {
+        if let Some(min) = schema.get("minItems").and_then(Value::as_u64).filter(|min| (array.len() as u64) < *min) {
+            errors.push(SchemaError { path: path.to_string(), message: format!("needs at least {} items", min) });
+        }
+        if let Some(max) = schema.get("maxItems").and_then(Value::as_u64).filter(|max| (array.len() as u64) > *max) {
+            errors.push(SchemaError { path: path.to_string(), message: format!("allows at most {} items", max) });
+        }
+        if let Some(items) = schema.get("items") {
+            for (i, item) in array.iter().enumerate() {
+                check(root, items, item, &format!("{}/{}", path, i), errors);
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Lexing sources which arrive asynchronously, only built with the `futures` feature
+
+use futures::io::AsyncRead;
+use futures::stream::Stream;
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::{Lexer, ParsingError, RuleSet, Token};
+
+#[derive(Debug)]
+pub enum LexError {
+    Lexing(ParsingError),
+    /// Reading failed, or the source wasn't valid UTF-8
+    Io(std::io::Error)
+}
+
+impl std::fmt::Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexError::Lexing(e) => write!(f, "{}", e),
+            LexError::Io(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+/// Stream of the tokens of an `AsyncRead` source, yielding each token as soon as the text after it has arrived
+///
+/// tokio readers can be adapted with `tokio_util::compat`. The whole source is kept, so spans and lines are the same
+/// as if it had been lexed at once.
+pub struct TokenStream<R> {
+    reader: R,
+    lexer: Lexer,
+    ready: VecDeque<Result<Token, LexError>>,
+    /// Bytes of a character split between reads
+    partial: Vec<u8>,
+    done: bool
+}
+
+impl<R: AsyncRead + Unpin> TokenStream<R> {
+    pub fn from_async_reader(rules: RuleSet, reader: R) -> Self {
+        Self { reader, lexer: Lexer::from_rules(rules, String::new()), ready: VecDeque::new(), partial: vec![], done: false }
+    }
+
+    fn feed(&mut self, chunk: &str, finished: bool) {
+        let tokens = self.lexer.feed(chunk, finished);
+        self.ready.extend(tokens.into_iter().map(|t| t.map_err(LexError::Lexing)));
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for TokenStream<R> {
+    type Item = Result<Token, LexError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut buf = [0u8; 4096];
+        loop {
+            if let Some(item) = this.ready.pop_front() {
+                return Poll::Ready(Some(item));
+            }
+            if this.done {
+                return Poll::Ready(None);
+            }
+            let n = match Pin::new(&mut this.reader).poll_read(cx, &mut buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(LexError::Io(e))));
+                }
+                Poll::Ready(Ok(n)) => n
+            };
+            if n == 0 {
+                this.done = true;
+                if !this.partial.is_empty() {
+                    this.ready.push_back(Err(LexError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, "source ends inside a UTF-8 character"))));
+                }
+                this.feed("", true);
+                continue;
+            }
+            this.partial.extend_from_slice(&buf[..n]);
+            let valid = match std::str::from_utf8(&this.partial) {
+                Ok(s) => s.len(),
+                // a character cut off at the end of the read is completed by the next one
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(e) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(LexError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))));
+                }
+            };
+            let chunk: Vec<u8> = this.partial.drain(..valid).collect();
+            this.feed(std::str::from_utf8(&chunk).unwrap(), false);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Helpers for unit testing grammars, failures show the expected and actual tokens side by side with their positions
+
+use serde::Deserialize;
+
+use crate::{Lexer, ParsingError, RuleSet, Token};
+
+/// Anything `assert_tokens!` accepts as rules: a `RuleSet` or its JSON
+pub trait TestRules {
+    fn into_rules(self) -> RuleSet;
+}
+
+impl TestRules for RuleSet {
+    fn into_rules(self) -> RuleSet {
+        self
+    }
+}
+
+impl TestRules for &RuleSet {
+    fn into_rules(self) -> RuleSet {
+        self.clone()
+    }
+}
+
+impl TestRules for &str {
+    fn into_rules(self) -> RuleSet {
+        RuleSet::from_string(self.to_string())
+    }
+}
+
+impl TestRules for String {
+    fn into_rules(self) -> RuleSet {
+        RuleSet::from_string(self)
+    }
+}
+
+/// Asserts that lexing `input` with `rules` gives exactly the listed (type, value) pairs, panicking with a table of
+/// the expected and actual tokens otherwise
+///
+/// ```
+/// use lexer_generator::assert_tokens;
+///
+/// let rules = r#"{ "literals": { "number": "[0-9]+", "add": "\\+" } }"#;
+/// assert_tokens!(rules, "123 + 4", [("number", "123"), ("add", "+"), ("number", "4")]);
+/// ```
+#[macro_export]
+macro_rules! assert_tokens {
+    ($rules:expr, $input:expr, [$(($token_type:expr, $value:expr)),* $(,)?]) => {
+        if let Err(diff) = $crate::testing::check_tokens($crate::testing::TestRules::into_rules($rules), $input, &[$(($token_type, $value)),*]) {
+            panic!("{}", diff);
+        }
+    };
+}
+
+/// Every token of `input` until the end of the source, an error ends the list
+fn lex(rules: RuleSet, input: &str) -> Vec<Result<Token, ParsingError>> {
+    let mut lexer = Lexer::from_rules(rules, input.to_string());
+    let mut tokens = vec![];
+    loop {
+        match lexer.next_token() {
+            Err(ParsingError::EndOfFileError) => return tokens,
+            Err(e) => {
+                tokens.push(Err(e));
+                return tokens;
+            }
+            Ok(token) => tokens.push(Ok(token))
+        }
+    }
+}
+
+fn describe(token: &Result<Token, ParsingError>) -> (String, String) {
+    match token {
+        Ok(token) => (format!("{}({:?})", token.token_type, token.value), format!("{}:{} {:?}", token.line, token.column, token.span)),
+        Err(e) => (format!("error {:?}", e), String::new())
+    }
+}
+
+/// Formats two token lists side by side, marking the rows which differ with `>`
+fn diff_table(title: &str, left: (&str, Vec<String>), right: (&str, Vec<(String, String)>)) -> String {
+    let rows = left.1.len().max(right.1.len());
+    let width = left.1.iter().map(|s| s.len()).chain([left.0.len(), 1]).max().unwrap();
+    let right_width = right.1.iter().map(|(s, _)| s.len()).chain([right.0.len(), 1]).max().unwrap();
+    let position = if right.1.iter().any(|(_, p)| !p.is_empty()) { "position" } else { "" };
+    let mut out = format!("{}\n", format!("{}\n    #  {:w$}  {:rw$}  {}", title, left.0, right.0, position, w = width, rw = right_width).trim_end());
+    for i in 0..rows {
+        let l = left.1.get(i).map_or("-", |s| s.as_str());
+        let (r, position) = right.1.get(i).map_or(("-", ""), |(s, p)| (s.as_str(), p.as_str()));
+        let marker = if l == r { ' ' } else { '>' };
+        out.push_str(format!("{} {:>3}  {:w$}  {:rw$}  {}", marker, i, l, r, position, w = width, rw = right_width).trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Lexes `input` and compares the tokens to the expected (type, value) pairs, returning a readable table of both
+/// lists if they differ
+pub fn check_tokens(rules: RuleSet, input: &str, expected: &[(&str, &str)]) -> Result<(), String> {
+    let actual: Vec<(String, String)> = lex(rules, input).iter().map(describe).collect();
+    let expected: Vec<String> = expected.iter().map(|(t, v)| format!("{}({:?})", t, v)).collect();
+    match (0..expected.len().max(actual.len())).find(|i| expected.get(*i) != actual.get(*i).map(|(s, _)| s)) {
+        None => Ok(()),
+        Some(first) => Err(diff_table(&format!("tokens of {:?} differ from token {}", input, first), ("expected", expected), ("actual", actual)))
+    }
+}
+
+/// Differential test of two rule sets, e.g. a rewritten grammar against the one it replaces: lexes `input` with both
+/// and returns a table of both token lists, with positions, if they differ
+pub fn compare_lexers(reference: RuleSet, candidate: RuleSet, input: &str) -> Result<(), String> {
+    // positions are compared too, the table shows them in the token columns
+    let with_position = |(token, position): (String, String)| format!("{} {}", token, position).trim_end().to_string();
+    let reference: Vec<String> = lex(reference, input).iter().map(describe).map(with_position).collect();
+    let candidate: Vec<(String, String)> = lex(candidate, input).iter().map(describe).map(|t| (with_position(t), String::new())).collect();
+    match (0..reference.len().max(candidate.len())).find(|i| reference.get(*i) != candidate.get(*i).map(|(s, _)| s)) {
+        None => Ok(()),
+        Some(first) => Err(diff_table(&format!("tokens of {:?} differ from token {}", input, first), ("reference", reference), ("candidate", candidate)))
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+/// A case of a grammar test file, an input and the (type, value) pairs it lexes to, see `load_test_cases`
+pub struct TestCase {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub input: String,
+    pub tokens: Vec<(String, String)>
+}
+
+impl TestCase {
+    /// Lexes the input with `rules` like `check_tokens`, returning the table of both token lists if they differ
+    pub fn run(&self, rules: &RuleSet) -> Result<(), String> {
+        let expected: Vec<(&str, &str)> = self.tokens.iter().map(|(t, v)| (t.as_str(), v.as_str())).collect();
+        check_tokens(rules.clone(), &self.input, &expected)
+    }
+}
+
+/// Parses a grammar test file, a JSON list of cases, so a grammar's expected tokens can live next to it and be checked
+/// by `lexer-generator check --tests`
+///
+/// ```
+/// use lexer_generator::{testing, RuleSet};
+///
+/// let cases = testing::load_test_cases(r#"[{ "name": "sum", "input": "1 + 2", "tokens": [["number", "1"], ["add", "+"], ["number", "2"]] }]"#).unwrap();
+/// let rules = RuleSet::from_string(String::from(r#"{ "literals": { "number": "[0-9]+", "add": "\\+" } }"#));
+/// assert!(cases[0].run(&rules).is_ok());
+/// ```
+pub fn load_test_cases(json: &str) -> Result<Vec<TestCase>, String> {
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
@@ -0,0 +1,88 @@
+//! Nesting tokens between the delimiters of `"pairs"` into token trees, like the input of Rust macros
+
+use std::ops::Range;
+
+use crate::{Lexer, ParsingError, Token};
+
+#[derive(Clone, Debug)]
+/// A token, or a group of token trees between an opener and its closer, built by `token_trees`
+pub enum TokenTree {
+    /// A token which is no delimiter of a pair
+    Token(Token),
+    /// The trees between a delimiter pair, `span` runs from the start of the opener to the end of the closer
+    Group { delim: Delimiter, children: Vec<TokenTree>, span: Range<usize> }
+}
+
+#[derive(Clone, Debug)]
+/// The opener and closer tokens of a `TokenTree::Group`
+pub struct Delimiter {
+    pub open: Token,
+    pub close: Token
+}
+
+impl TokenTree {
+    /// Byte range of the tree in the source
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            TokenTree::Token(token) => token.span.clone(),
+            TokenTree::Group { span, .. } => span.clone()
+        }
+    }
+}
+
+/// Nests the tokens between the (opener, closer) token types of `pairs` into groups, a closer which doesn't match the
+/// innermost opener is a `ParsingError::UnbalancedDelimiter` and the innermost opener which is never closed a
+/// `ParsingError::UnclosedDelimiter`, like the lexer reports them
+///
+/// ```
+/// use lexer_generator::{token_trees, Lexer, TokenTree};
+///
+/// let rules = String::from(r#"{ "literals": { "name": "[a-z]+", "lparen": "\\(", "rparen": "\\)" } }"#);
+/// let tokens: Vec<_> = Lexer::from(rules, String::from("f(a (b)) c")).map(Result::unwrap).collect();
+/// let pairs = [(String::from("lparen"), String::from("rparen"))];
+/// let trees = token_trees(tokens, &pairs).unwrap();
+/// assert_eq!(trees.len(), 3);
+/// let TokenTree::Group { delim, children, span } = &trees[1] else { panic!() };
+/// assert_eq!((delim.open.value.as_str(), delim.close.value.as_str()), ("(", ")"));
+/// assert_eq!((children.len(), span.clone()), (2, 1..8));
+/// ```
+pub fn token_trees(tokens: impl IntoIterator<Item = Token>, pairs: &[(String, String)]) -> Result<Vec<TokenTree>, ParsingError> {
+    // (pair, opener, trees around the group) of the groups open, innermost last
+    let mut open: Vec<(usize, Token, Vec<TokenTree>)> = vec![];
+    let mut trees = vec![];
+    for token in tokens {
+        if let Some(pair) = pairs.iter().position(|(opener, _)| *opener == token.token_type) {
+            open.push((pair, token, std::mem::take(&mut trees)));
+        } else if pairs.iter().any(|(_, closer)| *closer == token.token_type) {
+            match open.pop() {
+                Some((pair, opener, outer)) if pairs[pair].1 == token.token_type => {
+                    let children = std::mem::replace(&mut trees, outer);
+                    let span = opener.span.start..token.span.end;
+                    trees.push(TokenTree::Group { delim: Delimiter { open: opener, close: token }, children, span });
+                }
+                innermost => {
+                    return Err(ParsingError::UnbalancedDelimiter {
+                        found: token.token_type.clone(),
+                        expected: innermost.map(|(pair, _, _)| pairs[pair].1.clone()),
+                        location: token.location()
+                    })
+                }
+            }
+        } else {
+            trees.push(TokenTree::Token(token));
+        }
+    }
+    match open.pop() {
+        Some((pair, opener, _)) => Err(ParsingError::UnclosedDelimiter { opener: pairs[pair].0.clone(), location: opener.location() }),
+        None => Ok(trees)
+    }
+}
+
+impl Lexer {
+    /// Lexes the rest of the source into token trees of the rule set's `"pairs"` with `token_trees`, stopping at the
+    /// first error
+    pub fn token_trees(&mut self) -> Result<Vec<TokenTree>, ParsingError> {
+        let tokens = self.by_ref().collect::<Result<Vec<_>, _>>()?;
+        token_trees(tokens, &self.rules.pairs)
+    }
+}